@@ -0,0 +1,191 @@
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+
+/// Files that belong to one account and live in its own directory
+const ACCOUNT_FILES: [&str; 4] = ["telegram.session", "telegram.session-wal", "telegram.session-shm", "bandwidth.json"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Account {
+    pub name: String,
+    pub api_id: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct AccountRegistry {
+    active: Option<String>,
+    accounts: Vec<Account>,
+}
+
+/// Keeps track of the named Telegram accounts and which one is active.
+///
+/// Every account gets its own directory under `accounts/` holding its session
+/// file and bandwidth counters. The registry itself is stored in `accounts.json`.
+pub struct AccountManager {
+    root: PathBuf,
+    file_path: PathBuf,
+    registry: Mutex<AccountRegistry>,
+}
+
+impl AccountManager {
+    pub fn new(app_handle: &tauri::AppHandle) -> Self {
+        let app_data_dir = app_handle.path().app_data_dir().unwrap_or_else(|_| PathBuf::from("data"));
+        Self::load(&app_data_dir)
+    }
+
+    pub fn load(app_data_dir: &Path) -> Self {
+        let file_path = app_data_dir.join("accounts.json");
+        let mut registry = read_registry(&file_path).unwrap_or_default();
+        let mut migrated = false;
+
+        // Single-account installs kept everything directly in the data dir
+        if registry.accounts.is_empty() && app_data_dir.join("telegram.session").exists() {
+            let default_dir = app_data_dir.join("accounts").join("default");
+            if fs::create_dir_all(&default_dir).is_ok() {
+                log::info!("Migrating existing session to the 'default' account");
+                for name in ACCOUNT_FILES {
+                    let _ = fs::rename(app_data_dir.join(name), default_dir.join(name));
+                }
+                registry.accounts.push(Account { name: "default".to_string(), api_id: None });
+                registry.active = Some("default".to_string());
+                migrated = true;
+            }
+        }
+
+        let manager = Self {
+            root: app_data_dir.join("accounts"),
+            file_path,
+            registry: Mutex::new(registry),
+        };
+        if migrated {
+            manager.save(&manager.registry.lock().unwrap());
+        }
+        manager
+    }
+
+    /// Applies `change` to the registry as currently stored and saves it.
+    ///
+    /// The CLI runs next to the app with its own copy, so each change starts from the
+    /// file rather than from what this process loaded earlier, and never writes back a
+    /// stale active account.
+    fn update<T>(&self, change: impl FnOnce(&mut AccountRegistry) -> Result<T, String>) -> Result<T, String> {
+        let mut registry = self.registry.lock().unwrap();
+        if let Some(stored) = read_registry(&self.file_path) {
+            *registry = stored;
+        }
+        let result = change(&mut registry)?;
+        self.save(&registry);
+        Ok(result)
+    }
+
+    /// Writes a temporary file and renames it over `accounts.json`, so a crash never leaves it half-written
+    fn save(&self, registry: &AccountRegistry) {
+        let Ok(json) = serde_json::to_string_pretty(registry) else { return };
+        if let Err(e) = crate::atomic::write(&self.file_path, json) {
+            log::error!("Failed to save accounts: {}", e);
+        }
+    }
+
+    pub fn account_dir(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+
+    pub fn list(&self) -> Vec<Account> {
+        self.registry.lock().unwrap().accounts.clone()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Account> {
+        self.registry.lock().unwrap().accounts.iter().find(|a| a.name == name).cloned()
+    }
+
+    pub fn active_name(&self) -> Option<String> {
+        self.registry.lock().unwrap().active.clone()
+    }
+
+    /// Returns the active account, creating one if there is none yet
+    /// (first launch, or right after logging out of the previous one).
+    pub fn ensure_active(&self) -> Account {
+        if let Some(account) = self.active_name().and_then(|name| self.get(&name)) {
+            return account;
+        }
+
+        let taken: Vec<String> = self.list().into_iter().map(|a| a.name).collect();
+        let name = std::iter::once("default".to_string())
+            .chain((2..).map(|i| format!("account-{}", i)))
+            .find(|n| !taken.contains(n))
+            .unwrap();
+
+        let account = self.add(&name, None).expect("generated account names are valid and unused");
+        let _ = self.set_active(Some(&name));
+        account
+    }
+
+    pub fn add(&self, name: &str, api_id: Option<i32>) -> Result<Account, String> {
+        validate_name(name)?;
+        fs::create_dir_all(self.account_dir(name))
+            .map_err(|e| format!("Failed to create account dir: {}", e))?;
+        self.update(|registry| {
+            if registry.accounts.iter().any(|a| a.name == name) {
+                return Err(format!("Account '{}' already exists", name));
+            }
+            let account = Account { name: name.to_string(), api_id };
+            registry.accounts.push(account.clone());
+            Ok(account)
+        })
+    }
+
+    /// Forgets the account and deletes its session and bandwidth counters
+    pub fn remove(&self, name: &str) -> Result<(), String> {
+        self.update(|registry| {
+            let before = registry.accounts.len();
+            registry.accounts.retain(|a| a.name != name);
+            if registry.accounts.len() == before {
+                return Err(format!("Account '{}' not found", name));
+            }
+            if registry.active.as_deref() == Some(name) {
+                registry.active = None;
+            }
+            Ok(())
+        })?;
+        let _ = fs::remove_dir_all(self.account_dir(name));
+        Ok(())
+    }
+
+    pub fn set_active(&self, name: Option<&str>) -> Result<(), String> {
+        self.update(|registry| {
+            if let Some(name) = name {
+                if !registry.accounts.iter().any(|a| a.name == name) {
+                    return Err(format!("Account '{}' not found", name));
+                }
+            }
+            registry.active = name.map(|n| n.to_string());
+            Ok(())
+        })
+    }
+
+    pub fn set_api_id(&self, name: &str, api_id: i32) {
+        let _ = self.update(|registry| {
+            if let Some(account) = registry.accounts.iter_mut().find(|a| a.name == name) {
+                account.api_id = Some(api_id);
+            }
+            Ok(())
+        });
+    }
+}
+
+fn read_registry(file_path: &Path) -> Option<AccountRegistry> {
+    fs::read_to_string(file_path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+}
+
+/// Account names become directory names, so keep them to a safe character set
+fn validate_name(name: &str) -> Result<(), String> {
+    let valid_chars = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if name.is_empty() || name.len() > 32 || !valid_chars {
+        return Err("Account names must be 1-32 characters of letters, digits, '-' or '_'".to_string());
+    }
+    Ok(())
+}
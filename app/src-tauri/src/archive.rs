@@ -0,0 +1,457 @@
+use std::collections::HashSet;
+use std::io::Write;
+use std::sync::Arc;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use flate2::Compression;
+use flate2::write::DeflateEncoder;
+use futures::stream::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use grammers_client::Client;
+use crate::bot::BotDrive;
+use crate::commands::fs::file_metadata;
+use crate::commands::utils::resolve_peer;
+use crate::download::{RemoteFile, file_stream};
+use crate::error::DriveError;
+use crate::filenames::{numbered_name, sanitize_filename};
+use crate::messages::MessageCache;
+use crate::models::ZipSelection;
+use crate::peers::PeerCache;
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const DATA_DESCRIPTOR: u32 = 0x08074b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const ZIP64_END: u32 = 0x06064b50;
+const ZIP64_LOCATOR: u32 = 0x07064b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+
+/// Sizes and CRC follow the data (bit 3), names are UTF-8 (bit 11)
+const FLAGS: u16 = 0x0008 | 0x0800;
+const VERSION_DEFLATE: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+
+/// How entries are stored in the archive
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ZipMode {
+    /// Uncompressed, for media that does not shrink anyway
+    #[default]
+    Store,
+    Deflate,
+}
+
+impl ZipMode {
+    fn method(self) -> u16 {
+        match self {
+            ZipMode::Store => 0,
+            ZipMode::Deflate => 8,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ZipMode::Store => "store",
+            ZipMode::Deflate => "deflate",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "store" => Some(ZipMode::Store),
+            "deflate" => Some(ZipMode::Deflate),
+            _ => None,
+        }
+    }
+}
+
+/// A drive file going into an archive
+pub struct ZipItem {
+    pub name: String,
+    pub file: Arc<RemoteFile>,
+    pub modified: DateTime<Utc>,
+}
+
+struct CentralEntry {
+    name: String,
+    method: u16,
+    time: u16,
+    date: u16,
+    crc: u32,
+    compressed: u32,
+    size: u32,
+    offset: u64,
+}
+
+struct OpenEntry {
+    central: CentralEntry,
+    crc: crc32fast::Hasher,
+    deflate: Option<DeflateEncoder<Vec<u8>>>,
+    size: u64,
+    compressed: u64,
+}
+
+/// Writes a ZIP archive front to back without seeking.
+///
+/// Every method returns the bytes to append to the output. Entry sizes and CRCs
+/// go into a data descriptor after the data, so nothing has to be known up front.
+/// Offsets past 4 GB switch the central directory to ZIP64.
+#[derive(Default)]
+pub struct ZipBuilder {
+    offset: u64,
+    entries: Vec<CentralEntry>,
+    open: Option<OpenEntry>,
+}
+
+impl ZipBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start_entry(&mut self, name: &str, mode: ZipMode, modified: DateTime<Utc>) -> Vec<u8> {
+        let (time, date) = dos_date_time(modified);
+        let central = CentralEntry {
+            name: name.to_string(),
+            method: mode.method(),
+            time,
+            date,
+            crc: 0,
+            compressed: 0,
+            size: 0,
+            offset: self.offset,
+        };
+
+        let mut out = Vec::with_capacity(30 + name.len());
+        put_u32(&mut out, LOCAL_HEADER);
+        put_u16(&mut out, VERSION_DEFLATE);
+        put_u16(&mut out, FLAGS);
+        put_u16(&mut out, central.method);
+        put_u16(&mut out, time);
+        put_u16(&mut out, date);
+        put_u32(&mut out, 0); // crc, in the data descriptor
+        put_u32(&mut out, 0); // compressed size
+        put_u32(&mut out, 0); // uncompressed size
+        put_u16(&mut out, name.len() as u16);
+        put_u16(&mut out, 0); // extra field length
+        out.extend_from_slice(name.as_bytes());
+
+        self.open = Some(OpenEntry {
+            central,
+            crc: crc32fast::Hasher::new(),
+            deflate: (mode == ZipMode::Deflate).then(|| DeflateEncoder::new(Vec::new(), Compression::default())),
+            size: 0,
+            compressed: 0,
+        });
+        self.emit(out)
+    }
+
+    /// Adds file data to the entry started last
+    pub fn write(&mut self, data: &[u8]) -> Result<Vec<u8>, DriveError> {
+        let entry = self.open.as_mut().ok_or("No ZIP entry started")?;
+        entry.crc.update(data);
+        entry.size += data.len() as u64;
+        let out = match entry.deflate.as_mut() {
+            Some(encoder) => {
+                encoder.write_all(data)?;
+                std::mem::take(encoder.get_mut())
+            }
+            None => data.to_vec(),
+        };
+        entry.compressed += out.len() as u64;
+        Ok(self.emit(out))
+    }
+
+    pub fn finish_entry(&mut self) -> Result<Vec<u8>, DriveError> {
+        let mut entry = self.open.take().ok_or("No ZIP entry started")?;
+        let mut out = match entry.deflate.take() {
+            Some(encoder) => encoder.finish()?,
+            None => Vec::new(),
+        };
+        entry.compressed += out.len() as u64;
+
+        // Files are under 4 GB (Telegram's limit), so 32-bit sizes are enough
+        let (Ok(size), Ok(compressed)) = (u32::try_from(entry.size), u32::try_from(entry.compressed)) else {
+            return Err(DriveError::Other(format!("{} is too large for a ZIP entry", entry.central.name)));
+        };
+        entry.central.crc = entry.crc.finalize();
+        entry.central.size = size;
+        entry.central.compressed = compressed;
+
+        put_u32(&mut out, DATA_DESCRIPTOR);
+        put_u32(&mut out, entry.central.crc);
+        put_u32(&mut out, compressed);
+        put_u32(&mut out, size);
+        self.entries.push(entry.central);
+        Ok(self.emit(out))
+    }
+
+    /// Writes the central directory, ending the archive
+    pub fn finish(mut self) -> Vec<u8> {
+        let cd_offset = self.offset;
+        let mut out = Vec::new();
+        for entry in &self.entries {
+            let zip64 = entry.offset >= u32::MAX as u64;
+            put_u32(&mut out, CENTRAL_HEADER);
+            put_u16(&mut out, VERSION_ZIP64); // version made by
+            put_u16(&mut out, if zip64 { VERSION_ZIP64 } else { VERSION_DEFLATE });
+            put_u16(&mut out, FLAGS);
+            put_u16(&mut out, entry.method);
+            put_u16(&mut out, entry.time);
+            put_u16(&mut out, entry.date);
+            put_u32(&mut out, entry.crc);
+            put_u32(&mut out, entry.compressed);
+            put_u32(&mut out, entry.size);
+            put_u16(&mut out, entry.name.len() as u16);
+            put_u16(&mut out, if zip64 { 12 } else { 0 });
+            put_u16(&mut out, 0); // comment length
+            put_u16(&mut out, 0); // disk number
+            put_u16(&mut out, 0); // internal attributes
+            put_u32(&mut out, 0); // external attributes
+            put_u32(&mut out, if zip64 { u32::MAX } else { entry.offset as u32 });
+            out.extend_from_slice(entry.name.as_bytes());
+            if zip64 {
+                put_u16(&mut out, 0x0001);
+                put_u16(&mut out, 8);
+                put_u64(&mut out, entry.offset);
+            }
+        }
+        let cd_size = out.len() as u64;
+        let count = self.entries.len() as u64;
+
+        let zip64 = cd_offset + cd_size >= u32::MAX as u64 || count >= u16::MAX as u64;
+        if zip64 {
+            let end_offset = cd_offset + cd_size;
+            put_u32(&mut out, ZIP64_END);
+            put_u64(&mut out, 44); // size of the rest of this record
+            put_u16(&mut out, VERSION_ZIP64);
+            put_u16(&mut out, VERSION_ZIP64);
+            put_u32(&mut out, 0); // this disk
+            put_u32(&mut out, 0); // disk with the central directory
+            put_u64(&mut out, count);
+            put_u64(&mut out, count);
+            put_u64(&mut out, cd_size);
+            put_u64(&mut out, cd_offset);
+
+            put_u32(&mut out, ZIP64_LOCATOR);
+            put_u32(&mut out, 0);
+            put_u64(&mut out, end_offset);
+            put_u32(&mut out, 1); // total disks
+        }
+
+        put_u32(&mut out, END_OF_CENTRAL_DIRECTORY);
+        put_u16(&mut out, 0);
+        put_u16(&mut out, 0);
+        put_u16(&mut out, count.min(u16::MAX as u64) as u16);
+        put_u16(&mut out, count.min(u16::MAX as u64) as u16);
+        put_u32(&mut out, cd_size.min(u32::MAX as u64) as u32);
+        put_u32(&mut out, if zip64 { u32::MAX } else { cd_offset as u32 });
+        put_u16(&mut out, 0); // comment length
+        self.emit(out)
+    }
+
+    fn emit(&mut self, bytes: Vec<u8>) -> Vec<u8> {
+        self.offset += bytes.len() as u64;
+        bytes
+    }
+}
+
+/// Looks up the selected messages and names them for the archive.
+/// Names that occur twice get ` (1)`, ` (2)`, ... before the extension.
+pub async fn collect_items(
+    client: &Client,
+    drive: &BotDrive,
+    peers: &PeerCache,
+    messages: &MessageCache,
+    selection: &[ZipSelection],
+) -> Result<Vec<ZipItem>, DriveError> {
+    let mut items = Vec::new();
+    let mut taken = HashSet::new();
+    for group in selection {
+        let peer = resolve_peer(client, drive, peers, group.folder_id).await?;
+        let found = peers.check(&peer, messages.get_many(client, &peer, &group.message_ids).await)?;
+        for (id, msg) in group.message_ids.iter().zip(found) {
+            let not_found = || DriveError::NotFound(format!("File {} not found", id));
+            let msg = msg.ok_or_else(not_found)?;
+            let media = msg.media().ok_or_else(not_found)?;
+            let file = RemoteFile::from_media(&media).ok_or_else(not_found)?;
+            let name = file_metadata(&msg, group.folder_id).map(|m| m.name).unwrap_or_else(|| format!("file_{}", id));
+            items.push(ZipItem {
+                name: unique_name(&name, &mut taken),
+                file: Arc::new(file),
+                modified: msg.date(),
+            });
+        }
+    }
+    Ok(items)
+}
+
+/// Streams a ZIP archive of `items`, downloading each file while it is written.
+///
+/// Only one file is in flight at a time, `parallelism` chunks of it. `on_chunk`
+/// gets the size of every downloaded chunk, for bandwidth accounting.
+pub fn zip_stream(
+    client: Client,
+    items: Vec<ZipItem>,
+    mode: ZipMode,
+    parallelism: usize,
+    on_chunk: impl Fn(u64) + Send + 'static,
+) -> impl Stream<Item = Result<Vec<u8>, DriveError>> + Send + 'static {
+    async_stream::try_stream! {
+        let mut zip = ZipBuilder::new();
+        for item in items {
+            yield zip.start_entry(&item.name, mode, item.modified);
+            let mut chunks = Box::pin(file_stream(client.clone(), item.file.clone(), parallelism));
+            while let Some(chunk) = chunks.next().await {
+                let chunk = chunk?;
+                on_chunk(chunk.len() as u64);
+                yield zip.write(&chunk)?;
+            }
+            yield zip.finish_entry()?;
+        }
+        yield zip.finish();
+    }
+}
+
+/// `home:1,2,3;12345:4,5`, the selection as the `/zip` route takes it
+pub fn selection_to_path(selection: &[ZipSelection]) -> String {
+    selection.iter()
+        .map(|group| {
+            let folder = group.folder_id.map(|id| id.to_string()).unwrap_or_else(|| "home".to_string());
+            let ids = group.message_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",");
+            format!("{}:{}", folder, ids)
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+pub fn selection_from_path(path: &str) -> Option<Vec<ZipSelection>> {
+    path.split(';')
+        .map(|group| {
+            let (folder, ids) = group.split_once(':')?;
+            let folder_id = match folder {
+                "home" | "me" | "null" => None,
+                id => Some(id.parse().ok()?),
+            };
+            let message_ids = ids.split(',').map(|id| id.parse().ok()).collect::<Option<Vec<i32>>>()?;
+            Some(ZipSelection { folder_id, message_ids })
+        })
+        .collect()
+}
+
+fn unique_name(name: &str, taken: &mut HashSet<String>) -> String {
+    // Entries are flat and get extracted locally, so they follow the same rules as downloads
+    let name = sanitize_filename(name);
+    (0..)
+        .map(|n| numbered_name(&name, n))
+        .find(|candidate| taken.insert(candidate.clone()))
+        .unwrap_or(name)
+}
+
+/// MS-DOS time and date, which cannot go before 1980
+fn dos_date_time(t: DateTime<Utc>) -> (u16, u16) {
+    if t.year() < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    let time = (t.hour() << 11) | (t.minute() << 5) | (t.second() / 2);
+    let date = (((t.year() - 1980) as u32) << 9) | (t.month() << 5) | t.day();
+    (time as u16, date as u16)
+}
+
+fn put_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_u64(out: &mut Vec<u8>, v: u64) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+    use chrono::TimeZone;
+
+    fn build(files: &[(&str, &[u8])], mode: ZipMode) -> Vec<u8> {
+        let modified = Utc.with_ymd_and_hms(2024, 3, 15, 12, 30, 44).unwrap();
+        let mut zip = ZipBuilder::new();
+        let mut out = Vec::new();
+        for (name, data) in files {
+            out.extend(zip.start_entry(name, mode, modified));
+            // Several writes, as the download hands the data over in chunks
+            for chunk in data.chunks(1000) {
+                out.extend(zip.write(chunk).unwrap());
+            }
+            out.extend(zip.finish_entry().unwrap());
+        }
+        out.extend(zip.finish());
+        out
+    }
+
+    fn read_back(bytes: Vec<u8>) -> Vec<(String, zip::CompressionMethod, Vec<u8>)> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        (0..archive.len())
+            .map(|i| {
+                let mut entry = archive.by_index(i).unwrap();
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data).unwrap();
+                (entry.name().to_string(), entry.compression(), data)
+            })
+            .collect()
+    }
+
+    fn sample() -> Vec<u8> {
+        (0..10_000u32).flat_map(|i| (i % 251).to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn stored_archive_reads_back() {
+        let data = sample();
+        let entries = read_back(build(&[("a.bin", &data), ("empty.txt", b""), ("ünïcode.txt", b"hi")], ZipMode::Store));
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0], ("a.bin".to_string(), zip::CompressionMethod::Stored, data));
+        assert_eq!(entries[1], ("empty.txt".to_string(), zip::CompressionMethod::Stored, Vec::new()));
+        assert_eq!(entries[2], ("ünïcode.txt".to_string(), zip::CompressionMethod::Stored, b"hi".to_vec()));
+    }
+
+    #[test]
+    fn deflated_archive_reads_back() {
+        let data = sample();
+        let bytes = build(&[("a.bin", &data), ("b.txt", b"hello hello hello")], ZipMode::Deflate);
+        assert!(bytes.len() < data.len());
+        let entries = read_back(bytes);
+        assert_eq!(entries[0], ("a.bin".to_string(), zip::CompressionMethod::Deflated, data));
+        assert_eq!(entries[1], ("b.txt".to_string(), zip::CompressionMethod::Deflated, b"hello hello hello".to_vec()));
+    }
+
+    #[test]
+    fn many_entries_use_zip64_end_record() {
+        let names: Vec<String> = (0..u16::MAX as usize + 5).map(|i| format!("{}.txt", i)).collect();
+        let files: Vec<(&str, &[u8])> = names.iter().map(|n| (n.as_str(), &b"x"[..])).collect();
+        let bytes = build(&files, ZipMode::Store);
+        assert!(bytes.windows(4).any(|w| w == ZIP64_END.to_le_bytes()));
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.len(), names.len());
+        let mut last = archive.by_index(names.len() - 1).unwrap();
+        let mut data = Vec::new();
+        last.read_to_end(&mut data).unwrap();
+        assert_eq!((last.name(), data.as_slice()), (names.last().unwrap().as_str(), &b"x"[..]));
+    }
+
+    #[test]
+    fn dates_before_1980_are_clamped() {
+        let old = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(dos_date_time(old), (0, (1 << 5) | 1));
+        let t = Utc.with_ymd_and_hms(2024, 3, 15, 12, 30, 44).unwrap();
+        assert_eq!(dos_date_time(t), ((12 << 11) | (30 << 5) | 22, (44 << 9) | (3 << 5) | 15));
+    }
+
+    #[test]
+    fn write_without_entry_fails() {
+        let mut zip = ZipBuilder::new();
+        assert!(zip.write(b"data").is_err());
+        assert!(zip.finish_entry().is_err());
+    }
+}
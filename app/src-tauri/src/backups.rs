@@ -0,0 +1,677 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, UNIX_EPOCH};
+use chrono::{DateTime, Local, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+use tokio::sync::mpsc;
+use walkdir::WalkDir;
+use crate::accounts::AccountManager;
+use crate::bandwidth::BandwidthManager;
+use crate::bot::BotDrive;
+use crate::commands::TelegramState;
+use crate::commands::fs::{MAX_UPLOAD_SIZE, delete_files, upload_file_with_caption};
+use crate::commands::tree::{relative_path, stops_transfer};
+use crate::error::DriveError;
+use crate::filters::PathFilter;
+use crate::messages::MessageCache;
+use crate::peers::PeerCache;
+use crate::schedule::Schedule;
+use crate::transfers::TransferManager;
+
+/// How often the scheduler looks for due jobs
+const TICK: Duration = Duration::from_secs(30);
+/// A run starting later than this after its scheduled time counts as missed
+const GRACE_SECS: i64 = 5 * 60;
+/// The manifest is saved after this many uploads, so a crash loses little
+const SAVE_EVERY: usize = 20;
+
+/// What to do about runs missed while the app was closed or the account signed out
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CatchUp {
+    /// Record the missed run as skipped and wait for the next one
+    Skip,
+    /// Run once as soon as possible, however many runs were missed
+    Run,
+}
+
+/// A recurring upload of the changed files of a local directory
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupJob {
+    pub id: u32,
+    pub name: String,
+    /// Account the drive folder belongs to. The job only runs while it is active.
+    pub account: String,
+    pub source: PathBuf,
+    /// `None` for Saved Messages
+    pub folder_id: Option<i64>,
+    /// Cron expression, see `Schedule`
+    pub schedule: String,
+    pub catch_up: CatchUp,
+    /// Uploaded versions kept per file, older ones are deleted from the drive. 0 keeps all.
+    pub keep_versions: usize,
+    /// Runs kept in the history, with their logs
+    pub keep_runs: usize,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    pub enabled: bool,
+    /// Unix time of the last scheduled run that was started or skipped
+    pub last_slot: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RunTrigger {
+    Scheduled,
+    /// Makes up for runs missed while the app was not running
+    CatchUp,
+    Manual,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Running,
+    Succeeded,
+    /// Stopped early, or some files could not be uploaded
+    Failed,
+    /// Missed, and the job does not catch up
+    Skipped,
+}
+
+/// One run of a backup job. Its log is read with `BackupManager::run_log`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupRun {
+    pub id: u64,
+    pub job_id: u32,
+    pub trigger: RunTrigger,
+    pub status: RunStatus,
+    /// Unix times
+    pub started: i64,
+    pub finished: Option<i64>,
+    pub uploaded: usize,
+    pub unchanged: usize,
+    pub failed: usize,
+    /// Old file versions deleted from the drive
+    pub pruned: usize,
+    /// Why the run stopped early
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
+struct BackupState {
+    jobs: Vec<BackupJob>,
+    /// Oldest first
+    runs: Vec<BackupRun>,
+    next_run_id: u64,
+}
+
+/// A job's record of what it uploaded, by path relative to the source
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct Manifest {
+    files: BTreeMap<String, Vec<FileVersion>>,
+}
+
+/// An uploaded version of a file, oldest first in the manifest
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+struct FileVersion {
+    size: u64,
+    /// Milliseconds since the epoch
+    modified: u64,
+    message_id: i32,
+}
+
+/// Backup jobs and their run history, stored in `backups.json`, shared by all accounts.
+///
+/// Manifests and run logs go into the `backups` directory next to it.
+/// `spawn_scheduler` starts the jobs when they are due.
+pub struct BackupManager {
+    file_path: PathBuf,
+    dir: PathBuf,
+    state: Mutex<BackupState>,
+    /// Asks the scheduler to run a job now, once it runs
+    run_now: Mutex<Option<mpsc::UnboundedSender<u32>>>,
+}
+
+impl BackupManager {
+    pub fn new(app_handle: &tauri::AppHandle) -> Self {
+        let app_data_dir = app_handle.path().app_data_dir().unwrap_or_else(|_| PathBuf::from("data"));
+        Self::load(&app_data_dir)
+    }
+
+    pub fn load(app_data_dir: &Path) -> Self {
+        let file_path = app_data_dir.join("backups.json");
+        let mut state: BackupState = fs::read_to_string(&file_path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        // The app quit in the middle of these
+        for run in state.runs.iter_mut().filter(|r| r.status == RunStatus::Running) {
+            run.status = RunStatus::Failed;
+            run.error = Some("Interrupted".to_string());
+        }
+
+        Self {
+            file_path,
+            dir: app_data_dir.join("backups"),
+            state: Mutex::new(state),
+            run_now: Mutex::new(None),
+        }
+    }
+
+    pub fn jobs(&self) -> Vec<BackupJob> {
+        self.state.lock().unwrap().jobs.clone()
+    }
+
+    fn job(&self, id: u32) -> Option<BackupJob> {
+        self.state.lock().unwrap().jobs.iter().find(|j| j.id == id).cloned()
+    }
+
+    /// Adds a job. Its first run is the first scheduled time from now on.
+    pub fn add_job(&self, mut job: BackupJob) -> Result<BackupJob, DriveError> {
+        Schedule::parse(&job.schedule)?;
+        PathFilter::new(&job.include, &job.exclude)?;
+        if !job.source.is_dir() {
+            return Err(DriveError::Other(format!("{} is not a folder", job.source.display())));
+        }
+        job.keep_runs = job.keep_runs.max(1);
+        job.last_slot = Utc::now().timestamp();
+        self.update(|state| {
+            job.id = state.jobs.iter().map(|j| j.id).max().unwrap_or(0) + 1;
+            state.jobs.push(job.clone());
+        });
+        log::info!("Added backup job '{}' ({})", job.name, job.schedule);
+        Ok(job)
+    }
+
+    /// Removes a job with its history and manifest. Files already uploaded stay in the drive.
+    pub fn remove_job(&self, id: u32) {
+        let mut removed = Vec::new();
+        self.update(|state| {
+            state.jobs.retain(|j| j.id != id);
+            removed = state.runs.iter().filter(|r| r.job_id == id).map(|r| r.id).collect();
+            state.runs.retain(|r| r.job_id != id);
+        });
+        for run_id in removed {
+            let _ = fs::remove_file(self.log_path(run_id));
+        }
+        let _ = fs::remove_file(self.manifest_path(id));
+    }
+
+    pub fn set_enabled(&self, id: u32, enabled: bool) -> Result<BackupJob, DriveError> {
+        let mut found = None;
+        self.update(|state| {
+            if let Some(job) = state.jobs.iter_mut().find(|j| j.id == id) {
+                job.enabled = enabled;
+                // Runs missed while disabled are not caught up on
+                if enabled {
+                    job.last_slot = Utc::now().timestamp();
+                }
+                found = Some(job.clone());
+            }
+        });
+        found.ok_or_else(|| DriveError::NotFound(format!("Backup job {} not found", id)))
+    }
+
+    /// Queues a run of `id` outside its schedule
+    pub fn run_now(&self, id: u32) -> Result<(), DriveError> {
+        if self.job(id).is_none() {
+            return Err(DriveError::NotFound(format!("Backup job {} not found", id)));
+        }
+        let sender = self.run_now.lock().unwrap();
+        sender.as_ref()
+            .and_then(|tx| tx.send(id).ok())
+            .ok_or_else(|| DriveError::Other("The backup scheduler is not running".to_string()))
+    }
+
+    /// Next scheduled run of a job as a Unix time, if it is enabled
+    pub fn next_run(&self, job: &BackupJob) -> Option<i64> {
+        if !job.enabled {
+            return None;
+        }
+        let schedule = Schedule::parse(&job.schedule).ok()?;
+        let since = Local.timestamp_opt(job.last_slot, 0).single()?;
+        schedule.next_after(since).map(|t| t.timestamp())
+    }
+
+    /// Run history, newest first, optionally of one job only
+    pub fn runs(&self, job_id: Option<u32>) -> Vec<BackupRun> {
+        let state = self.state.lock().unwrap();
+        state.runs.iter().rev().filter(|r| job_id.is_none() || job_id == Some(r.job_id)).cloned().collect()
+    }
+
+    pub fn run_log(&self, run_id: u64) -> Result<String, DriveError> {
+        if !self.state.lock().unwrap().runs.iter().any(|r| r.id == run_id) {
+            return Err(DriveError::NotFound(format!("Backup run {} not found", run_id)));
+        }
+        // Skipped runs have no log
+        Ok(fs::read_to_string(self.log_path(run_id)).unwrap_or_default())
+    }
+
+    fn start_run(&self, job_id: u32, trigger: RunTrigger, status: RunStatus) -> BackupRun {
+        let mut run = BackupRun {
+            id: 0,
+            job_id,
+            trigger,
+            status,
+            started: Utc::now().timestamp(),
+            finished: None,
+            uploaded: 0,
+            unchanged: 0,
+            failed: 0,
+            pruned: 0,
+            error: None,
+        };
+        self.update(|state| {
+            state.next_run_id += 1;
+            run.id = state.next_run_id;
+            state.runs.push(run.clone());
+        });
+        run
+    }
+
+    /// Stores a finished run and drops the oldest ones beyond the job's `keep_runs`
+    fn finish_run(&self, run: &BackupRun, keep_runs: usize) {
+        let mut dropped = Vec::new();
+        self.update(|state| {
+            if let Some(stored) = state.runs.iter_mut().find(|r| r.id == run.id) {
+                *stored = run.clone();
+            }
+            let ids: Vec<u64> = state.runs.iter().filter(|r| r.job_id == run.job_id).map(|r| r.id).collect();
+            if ids.len() > keep_runs {
+                dropped = ids[..ids.len() - keep_runs].to_vec();
+                state.runs.retain(|r| !dropped.contains(&r.id));
+            }
+        });
+        for run_id in dropped {
+            let _ = fs::remove_file(self.log_path(run_id));
+        }
+    }
+
+    fn set_last_slot(&self, id: u32, slot: i64) {
+        self.update(|state| {
+            if let Some(job) = state.jobs.iter_mut().find(|j| j.id == id) {
+                job.last_slot = slot;
+            }
+        });
+    }
+
+    fn log_path(&self, run_id: u64) -> PathBuf {
+        self.dir.join(format!("run-{}.log", run_id))
+    }
+
+    fn manifest_path(&self, job_id: u32) -> PathBuf {
+        self.dir.join(format!("job-{}.json", job_id))
+    }
+
+    fn load_manifest(&self, job_id: u32) -> Manifest {
+        fs::read_to_string(self.manifest_path(job_id))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn save_manifest(&self, job_id: u32, manifest: &Manifest) -> Result<(), DriveError> {
+        fs::create_dir_all(&self.dir)?;
+        let json = serde_json::to_string(manifest).map_err(|e| DriveError::Other(e.to_string()))?;
+        crate::atomic::write(self.manifest_path(job_id), json)?;
+        Ok(())
+    }
+
+    fn update(&self, change: impl FnOnce(&mut BackupState)) {
+        let mut state = self.state.lock().unwrap();
+        change(&mut state);
+        match serde_json::to_string_pretty(&*state) {
+            Ok(json) => {
+                if let Err(e) = crate::atomic::write(&self.file_path, json) {
+                    log::error!("Failed to save backup jobs: {}", e);
+                }
+            }
+            Err(e) => log::error!("Failed to serialize backup jobs: {}", e),
+        }
+    }
+}
+
+/// Timestamped lines written to a run's log file
+struct RunLog {
+    out: Option<BufWriter<File>>,
+}
+
+impl RunLog {
+    fn create(path: &Path) -> Self {
+        let out = path.parent().map(fs::create_dir_all).transpose()
+            .and_then(|_| File::create(path))
+            .map_err(|e| log::warn!("Cannot write backup log {}: {}", path.display(), e))
+            .ok();
+        Self { out: out.map(BufWriter::new) }
+    }
+
+    fn line(&mut self, message: impl AsRef<str>) {
+        if let Some(out) = &mut self.out {
+            let _ = writeln!(out, "{} {}", Local::now().format("%Y-%m-%d %H:%M:%S"), message.as_ref());
+        }
+    }
+}
+
+impl Drop for RunLog {
+    fn drop(&mut self) {
+        if let Some(out) = &mut self.out {
+            let _ = out.flush();
+        }
+    }
+}
+
+/// Starts due backup jobs, and the ones asked for with `run_now`, one at a time
+pub fn spawn_scheduler(app_handle: tauri::AppHandle) {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    *app_handle.state::<BackupManager>().run_now.lock().unwrap() = Some(tx);
+
+    tauri::async_runtime::spawn(async move {
+        let backups = app_handle.state::<BackupManager>();
+        let mut ticker = tokio::time::interval(TICK);
+        loop {
+            tokio::select! {
+                requested = rx.recv() => match requested {
+                    Some(id) => {
+                        if let Some(job) = backups.job(id) {
+                            run_job(&app_handle, &job, RunTrigger::Manual).await;
+                        }
+                    }
+                    None => break,
+                },
+                _ = ticker.tick() => {
+                    for job in backups.jobs().into_iter().filter(|j| j.enabled) {
+                        run_if_due(&app_handle, &backups, &job).await;
+                    }
+                }
+            }
+        }
+    });
+}
+
+async fn run_if_due(app_handle: &tauri::AppHandle, backups: &BackupManager, job: &BackupJob) {
+    let schedule = match Schedule::parse(&job.schedule) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            log::error!("Backup job '{}' has an invalid schedule: {}", job.name, e);
+            return;
+        }
+    };
+    let now = Local::now();
+    let Some(since) = Local.timestamp_opt(job.last_slot, 0).single() else { return };
+    let Some(slot) = schedule.last_before(since, now) else { return };
+    // Wait for the account instead; if that takes too long the run counts as missed
+    if !is_ready(app_handle, job).await {
+        return;
+    }
+
+    backups.set_last_slot(job.id, slot.timestamp());
+    match trigger_for(job, &slot, &now) {
+        Some(trigger) => run_job(app_handle, job, trigger).await,
+        None => {
+            log::info!("Skipping missed backup '{}' from {}", job.name, slot);
+            let mut run = backups.start_run(job.id, RunTrigger::Scheduled, RunStatus::Skipped);
+            run.finished = Some(run.started);
+            run.error = Some(format!("Missed the run at {}", slot.format("%Y-%m-%d %H:%M")));
+            backups.finish_run(&run, job.keep_runs);
+            let _ = app_handle.emit("backup-run", &run);
+        }
+    }
+}
+
+/// How to run the scheduled run at `slot`, or `None` if it was missed and is skipped
+fn trigger_for<Tz: TimeZone>(job: &BackupJob, slot: &DateTime<Tz>, now: &DateTime<Tz>) -> Option<RunTrigger> {
+    if (now.clone() - slot.clone()).num_seconds() <= GRACE_SECS {
+        return Some(RunTrigger::Scheduled);
+    }
+    match job.catch_up {
+        CatchUp::Run => Some(RunTrigger::CatchUp),
+        CatchUp::Skip => None,
+    }
+}
+
+/// The job's account is active and connected
+async fn is_ready(app_handle: &tauri::AppHandle, job: &BackupJob) -> bool {
+    app_handle.state::<AccountManager>().active_name().as_deref() == Some(job.account.as_str())
+        && app_handle.state::<TelegramState>().client.lock().await.is_some()
+}
+
+/// Runs a job and records it in the history, emitting `backup-run` when it starts and ends
+async fn run_job(app_handle: &tauri::AppHandle, job: &BackupJob, trigger: RunTrigger) {
+    let backups = app_handle.state::<BackupManager>();
+    let mut run = backups.start_run(job.id, trigger, RunStatus::Running);
+    let _ = app_handle.emit("backup-run", &run);
+    let mut log = RunLog::create(&backups.log_path(run.id));
+    log.line(format!("Backing up {} ({:?})", job.source.display(), trigger));
+
+    let result = back_up(app_handle, &backups, job, &mut run, &mut log).await;
+    run.status = match &result {
+        Ok(()) if run.failed == 0 => RunStatus::Succeeded,
+        _ => RunStatus::Failed,
+    };
+    if let Err(e) = result {
+        log.line(format!("Stopped: {}", e));
+        run.error = Some(e.to_string());
+    }
+    log.line(format!(
+        "Done: {} uploaded, {} unchanged, {} failed, {} old versions deleted",
+        run.uploaded, run.unchanged, run.failed, run.pruned
+    ));
+    drop(log);
+
+    run.finished = Some(Utc::now().timestamp());
+    log::info!("Backup '{}' finished: {:?}", job.name, run.status);
+    backups.finish_run(&run, job.keep_runs);
+    let _ = app_handle.emit("backup-run", &run);
+}
+
+/// Removes and returns the oldest versions beyond `keep`. 0 keeps all.
+fn prune_versions(versions: &mut Vec<FileVersion>, keep: usize) -> Vec<FileVersion> {
+    if keep == 0 || versions.len() <= keep {
+        return Vec::new();
+    }
+    versions.drain(..versions.len() - keep).collect()
+}
+
+/// Uploads the files of `job.source` that changed since their last uploaded version,
+/// each captioned with its path relative to `job.source`
+async fn back_up(
+    app_handle: &tauri::AppHandle,
+    backups: &BackupManager,
+    job: &BackupJob,
+    run: &mut BackupRun,
+    log: &mut RunLog,
+) -> Result<(), DriveError> {
+    if app_handle.state::<AccountManager>().active_name().as_deref() != Some(job.account.as_str()) {
+        return Err(DriveError::Other(format!("Account '{}' is not the active account", job.account)));
+    }
+    let client = app_handle.state::<TelegramState>().client.lock().await.clone().ok_or(DriveError::NotConnected)?;
+    let bw_state = app_handle.state::<BandwidthManager>();
+    let drive = app_handle.state::<BotDrive>();
+    let peers = app_handle.state::<PeerCache>();
+    let messages = app_handle.state::<MessageCache>();
+    let transfers = app_handle.state::<TransferManager>();
+    let filter = PathFilter::new(&job.include, &job.exclude)?;
+
+    let root = &job.source;
+    if !root.is_dir() {
+        return Err(DriveError::NotFound(format!("{} does not exist", root.display())));
+    }
+    let mut manifest = backups.load_manifest(job.id);
+    let mut unsaved = 0;
+
+    let entries = WalkDir::new(root)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !filter.is_excluded(&relative_path(root, e.path())));
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                log.line(format!("Cannot read {}", e));
+                run.failed += 1;
+                continue;
+            }
+        };
+        let relative = relative_path(root, entry.path());
+        if !entry.file_type().is_file() || !filter.accepts(&relative) {
+            continue;
+        }
+        let meta = match entry.metadata() {
+            Ok(meta) => meta,
+            Err(e) => {
+                log.line(format!("Cannot read {}: {}", relative, e));
+                run.failed += 1;
+                continue;
+            }
+        };
+        if meta.len() == 0 || meta.len() > MAX_UPLOAD_SIZE {
+            log.line(format!("Skipped {}: {}", relative, if meta.len() == 0 { "empty" } else { "too large" }));
+            continue;
+        }
+        let size = meta.len();
+        let modified = meta.modified().ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let versions = manifest.files.entry(relative.clone()).or_default();
+        if versions.last().is_some_and(|v| v.size == size && v.modified == modified) {
+            run.unchanged += 1;
+            continue;
+        }
+
+        // Files from every subdirectory land in the same folder, the caption keeps where they came from
+        match upload_file_with_caption(&client, &bw_state, &drive, &peers, &transfers, entry.path(), &relative, job.folder_id, |_| {}).await {
+            Ok(message_id) => {
+                log.line(format!("Uploaded {}", relative));
+                run.uploaded += 1;
+                versions.push(FileVersion { size, modified, message_id });
+                let old = prune_versions(versions, job.keep_versions);
+                if !old.is_empty() {
+                    let ids: Vec<i32> = old.iter().map(|v| v.message_id).collect();
+                    match delete_files(&client, &drive, &peers, &messages, &ids, job.folder_id).await {
+                        Ok(_) => run.pruned += ids.len(),
+                        Err(e) => {
+                            // Keep them listed so the next run tries again
+                            log.line(format!("Cannot delete old versions of {}: {}", relative, e));
+                            versions.splice(0..0, old);
+                        }
+                    }
+                }
+                unsaved += 1;
+                if unsaved >= SAVE_EVERY {
+                    backups.save_manifest(job.id, &manifest)?;
+                    unsaved = 0;
+                }
+            }
+            Err(e) => {
+                log.line(format!("Failed {}: {}", relative, e));
+                run.failed += 1;
+                if stops_transfer(&e) {
+                    backups.save_manifest(job.id, &manifest)?;
+                    return Err(e);
+                }
+            }
+        }
+    }
+    manifest.files.retain(|_, versions| !versions.is_empty());
+    backups.save_manifest(job.id, &manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    fn job(catch_up: CatchUp) -> BackupJob {
+        BackupJob {
+            id: 1,
+            name: "Documents".to_string(),
+            account: "default".to_string(),
+            source: PathBuf::from("/home/user/Documents"),
+            folder_id: None,
+            schedule: "0 * * * *".to_string(),
+            catch_up,
+            keep_versions: 2,
+            keep_runs: 2,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            enabled: true,
+            last_slot: 0,
+        }
+    }
+
+    fn at(hour: u32, minute: u32) -> DateTime<FixedOffset> {
+        FixedOffset::east_opt(3600).unwrap().with_ymd_and_hms(2024, 5, 6, hour, minute, 0).unwrap()
+    }
+
+    fn version(message_id: i32) -> FileVersion {
+        FileVersion { size: 1, modified: message_id as u64, message_id }
+    }
+
+    #[test]
+    fn runs_within_the_grace_period_are_on_schedule() {
+        let hourly = Schedule::parse(&job(CatchUp::Skip).schedule).unwrap();
+        let slot = hourly.last_before(at(8, 30), at(10, 4)).unwrap();
+        assert_eq!(slot, at(10, 0));
+        assert_eq!(trigger_for(&job(CatchUp::Skip), &slot, &at(10, 4)), Some(RunTrigger::Scheduled));
+        assert_eq!(trigger_for(&job(CatchUp::Run), &slot, &at(10, 5)), Some(RunTrigger::Scheduled));
+    }
+
+    #[test]
+    fn missed_runs_are_caught_up_once_or_skipped() {
+        let hourly = Schedule::parse(&job(CatchUp::Run).schedule).unwrap();
+        // Closed from 08:30 to 10:40: only the 10:00 run is made up for
+        let slot = hourly.last_before(at(8, 30), at(10, 40)).unwrap();
+        assert_eq!(slot, at(10, 0));
+        assert_eq!(trigger_for(&job(CatchUp::Run), &slot, &at(10, 40)), Some(RunTrigger::CatchUp));
+        assert_eq!(trigger_for(&job(CatchUp::Skip), &slot, &at(10, 40)), None);
+        assert_eq!(hourly.last_before(at(10, 0), at(10, 40)), None, "nothing is due after the made-up run");
+    }
+
+    #[test]
+    fn only_the_newest_versions_are_kept() {
+        let mut versions = vec![version(1), version(2), version(3)];
+        assert_eq!(prune_versions(&mut versions, 2), vec![version(1)]);
+        assert_eq!(versions, vec![version(2), version(3)]);
+        assert_eq!(prune_versions(&mut versions, 2), vec![]);
+
+        let mut all = vec![version(1), version(2), version(3)];
+        assert_eq!(prune_versions(&mut all, 0), vec![], "0 keeps every version");
+        assert_eq!(all.len(), 3);
+    }
+
+    #[test]
+    fn finished_runs_beyond_the_limit_are_dropped_with_their_logs() {
+        let dir = std::env::temp_dir().join(format!("telegram-drive-test-backups-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("backups")).unwrap();
+        let backups = BackupManager::load(&dir);
+
+        let other = backups.start_run(2, RunTrigger::Manual, RunStatus::Running);
+        backups.finish_run(&other, 1);
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            let mut run = backups.start_run(1, RunTrigger::Scheduled, RunStatus::Running);
+            fs::write(backups.log_path(run.id), "log").unwrap();
+            run.status = RunStatus::Succeeded;
+            backups.finish_run(&run, 2);
+            ids.push(run.id);
+        }
+
+        let kept: Vec<u64> = backups.runs(Some(1)).iter().map(|r| r.id).collect();
+        assert_eq!(kept, vec![ids[2], ids[1]]);
+        assert!(!backups.log_path(ids[0]).exists());
+        assert!(backups.log_path(ids[1]).exists());
+        assert_eq!(backups.runs(Some(2)).len(), 1, "other jobs keep their history");
+        assert_eq!(BackupManager::load(&dir).runs(None).len(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
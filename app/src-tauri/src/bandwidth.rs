@@ -1,9 +1,11 @@
 use std::sync::Mutex;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use chrono::Local;
 use tauri::Manager;
+use crate::accounts::AccountManager;
+use crate::error::DriveError;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct BandwidthStats {
@@ -23,20 +25,40 @@ impl Default for BandwidthStats {
 }
 
 pub struct BandwidthManager {
-    pub file_path: PathBuf,
+    pub file_path: Mutex<PathBuf>,
     pub stats: Mutex<BandwidthStats>,
     pub limit: u64, // Daily limit in bytes
 }
 
 impl BandwidthManager {
+    /// Counters are kept per account, so this loads the active account's file
     pub fn new(app_handle: &tauri::AppHandle) -> Self {
-        // Resolve app data directory
-        let app_data_dir = app_handle.path().app_data_dir().unwrap_or_else(|_| PathBuf::from("data"));
-        
-        if !app_data_dir.exists() {
-             let _ = std::fs::create_dir_all(&app_data_dir);
+        let accounts = app_handle.state::<AccountManager>();
+        let account = accounts.ensure_active();
+        Self::load(&accounts.account_dir(&account.name))
+    }
+
+    /// Loads the counters stored in `dir` (used directly by the CLI, which has no AppHandle)
+    pub fn load(dir: &Path) -> Self {
+        let (file_path, stats) = Self::read_stats(dir);
+        Self {
+            file_path: Mutex::new(file_path),
+            stats: Mutex::new(stats),
+            limit: 250 * 1024 * 1024 * 1024, // 250 GB
         }
-        let file_path = app_data_dir.join("bandwidth.json");
+    }
+
+    /// Points the manager at another account's counters
+    pub fn switch_to(&self, dir: &Path) {
+        let (file_path, stats) = Self::read_stats(dir);
+        *self.file_path.lock().unwrap() = file_path;
+        *self.stats.lock().unwrap() = stats;
+    }
+
+    /// Does not create `dir`: account directories come from `AccountManager::add`, and a
+    /// logged-out account's must stay deleted
+    fn read_stats(dir: &Path) -> (PathBuf, BandwidthStats) {
+        let file_path = dir.join("bandwidth.json");
         
         let stats = if file_path.exists() {
             let content = fs::read_to_string(&file_path).unwrap_or_default();
@@ -44,12 +66,7 @@ impl BandwidthManager {
         } else {
             BandwidthStats::default()
         };
-
-        Self {
-            file_path,
-            stats: Mutex::new(stats),
-            limit: 250 * 1024 * 1024 * 1024, // 250 GB
-        }
+        (file_path, stats)
     }
 
     pub fn check_and_reset(&self) {
@@ -63,16 +80,16 @@ impl BandwidthManager {
             // Save immediately
             drop(stats); // Release lock before calling save if save uses lock (it doesn't, but self.save_locked needs the data)
             // Actually save_locked takes &stats, so we keep lock.
-            if let Ok(json) = serde_json::to_string(&self.stats.lock().unwrap().clone()) { let _ = fs::write(&self.file_path, json); }
+            if let Ok(json) = serde_json::to_string(&self.stats.lock().unwrap().clone()) { let _ = crate::atomic::write(&*self.file_path.lock().unwrap(), json); }
         }
     }
 
-    pub fn can_transfer(&self, bytes: u64) -> Result<(), String> {
+    pub fn can_transfer(&self, bytes: u64) -> Result<(), DriveError> {
         self.check_and_reset();
         let stats = self.stats.lock().unwrap();
         let total = stats.up_bytes + stats.down_bytes + bytes;
         if total > self.limit {
-            return Err(format!("Daily bandwidth limit ({}) exceeded! Used: {}", self.format_bytes(self.limit), self.format_bytes(total)));
+            return Err(DriveError::BandwidthExceeded(format!("Daily bandwidth limit ({}) exceeded! Used: {}", self.format_bytes(self.limit), self.format_bytes(total))));
         }
         Ok(())
     }
@@ -93,7 +110,7 @@ impl BandwidthManager {
 
     fn save_locked(&self, stats: &BandwidthStats) {
         if let Ok(json) = serde_json::to_string(stats) {
-            let _ = fs::write(&self.file_path, json);
+            let _ = crate::atomic::write(&*self.file_path.lock().unwrap(), json);
         }
     }
     
@@ -0,0 +1,486 @@
+//! Headless Telegram Drive client for cron jobs and CI runners.
+//!
+//! Uses the same `telegram.session`, bandwidth counters and command logic as the
+//! desktop app, but never starts Tauri or opens a window.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use app_lib::accounts::AccountManager;
+use app_lib::bandwidth::BandwidthManager;
+use app_lib::bot::BotDrive;
+use app_lib::messages::MessageCache;
+use app_lib::peers::PeerCache;
+use app_lib::transfers::TransferManager;
+use app_lib::commands::{auth, bot, fs, tree};
+use app_lib::filenames::{CollisionStrategy, resolve_collision};
+use app_lib::filters::PathFilter;
+use app_lib::models::AuthResult;
+use app_lib::proxy::ProxyManager;
+use app_lib::vault::SessionVault;
+use clap::{Parser, Subcommand};
+use grammers_client::{Client, SignInError};
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+/// Must match `identifier` in tauri.conf.json so the CLI finds the desktop app's session
+const APP_IDENTIFIER: &str = "com.cameronamer.telegramdrive";
+
+#[derive(Parser)]
+#[command(name = "tdrive", version, about = "Headless Telegram Drive client")]
+struct Cli {
+    /// Directory holding telegram.session (defaults to the desktop app's data directory)
+    #[arg(long, env = "TELEGRAM_DRIVE_DATA_DIR", global = true)]
+    data_dir: Option<PathBuf>,
+
+    /// Account to use (defaults to the one active in the desktop app)
+    #[arg(long, env = "TELEGRAM_DRIVE_ACCOUNT", global = true)]
+    account: Option<String>,
+
+    /// Telegram API ID (defaults to the one saved for the account)
+    #[arg(long, env = "TELEGRAM_API_ID", global = true)]
+    api_id: Option<i32>,
+
+    /// Print JSON instead of human-readable text
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Sign in with a phone number (prompts for the code and 2FA password) or a bot token
+    Login {
+        #[arg(long, required_unless_present = "bot_token", conflicts_with = "bot_token")]
+        phone: Option<String>,
+        /// Sign in as a bot; use a separate --account for it
+        #[arg(long, env = "TELEGRAM_BOT_TOKEN")]
+        bot_token: Option<String>,
+        #[arg(long, env = "TELEGRAM_API_HASH")]
+        api_hash: String,
+    },
+    /// List files in a folder, or the drive folders themselves with --folders
+    Ls {
+        /// Folder ID or name ("home" for Saved Messages)
+        folder: Option<String>,
+        #[arg(long)]
+        folders: bool,
+    },
+    /// Upload one or more files
+    Upload {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        #[arg(short, long)]
+        folder: Option<String>,
+    },
+    /// Upload a directory tree, one drive folder per directory
+    UploadDir {
+        path: PathBuf,
+        /// Only upload files matching this glob (repeatable)
+        #[arg(long)]
+        include: Vec<String>,
+        /// Skip files and directories matching this glob (repeatable)
+        #[arg(long)]
+        exclude: Vec<String>,
+    },
+    /// Download a file by message ID
+    Download {
+        message_id: i32,
+        /// File to write, or an existing directory to save under the file's own name
+        dest: PathBuf,
+        #[arg(short, long)]
+        folder: Option<String>,
+        /// What to do if the file exists: overwrite, skip or rename
+        #[arg(long, default_value = "overwrite")]
+        on_conflict: CollisionStrategy,
+    },
+    /// Download a folder and its subfolders into a local directory
+    DownloadDir {
+        /// Folder ID or name ("home" for Saved Messages)
+        folder: String,
+        dest: PathBuf,
+        /// What to do if a different file exists under the same name: overwrite, skip or rename
+        #[arg(long, default_value = "rename")]
+        on_conflict: CollisionStrategy,
+    },
+    /// Create a drive folder
+    Mkdir { name: String },
+    /// Delete files by message ID, or the folder itself with --dir
+    Rm {
+        message_ids: Vec<i32>,
+        #[arg(short, long)]
+        folder: Option<String>,
+        #[arg(long, requires = "folder", conflicts_with = "message_ids")]
+        dir: bool,
+    },
+    /// Move files between folders
+    Mv {
+        #[arg(required = true)]
+        message_ids: Vec<i32>,
+        #[arg(long)]
+        from: Option<String>,
+        #[arg(long)]
+        to: Option<String>,
+    },
+    /// Search documents across all chats
+    Search { query: String },
+    /// Encrypt all sessions with a passphrase, or decrypt them again with --remove (close the desktop app first)
+    Passphrase {
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Register a channel where the bot is admin as a folder (bot accounts only)
+    AddFolder {
+        /// @username, t.me link or channel ID
+        channel: String,
+    },
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    env_logger::init();
+    // Share this runtime with the code paths that spawn through tauri::async_runtime
+    tauri::async_runtime::set(tokio::runtime::Handle::current());
+
+    let cli = Cli::parse();
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), String> {
+    let data_dir = match cli.data_dir {
+        Some(dir) => dir,
+        None => dirs::data_dir()
+            .ok_or("Could not determine the data directory, pass --data-dir")?
+            .join(APP_IDENTIFIER),
+    };
+    std::fs::create_dir_all(&data_dir)
+        .map_err(|e| format!("Failed to create data dir: {}", e))?;
+
+    let accounts = AccountManager::load(&data_dir);
+    let vault = SessionVault::load(&data_dir);
+
+    if let Command::Passphrase { remove } = cli.command {
+        return set_passphrase(&vault, &accounts, remove);
+    }
+    if vault.is_enabled() {
+        let passphrase = match std::env::var("TELEGRAM_DRIVE_PASSPHRASE") {
+            Ok(p) => p,
+            Err(_) => prompt("Session passphrase: ")?,
+        };
+        vault.unlock(&passphrase)?;
+    }
+
+    let account = match cli.account {
+        Some(name) => match accounts.get(&name) {
+            Some(account) => account,
+            None if matches!(cli.command, Command::Login { .. }) => accounts.add(&name, None)?,
+            None => return Err(format!("Account '{}' not found", name)),
+        },
+        // Never creates or activates an account: the desktop app may be running with its own choice
+        None => accounts.active_name()
+            .and_then(|name| accounts.get(&name))
+            .ok_or("No active account in the desktop app. Pass --account to pick or create one")?,
+    };
+
+    let api_id = match cli.api_id.or(account.api_id) {
+        Some(id) => id,
+        None => saved_api_id(&data_dir)
+            .ok_or("No API ID found. Pass --api-id or log in once from the desktop app")?,
+    };
+    accounts.set_api_id(&account.name, api_id);
+
+    let account_dir = accounts.account_dir(&account.name);
+    std::fs::create_dir_all(&account_dir)
+        .map_err(|e| format!("Failed to create account dir: {}", e))?;
+    // A plain session already present may be in use by the desktop app, so only re-seal what we decrypted
+    let unsealed_here = vault.is_enabled() && !vault.is_open(&account_dir);
+    let session = auth::open_session(&vault.open(&account_dir)?)?;
+    vault.checkpoint(&account_dir)?;
+    let proxy_url = ProxyManager::load(&data_dir).proxy_url().await?;
+    // The runner stops as soon as this sender is dropped, so keep it alive until we are done
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    let client = auth::start_client(Arc::new(session), api_id, proxy_url, 1, shutdown_rx, None);
+
+    let transfers = TransferManager::load(&data_dir);
+    let result = execute(&client, &account_dir, &transfers, cli.command, cli.json).await;
+    let _ = shutdown_tx.send(());
+
+    if unsealed_here {
+        drop(client);
+        // Give the runner a moment to release the session file
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        vault.seal(&account_dir)?;
+    }
+    result
+}
+
+fn set_passphrase(vault: &SessionVault, accounts: &AccountManager, remove: bool) -> Result<(), String> {
+    let dirs: Vec<PathBuf> = accounts.list().iter().map(|a| accounts.account_dir(&a.name)).collect();
+    if remove {
+        vault.disable(&prompt("Current passphrase: ")?, &dirs)?;
+        eprintln!("Sessions are no longer encrypted.");
+        return Ok(());
+    }
+
+    let passphrase = prompt("New passphrase: ")?;
+    if prompt("Repeat passphrase: ")? != passphrase {
+        return Err("Passphrases do not match".to_string());
+    }
+    vault.enable(&passphrase)?;
+    for dir in &dirs {
+        vault.seal(dir)?;
+    }
+    eprintln!("Encrypted {} session(s).", dirs.len());
+    Ok(())
+}
+
+async fn execute(client: &Client, account_dir: &Path, transfers: &TransferManager, command: Command, json: bool) -> Result<(), String> {
+    let drive = BotDrive::load(account_dir);
+    let peers = PeerCache::load(account_dir);
+    let messages = MessageCache::new();
+
+    if let Command::Login { phone, bot_token, api_hash } = command {
+        let result = match (phone, bot_token) {
+            (_, Some(token)) => bot_login(client, &drive, &token, &api_hash).await?,
+            (Some(phone), None) => login(client, &phone, &api_hash).await?,
+            (None, None) => unreachable!("clap requires --phone or --bot-token"),
+        };
+        return print_output(json, &result, || "Logged in.".to_string());
+    }
+
+    if !client.is_authorized().await.map_err(|e| e.to_string())? {
+        return Err("Not logged in. Run `tdrive login` first".to_string());
+    }
+    let bw_state = BandwidthManager::load(account_dir);
+
+    match command {
+        Command::Login { .. } | Command::Passphrase { .. } => unreachable!(),
+        Command::Ls { folder, folders: true } => {
+            if folder.is_some() {
+                return Err("--folders does not take a folder argument".to_string());
+            }
+            let folders = fs::scan_folders(client, &drive, &peers).await?;
+            print_output(json, &folders, || {
+                folders.iter().map(|f| format!("{}\t{}", f.id, f.name)).collect::<Vec<_>>().join("\n")
+            })
+        }
+        Command::Ls { folder, folders: false } => {
+            let folder_id = resolve_folder(client, &drive, &peers, folder.as_deref()).await?;
+            let files = fs::list_files(client, &drive, &peers, &messages, folder_id).await?;
+            print_output(json, &files, || {
+                files.iter().map(|f| format!("{}\t{}\t{}", f.id, f.size, f.name)).collect::<Vec<_>>().join("\n")
+            })
+        }
+        Command::Upload { paths, folder } => {
+            let folder_id = resolve_folder(client, &drive, &peers, folder.as_deref()).await?;
+            let mut uploaded = Vec::new();
+            for path in paths {
+                let name = path.to_string_lossy().to_string();
+                fs::upload_file(client, &bw_state, &drive, &peers, transfers, &path, folder_id, |progress| {
+                    if !json {
+                        eprint!("\r{}: {}/{} MB", progress.path, progress.bytes_sent / 1_048_576, progress.size / 1_048_576);
+                    }
+                }).await
+                    .map_err(|e| format!("{}: {}", name, e))?;
+                if !json {
+                    eprintln!();
+                }
+                uploaded.push(name);
+            }
+            print_output(json, &uploaded, || format!("Uploaded {} file(s).", uploaded.len()))
+        }
+        Command::UploadDir { path, include, exclude } => {
+            let filter = PathFilter::new(&include, &exclude)?;
+            let summary = tree::upload_directory(client, &bw_state, &drive, &peers, transfers, &path, &filter, |progress| {
+                if !json {
+                    eprint!("\r[{}/{}] {}", progress.files_done, progress.files_total, progress.path);
+                }
+            }).await?;
+            if !json {
+                eprintln!();
+            }
+            print_output(json, &summary, || {
+                let mut lines = vec![format!(
+                    "Uploaded {} file(s), skipped {}, failed {}.",
+                    summary.uploaded.len(), summary.skipped.len(), summary.failed.len()
+                )];
+                lines.extend(summary.skipped.iter().map(|i| format!("skipped\t{}\t{}", i.path, i.reason)));
+                lines.extend(summary.failed.iter().map(|i| format!("failed\t{}\t{}", i.path, i.reason)));
+                lines.join("\n")
+            })
+        }
+        Command::Download { message_id, dest, folder, on_conflict } => {
+            let folder_id = resolve_folder(client, &drive, &peers, folder.as_deref()).await?;
+            let target = if dest.is_dir() {
+                fs::SaveTarget::Dir(dest)
+            } else if on_conflict == CollisionStrategy::Rename {
+                // A path target is never renamed, so pick the free name here
+                fs::SaveTarget::Path(resolve_collision(&dest, on_conflict).unwrap_or(dest))
+            } else {
+                fs::SaveTarget::Path(dest)
+            };
+            let saved = fs::download_file(client, &bw_state, &drive, &peers, &messages, transfers, message_id, target, folder_id, on_conflict).await?;
+            print_output(json, &saved, || {
+                if saved.skipped {
+                    format!("Skipped, {} already exists", saved.path)
+                } else {
+                    format!("Saved to {}", saved.path)
+                }
+            })
+        }
+        Command::DownloadDir { folder, dest, on_conflict } => {
+            let folder_id = resolve_folder(client, &drive, &peers, Some(&folder)).await?;
+            let summary = tree::download_folder(client, &bw_state, &drive, &peers, &messages, transfers, folder_id, &dest, on_conflict, |progress| {
+                if !json {
+                    eprint!("\r[{}/{}] {}", progress.files_done, progress.files_total, progress.path);
+                }
+            }).await?;
+            if !json {
+                eprintln!();
+            }
+            print_output(json, &summary, || {
+                let mut lines = vec![format!(
+                    "Downloaded {} file(s), skipped {}, failed {}.",
+                    summary.downloaded.len(), summary.skipped.len(), summary.failed.len()
+                )];
+                lines.extend(summary.failed.iter().map(|i| format!("failed\t{}\t{}", i.path, i.reason)));
+                lines.join("\n")
+            })
+        }
+        Command::Mkdir { name } => {
+            let created = fs::create_folder(client, &drive, &peers, name).await?;
+            print_output(json, &created, || format!("{}\t{}", created.id, created.name))
+        }
+        Command::Rm { folder, dir: true, .. } => {
+            let folder_id = resolve_folder(client, &drive, &peers, folder.as_deref()).await?
+                .ok_or("Saved Messages cannot be deleted")?;
+            fs::delete_folder(client, &drive, &peers, folder_id).await?;
+            print_output(json, &folder_id, || format!("Deleted folder {}", folder_id))
+        }
+        Command::Rm { message_ids, folder, dir: false } => {
+            if message_ids.is_empty() {
+                return Err("No message IDs given".to_string());
+            }
+            let folder_id = resolve_folder(client, &drive, &peers, folder.as_deref()).await?;
+            fs::delete_files(client, &drive, &peers, &messages, &message_ids, folder_id).await?;
+            print_output(json, &message_ids, || format!("Deleted {} file(s).", message_ids.len()))
+        }
+        Command::Mv { message_ids, from, to } => {
+            let source = resolve_folder(client, &drive, &peers, from.as_deref()).await?;
+            let target = resolve_folder(client, &drive, &peers, to.as_deref()).await?;
+            fs::move_files(client, &drive, &peers, &messages, &message_ids, source, target).await?;
+            print_output(json, &message_ids, || format!("Moved {} file(s).", message_ids.len()))
+        }
+        Command::Search { query } => {
+            let files = fs::search_global(client, &drive, &peers, &messages, query).await?;
+            print_output(json, &files, || {
+                files.iter()
+                    .map(|f| format!("{}\t{}\t{}\t{}", f.folder_id.unwrap_or_default(), f.id, f.size, f.name))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+        }
+        Command::AddFolder { channel } => {
+            let folder = bot::add_bot_folder(client, &drive, &channel).await?;
+            print_output(json, &folder, || format!("{}\t{}", folder.id, folder.name))
+        }
+    }
+}
+
+async fn login(client: &Client, phone: &str, api_hash: &str) -> Result<AuthResult, String> {
+    let token = auth::request_login_code(client, phone, api_hash).await?;
+    let code = prompt("Login code: ")?;
+
+    match client.sign_in(&token, &code).await {
+        Ok(_user) => {}
+        Err(SignInError::PasswordRequired(pw_token)) => {
+            let password = match std::env::var("TELEGRAM_PASSWORD") {
+                Ok(p) => p,
+                Err(_) => prompt("2FA password: ")?,
+            };
+            client.check_password(pw_token, password.as_str()).await
+                .map_err(|e| format!("2FA Failed: {}", e))?;
+        }
+        Err(e) => return Err(format!("Sign in failed: {}", e)),
+    }
+
+    Ok(AuthResult {
+        success: true,
+        next_step: None,
+        error: None,
+    })
+}
+
+async fn bot_login(client: &Client, drive: &BotDrive, token: &str, api_hash: &str) -> Result<AuthResult, String> {
+    if !drive.is_bot() && client.is_authorized().await.map_err(|e| e.to_string())? {
+        return Err("This account is signed in with a phone number. Use --account to log the bot into its own".to_string());
+    }
+    client.bot_sign_in(token, api_hash).await
+        .map_err(|e| format!("Bot sign in failed: {}", e))?;
+    drive.enable();
+
+    Ok(AuthResult {
+        success: true,
+        next_step: None,
+        error: None,
+    })
+}
+
+/// "home", "me" or no folder means Saved Messages; numbers are channel IDs; anything else is matched by name.
+async fn resolve_folder(client: &Client, drive: &BotDrive, peers: &PeerCache, folder: Option<&str>) -> Result<Option<i64>, String> {
+    let Some(folder) = folder else { return Ok(None) };
+    if folder == "home" || folder == "me" {
+        return Ok(None);
+    }
+    if let Ok(id) = folder.parse::<i64>() {
+        return Ok(Some(id));
+    }
+    fs::scan_folders(client, drive, peers).await?
+        .into_iter()
+        .find(|f| f.name.eq_ignore_ascii_case(folder))
+        .map(|f| Some(f.id))
+        .ok_or_else(|| format!("Folder '{}' not found", folder))
+}
+
+/// Reads the API ID the desktop app saved in its store (config.json, or settings.json on older installs)
+fn saved_api_id(data_dir: &Path) -> Option<i32> {
+    ["config.json", "settings.json"].iter().find_map(|name| {
+        let content = std::fs::read_to_string(data_dir.join(name)).ok()?;
+        let value: serde_json::Value = serde_json::from_str(&content).ok()?;
+        match value.get("api_id")? {
+            serde_json::Value::String(s) => s.trim().parse().ok(),
+            serde_json::Value::Number(n) => n.as_i64().and_then(|n| i32::try_from(n).ok()),
+            _ => None,
+        }
+    })
+}
+
+fn prompt(label: &str) -> Result<String, String> {
+    eprint!("{}", label);
+    std::io::stderr().flush().map_err(|e| e.to_string())?;
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line).map_err(|e| e.to_string())?;
+    Ok(line.trim().to_string())
+}
+
+fn print_output<T: Serialize>(json: bool, value: &T, text: impl FnOnce() -> String) -> Result<(), String> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(value).map_err(|e| e.to_string())?);
+    } else {
+        let text = text();
+        if !text.is_empty() {
+            println!("{}", text);
+        }
+    }
+    Ok(())
+}
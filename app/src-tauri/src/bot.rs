@@ -0,0 +1,146 @@
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+use crate::accounts::AccountManager;
+
+/// Name of the index file inside a bot account's directory
+pub const INDEX_FILE: &str = "bot_drive.json";
+
+/// A channel where the bot is admin, used as a drive folder
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BotFolder {
+    pub id: i64,
+    pub access_hash: i64,
+    pub name: String,
+    /// Messages the bot knows about in this channel, oldest first
+    #[serde(default)]
+    pub message_ids: Vec<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct BotIndex {
+    folders: Vec<BotFolder>,
+}
+
+/// Folder and file index for accounts signed in with a bot token.
+///
+/// Bots can neither list their dialogs nor read chat history, so the channels a
+/// bot drives and the messages it has posted there are recorded in `bot_drive.json`.
+/// Regular accounts have no such file, and every method is then a no-op.
+pub struct BotDrive {
+    file_path: Mutex<PathBuf>,
+    index: Mutex<Option<BotIndex>>,
+}
+
+impl BotDrive {
+    /// Loads the index of the active account
+    pub fn new(app_handle: &tauri::AppHandle) -> Self {
+        let accounts = app_handle.state::<AccountManager>();
+        let account = accounts.ensure_active();
+        Self::load(&accounts.account_dir(&account.name))
+    }
+
+    pub fn load(dir: &Path) -> Self {
+        let (file_path, index) = Self::read_index(dir);
+        Self {
+            file_path: Mutex::new(file_path),
+            index: Mutex::new(index),
+        }
+    }
+
+    /// Points the drive at another account's index
+    pub fn switch_to(&self, dir: &Path) {
+        let (file_path, index) = Self::read_index(dir);
+        *self.file_path.lock().unwrap() = file_path;
+        *self.index.lock().unwrap() = index;
+    }
+
+    fn read_index(dir: &Path) -> (PathBuf, Option<BotIndex>) {
+        let file_path = dir.join(INDEX_FILE);
+        let index = fs::read_to_string(&file_path)
+            .ok()
+            .map(|content| serde_json::from_str(&content).unwrap_or_default());
+        (file_path, index)
+    }
+
+    fn save(&self, index: &BotIndex) {
+        if let Ok(json) = serde_json::to_string_pretty(index) {
+            let _ = crate::atomic::write(&*self.file_path.lock().unwrap(), json);
+        }
+    }
+
+    pub fn is_bot(&self) -> bool {
+        self.index.lock().unwrap().is_some()
+    }
+
+    /// Marks the current account as a bot account, keeping any existing index
+    pub fn enable(&self) {
+        let mut index = self.index.lock().unwrap();
+        let index = index.get_or_insert_with(BotIndex::default);
+        self.save(index);
+    }
+
+    pub fn folders(&self) -> Vec<BotFolder> {
+        self.index.lock().unwrap().as_ref().map(|i| i.folders.clone()).unwrap_or_default()
+    }
+
+    pub fn folder(&self, id: i64) -> Option<BotFolder> {
+        self.index.lock().unwrap().as_ref()?.folders.iter().find(|f| f.id == id).cloned()
+    }
+
+    /// Registers a folder, or refreshes its name and access hash if it is already known
+    pub fn add_folder(&self, id: i64, access_hash: i64, name: String) {
+        self.update(|index| {
+            match index.folders.iter_mut().find(|f| f.id == id) {
+                Some(folder) => {
+                    folder.access_hash = access_hash;
+                    folder.name = name;
+                }
+                None => index.folders.push(BotFolder { id, access_hash, name, message_ids: Vec::new() }),
+            }
+        });
+    }
+
+    pub fn remove_folder(&self, id: i64) {
+        self.update(|index| index.folders.retain(|f| f.id != id));
+    }
+
+    pub fn message_ids(&self, folder_id: i64) -> Vec<i32> {
+        self.folder(folder_id).map(|f| f.message_ids).unwrap_or_default()
+    }
+
+    /// Records messages posted to `folder_id`
+    pub fn track(&self, folder_id: Option<i64>, message_ids: &[i32]) {
+        let Some(folder_id) = folder_id else { return };
+        self.update(|index| {
+            if let Some(folder) = index.folders.iter_mut().find(|f| f.id == folder_id) {
+                folder.message_ids.extend_from_slice(message_ids);
+                folder.message_ids.sort_unstable();
+                folder.message_ids.dedup();
+            }
+        });
+    }
+
+    /// Forgets messages that were deleted or moved out of `folder_id`
+    pub fn untrack(&self, folder_id: Option<i64>, message_ids: &[i32]) {
+        let Some(folder_id) = folder_id else { return };
+        if message_ids.is_empty() {
+            return;
+        }
+        self.update(|index| {
+            if let Some(folder) = index.folders.iter_mut().find(|f| f.id == folder_id) {
+                folder.message_ids.retain(|id| !message_ids.contains(id));
+            }
+        });
+    }
+
+    fn update(&self, f: impl FnOnce(&mut BotIndex)) {
+        let mut index = self.index.lock().unwrap();
+        if let Some(index) = index.as_mut() {
+            f(index);
+            self.save(index);
+        }
+    }
+}
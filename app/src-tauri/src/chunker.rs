@@ -0,0 +1,186 @@
+use std::io::{self, Read};
+
+/// No cut is made before this many bytes
+pub const MIN_CHUNK: usize = 256 * 1024;
+/// Chunks average about this size: a cut is expected every 2^20 bytes past the minimum
+pub const AVG_CHUNK: usize = 1024 * 1024;
+/// Chunks are cut here at the latest
+pub const MAX_CHUNK: usize = 4 * 1024 * 1024;
+
+/// Top 20 bits of the rolling hash, which depend on the last 64 bytes only
+const MASK: u64 = !(u64::MAX >> AVG_CHUNK.trailing_zeros());
+const READ_SIZE: usize = 64 * 1024;
+
+/// Random values for each byte, fixed so the same data is always cut the same way
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    // splitmix64
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x5444_7269_7665_4344;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Splits a stream into content-defined chunks (a gear hash, as in FastCDC).
+///
+/// Cut points depend only on the bytes around them, so an insertion early in a
+/// file changes the chunks near it and leaves the rest identical, which is what
+/// lets snapshots share chunks between file versions.
+pub struct Chunker<R> {
+    reader: R,
+    buf: Vec<u8>,
+    eof: bool,
+}
+
+impl<R: Read> Chunker<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader, buf: Vec::with_capacity(MAX_CHUNK), eof: false }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let mut block = [0u8; READ_SIZE];
+        while !self.eof && self.buf.len() < MAX_CHUNK {
+            let want = READ_SIZE.min(MAX_CHUNK - self.buf.len());
+            match self.reader.read(&mut block[..want]) {
+                Ok(0) => self.eof = true,
+                Ok(n) => self.buf.extend_from_slice(&block[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for Chunker<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(e) = self.fill() {
+            return Some(Err(e));
+        }
+        if self.buf.is_empty() {
+            return None;
+        }
+        let rest = self.buf.split_off(cut_point(&self.buf));
+        Some(Ok(std::mem::replace(&mut self.buf, rest)))
+    }
+}
+
+/// Length of the first chunk of `data`
+fn cut_point(data: &[u8]) -> usize {
+    let end = data.len().min(MAX_CHUNK);
+    if end <= MIN_CHUNK {
+        return end;
+    }
+    // The hash covers the last 64 bytes, so it starts that far before the first possible cut
+    let mut hash = 0u64;
+    for (i, byte) in data.iter().enumerate().take(end).skip(MIN_CHUNK - 64) {
+        hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+        if i >= MIN_CHUNK && hash & MASK == 0 {
+            return i + 1;
+        }
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random bytes (xorshift64)
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 24) as u8
+        }).collect()
+    }
+
+    fn chunks(data: &[u8]) -> Vec<Vec<u8>> {
+        Chunker::new(data).collect::<io::Result<_>>().unwrap()
+    }
+
+    /// Hands out at most `step` bytes per read, with an interruption before each one
+    struct Trickle<'a> {
+        data: &'a [u8],
+        step: usize,
+        interrupt: bool,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+            let n = buf.len().min(self.step).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn small_inputs_are_one_chunk() {
+        assert!(chunks(&[]).is_empty());
+        assert_eq!(chunks(b"hello"), vec![b"hello".to_vec()]);
+        let data = noise(MIN_CHUNK, 1);
+        assert_eq!(chunks(&data), vec![data]);
+    }
+
+    #[test]
+    fn cut_point_stays_within_bounds() {
+        let data = noise(MAX_CHUNK * 2, 2);
+        let cut = cut_point(&data);
+        assert!(cut > MIN_CHUNK && cut <= MAX_CHUNK);
+        assert_eq!(cut_point(&data[..MIN_CHUNK + 10]), cut.min(MIN_CHUNK + 10));
+        // Nothing in a run of equal bytes to cut at
+        assert_eq!(cut_point(&vec![7u8; MAX_CHUNK + 1]), MAX_CHUNK);
+    }
+
+    #[test]
+    fn chunks_rebuild_the_input() {
+        let data = noise(16 * 1024 * 1024, 3);
+        let chunks = chunks(&data);
+        assert_eq!(chunks.concat(), data);
+        let (last, rest) = chunks.split_last().unwrap();
+        assert!(rest.iter().all(|c| c.len() > MIN_CHUNK && c.len() <= MAX_CHUNK));
+        assert!(!last.is_empty() && last.len() <= MAX_CHUNK);
+        // About one cut per AVG_CHUNK past the minimum
+        assert!((4..=24).contains(&chunks.len()), "{} chunks", chunks.len());
+    }
+
+    #[test]
+    fn cuts_do_not_depend_on_read_sizes() {
+        let data = noise(6 * 1024 * 1024, 4);
+        let trickled: Vec<Vec<u8>> = Chunker::new(Trickle { data: &data, step: 1000, interrupt: false })
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(trickled, chunks(&data));
+    }
+
+    #[test]
+    fn insertion_only_changes_nearby_chunks() {
+        let data = noise(16 * 1024 * 1024, 5);
+        let mut edited = data[..1000].to_vec();
+        edited.extend_from_slice(b"inserted near the start");
+        edited.extend_from_slice(&data[1000..]);
+
+        let before = chunks(&data);
+        let after = chunks(&edited);
+        assert_ne!(before[0], after[0]);
+        assert_eq!(before[1..], after[1..]);
+    }
+}
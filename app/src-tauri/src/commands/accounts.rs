@@ -0,0 +1,144 @@
+use std::path::Path;
+use std::sync::Arc;
+use tauri::{Manager, State};
+use crate::TelegramState;
+use crate::accounts::AccountManager;
+use crate::bandwidth::BandwidthManager;
+use crate::bot::BotDrive;
+use crate::live::LiveFolders;
+use crate::messages::MessageCache;
+use crate::peers::PeerCache;
+use crate::commands::{AccountCaches, ParkedClient, ensure_client_initialized};
+use crate::error::DriveError;
+use crate::models::AccountInfo;
+use crate::vault::SessionVault;
+
+#[tauri::command]
+pub async fn cmd_list_accounts(
+    state: State<'_, TelegramState>,
+    accounts: State<'_, AccountManager>,
+) -> Result<Vec<AccountInfo>, DriveError> {
+    let active = accounts.active_name();
+    let active_connected = state.client.lock().await.is_some();
+    let parked = state.parked.lock().await;
+
+    Ok(accounts.list().into_iter().map(|a| {
+        let is_active = active.as_deref() == Some(a.name.as_str());
+        AccountInfo {
+            connected: if is_active { active_connected } else { parked.contains_key(&a.name) },
+            bot: accounts.account_dir(&a.name).join(crate::bot::INDEX_FILE).exists(),
+            active: is_active,
+            name: a.name,
+            api_id: a.api_id,
+        }
+    }).collect())
+}
+
+/// Registers a new account and makes it active. The frontend then runs the normal login flow for it.
+#[tauri::command]
+pub async fn cmd_add_account(
+    name: String,
+    app_handle: tauri::AppHandle,
+    state: State<'_, TelegramState>,
+    accounts: State<'_, AccountManager>,
+) -> Result<AccountInfo, DriveError> {
+    let account = accounts.add(&name, None)?;
+    park_active_client(&state, &accounts).await;
+    accounts.set_active(Some(&account.name))?;
+    switch_account_state(&app_handle, &accounts.account_dir(&account.name));
+
+    Ok(AccountInfo { name: account.name, api_id: None, active: true, connected: false, bot: false })
+}
+
+/// Makes `name` the active account. The previous client stays connected in the
+/// background, so switching back and forth does not reconnect.
+#[tauri::command]
+pub async fn cmd_switch_account(
+    name: String,
+    app_handle: tauri::AppHandle,
+    state: State<'_, TelegramState>,
+    accounts: State<'_, AccountManager>,
+    drive: State<'_, BotDrive>,
+) -> Result<AccountInfo, DriveError> {
+    let account = accounts.get(&name).ok_or_else(|| DriveError::NotFound(format!("Account '{}' not found", name)))?;
+
+    if accounts.active_name().as_deref() != Some(name.as_str()) {
+        log::info!("Switching to account '{}'", name);
+        park_active_client(&state, &accounts).await;
+        accounts.set_active(Some(&name))?;
+        switch_account_state(&app_handle, &accounts.account_dir(&name));
+
+        let parked = state.parked.lock().await.remove(&name);
+        if let Some(parked) = parked {
+            *state.client.lock().await = Some(parked.client);
+            *state.session.lock().await = parked.session;
+            *state.api_id.lock().await = Some(parked.api_id);
+            *state.runner_shutdown.lock().await = Some(parked.runner_shutdown);
+        } else if let Some(api_id) = account.api_id {
+            *state.api_id.lock().await = Some(api_id);
+            ensure_client_initialized(&app_handle, &state, api_id).await?;
+        }
+    }
+
+    let connected = state.client.lock().await.is_some();
+    Ok(AccountInfo { name: account.name, api_id: account.api_id, active: true, connected, bot: drive.is_bot() })
+}
+
+/// Signs an inactive account out and deletes its session. Removing the active account is `cmd_logout`.
+#[tauri::command]
+pub async fn cmd_remove_account(
+    name: String,
+    state: State<'_, TelegramState>,
+    accounts: State<'_, AccountManager>,
+    vault: State<'_, SessionVault>,
+) -> Result<bool, DriveError> {
+    if accounts.active_name().as_deref() == Some(name.as_str()) {
+        return Err(DriveError::Other("Cannot remove the active account, log out instead".to_string()));
+    }
+
+    let parked = state.parked.lock().await.remove(&name);
+    if let Some(parked) = parked {
+        let _ = parked.client.sign_out().await;
+        let _ = parked.runner_shutdown.send(());
+    }
+
+    vault.discard(&accounts.account_dir(&name));
+    accounts.remove(&name)?;
+    log::info!("Removed account '{}'", name);
+    Ok(true)
+}
+
+/// Points the managed per-account state at the account in `account_dir`: its bandwidth
+/// counters, bot folders and peer cache. Messages and live folders of the previous one are dropped.
+pub fn switch_account_state(app_handle: &tauri::AppHandle, account_dir: &Path) {
+    app_handle.state::<BandwidthManager>().switch_to(account_dir);
+    app_handle.state::<BotDrive>().switch_to(account_dir);
+    app_handle.state::<PeerCache>().switch_to(account_dir);
+    app_handle.state::<MessageCache>().clear();
+    app_handle.state::<LiveFolders>().clear();
+}
+
+/// Moves the active client (if connected) into `parked` and clears the active slot
+async fn park_active_client(state: &TelegramState, accounts: &AccountManager) {
+    let client = state.client.lock().await.take();
+    let api_id = state.api_id.lock().await.take();
+    let runner_shutdown = state.runner_shutdown.lock().await.take();
+    let session = state.session.lock().await.take();
+    if let Some(qr_login) = state.qr_login.lock().await.take() {
+        qr_login.abort();
+    }
+    *state.login_token.lock().await = None;
+    *state.password_token.lock().await = None;
+
+    match (accounts.active_name(), client, api_id, runner_shutdown) {
+        (Some(name), Some(client), Some(api_id), Some(runner_shutdown)) => {
+            let caches = Arc::new(AccountCaches::load(&accounts.account_dir(&name)));
+            state.parked.lock().await.insert(name, ParkedClient { client, session, api_id, runner_shutdown, caches });
+        }
+        (_, _, _, Some(runner_shutdown)) => {
+            // Half-initialized (e.g. mid-login): nothing worth keeping alive
+            let _ = runner_shutdown.send(());
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,66 @@
+use futures::StreamExt;
+use tauri::{Manager, State};
+use tokio::io::AsyncWriteExt;
+use crate::TelegramState;
+use crate::archive::{ZipBuilder, ZipMode, collect_items, zip_stream};
+use crate::bandwidth::BandwidthManager;
+use crate::bot::BotDrive;
+use crate::download::part_path;
+use crate::error::DriveError;
+use crate::messages::MessageCache;
+use crate::models::ZipSelection;
+use crate::paths::PathPolicy;
+use crate::peers::PeerCache;
+use crate::transfers::TransferManager;
+
+/// Saves the selected files as one ZIP archive, written while the files download.
+#[tauri::command]
+pub async fn cmd_download_zip(
+    selection: Vec<ZipSelection>,
+    save_path: String,
+    mode: Option<ZipMode>,
+    app_handle: tauri::AppHandle,
+    state: State<'_, TelegramState>,
+    bw_state: State<'_, BandwidthManager>,
+    drive: State<'_, BotDrive>,
+    peers: State<'_, PeerCache>,
+    messages: State<'_, MessageCache>,
+    transfers: State<'_, TransferManager>,
+    policy: State<'_, PathPolicy>,
+) -> Result<String, DriveError> {
+    let save_path = policy.writable(&save_path)?;
+    let client_opt = { state.client.lock().await.clone() };
+    if client_opt.is_none() {
+        log::info!("[MOCK] Zipped {} folder selection(s) to {}", selection.len(), save_path.display());
+        std::fs::write(&save_path, ZipBuilder::new().finish())?;
+        return Ok("Download successful".to_string());
+    }
+    let client = client_opt.unwrap();
+
+    let items = collect_items(&client, &drive, &peers, &messages, &selection).await?;
+    bw_state.can_transfer(items.iter().filter_map(|i| i.file.size).sum())?;
+
+    // Like single downloads, the archive only appears under its name once it is complete
+    let part = part_path(&save_path);
+    let mut out = tokio::fs::File::create(&part).await?;
+    let handle = app_handle.clone();
+    let mut archive = Box::pin(zip_stream(client, items, mode.unwrap_or_default(), transfers.download_parallelism(), move |n| {
+        handle.state::<BandwidthManager>().add_down(n);
+    }));
+
+    while let Some(bytes) = archive.next().await {
+        let written = match bytes {
+            Ok(bytes) => out.write_all(&bytes).await.map_err(DriveError::from),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            drop(out);
+            let _ = tokio::fs::remove_file(&part).await;
+            return Err(e);
+        }
+    }
+    out.sync_all().await?;
+    drop(out);
+    tokio::fs::rename(&part, &save_path).await?;
+    Ok("Download successful".to_string())
+}
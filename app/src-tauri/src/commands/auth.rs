@@ -1,16 +1,22 @@
 use tauri::State;
 use tauri::Manager;
-use grammers_client::Client;
+use grammers_client::{Client, UpdatesConfiguration};
+use grammers_client::types::LoginToken;
+use std::path::Path;
 use std::sync::Arc;
 use std::sync::atomic::Ordering;
-use grammers_mtsender::SenderPool;
+use grammers_mtsender::{ConnectionParams, SenderPool};
 use grammers_session::storages::SqliteSession;
-use tokio::sync::oneshot;
+use tokio::sync::{broadcast, oneshot};
 use tokio::time::Duration;
 
 use crate::TelegramState;
+use crate::commands::{AccountUpdate, checkpoint_session, switch_account_state};
+use crate::accounts::AccountManager;
+use crate::proxy::ProxyManager;
+use crate::vault::SessionVault;
 use crate::models::{AuthResult};
-use crate::commands::utils::map_error;
+use crate::error::{DriveError, with_flood_retry};
 use grammers_client::SignInError;
 
 /// Ensures the Telegram client is initialized.
@@ -21,7 +27,7 @@ pub async fn ensure_client_initialized(
     app_handle: &tauri::AppHandle,
     state: &State<'_, TelegramState>,
     api_id: i32,
-) -> Result<Client, String> {
+) -> Result<Client, DriveError> {
     let mut client_guard = state.client.lock().await;
 
     if let Some(client) = client_guard.as_ref() {
@@ -42,51 +48,117 @@ pub async fn ensure_client_initialized(
     }
 
     let runner_num = state.runner_count.fetch_add(1, Ordering::SeqCst) + 1;
-    log::info!("Initializing Telegram Client #{} with API ID: {}", runner_num, api_id);
     
-    // Resolve session path safely
-    let app_data_dir = app_handle.path().app_data_dir()
-        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
-        
-    if !app_data_dir.exists() {
-        std::fs::create_dir_all(&app_data_dir)
-            .map_err(|e| format!("Failed to create app data dir: {}", e))?;
+    // Each account keeps its session in its own directory
+    let accounts = app_handle.state::<AccountManager>();
+    let account = accounts.ensure_active();
+    log::info!("Initializing Telegram Client #{} for account '{}' with API ID: {}", runner_num, account.name, api_id);
+    accounts.set_api_id(&account.name, api_id);
+    
+    let account_dir = accounts.account_dir(&account.name);
+    if !account_dir.exists() {
+        std::fs::create_dir_all(&account_dir)
+            .map_err(|e| DriveError::Io(format!("Failed to create account dir: {}", e)))?;
     }
     
-    let session_path = app_data_dir.join("telegram.session");
+    switch_account_state(app_handle, &account_dir);
+    
+    // Fails with SESSION_LOCKED before open_session could mistake the encrypted file for a corrupt one
+    let vault = app_handle.state::<SessionVault>();
+    let session = Arc::new(open_session(&vault.open(&account_dir)?)?);
+    // Seal again right away, so a crash never leaves the decrypted copy as the only one
+    if let Err(e) = vault.checkpoint(&account_dir) {
+        log::error!("Failed to encrypt session of '{}': {}", account.name, e);
+    }
+    *state.session.lock().await = Some(session.clone());
+    let proxy_url = app_handle.state::<ProxyManager>().proxy_url().await?;
+    
+    // Create shutdown channel for this runner
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    *state.runner_shutdown.lock().await = Some(shutdown_tx);
+    
+    let updates = Some((account.name.clone(), state.updates.clone()));
+    let client = start_client(session, api_id, proxy_url, runner_num, shutdown_rx, updates);
+    
+    *client_guard = Some(client.clone());
+    Ok(client)
+}
+
+/// Opens the SQLite session at `session_path`, recreating it if the file is corrupted.
+pub fn open_session(session_path: &Path) -> Result<SqliteSession, String> {
     let session_path_str = session_path.to_string_lossy().to_string();
     log::info!("Opening session at: {}", session_path_str);
     
     // Grammers initialization with corruption recovery
-    let session = match SqliteSession::open(&session_path_str).map_err(|e| e.to_string()) {
-        Ok(s) => s,
+    match SqliteSession::open(&session_path_str).map_err(|e| e.to_string()) {
+        Ok(s) => Ok(s),
         Err(_) => {
             log::warn!("Session file corrupted or invalid. Recreating...");
-            let _ = std::fs::remove_file(&session_path);
+            let _ = std::fs::remove_file(session_path);
             let _ = std::fs::remove_file(format!("{}-wal", session_path_str));
             let _ = std::fs::remove_file(format!("{}-shm", session_path_str));
             
             SqliteSession::open(&session_path_str)
-                .map_err(|e| format!("Failed to open session after recreation: {}", e))?
+                .map_err(|e| format!("Failed to open session after recreation: {}", e))
+        }
+    }
+}
+
+/// Builds a client on top of `session` and spawns its network runner,
+/// which exits when `shutdown_rx` fires (or its sender is dropped).
+/// `proxy_url` is a `socks5://` URL from `ProxyManager::proxy_url`.
+/// When `updates` is given, incoming updates are tagged with that account name and broadcast.
+pub fn start_client(
+    session: Arc<SqliteSession>,
+    api_id: i32,
+    proxy_url: Option<String>,
+    runner_num: u32,
+    shutdown_rx: oneshot::Receiver<()>,
+    updates: Option<(String, broadcast::Sender<AccountUpdate>)>,
+) -> Client {
+    let pool = match proxy_url {
+        Some(proxy_url) => {
+            log::info!("Connecting through proxy");
+            SenderPool::with_configuration(session, api_id, ConnectionParams {
+                proxy_url: Some(proxy_url),
+                ..Default::default()
+            })
         }
+        None => SenderPool::new(session, api_id),
     };
-        
-    let session = Arc::new(session);
-    let pool = SenderPool::new(session, api_id);
     let client = Client::new(&pool);
     
-    // Create shutdown channel for this runner
-    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
-    *state.runner_shutdown.lock().await = Some(shutdown_tx);
-    
     // Spawn the network runner with shutdown support
-    let SenderPool { runner, .. } = pool;
+    let SenderPool { runner, updates: update_rx, .. } = pool;
+    let update_client = client.clone();
+    let forward_updates = async move {
+        let Some((account, update_tx)) = updates else {
+            return std::future::pending::<()>().await;
+        };
+        let mut stream = update_client.stream_updates(update_rx, UpdatesConfiguration {
+            catch_up: false,
+            ..Default::default()
+        }).await;
+        loop {
+            match stream.next().await {
+                Ok(update) => {
+                    // Nobody listening is fine, updates are only needed while a flow waits on them
+                    let _ = update_tx.send(AccountUpdate { account: account.clone(), update: Arc::new(update) });
+                }
+                Err(e) => {
+                    log::warn!("Update stream #{} error: {}", runner_num, e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    };
     tauri::async_runtime::spawn(async move {
         tokio::select! {
             // Normal runner operation
             _ = runner.run() => {
                 log::info!("Runner #{} exited normally", runner_num);
             }
+            _ = forward_updates => {}
             // Shutdown requested
             _ = shutdown_rx => {
                 log::info!("Runner #{} shutdown requested, exiting", runner_num);
@@ -94,8 +166,7 @@ pub async fn ensure_client_initialized(
         }
     });
     
-    *client_guard = Some(client.clone());
-    Ok(client)
+    client
 }
 
 #[tauri::command]
@@ -103,7 +174,7 @@ pub async fn cmd_connect(
     app_handle: tauri::AppHandle,
     state: State<'_, TelegramState>,
     api_id: i32,
-) -> Result<bool, String> {
+) -> Result<bool, DriveError> {
     // Store API ID for auto-reconnect
     *state.api_id.lock().await = Some(api_id);
     ensure_client_initialized(&app_handle, &state, api_id).await?;
@@ -114,7 +185,7 @@ pub async fn cmd_connect(
 pub async fn cmd_check_connection(
     app_handle: tauri::AppHandle,
     state: State<'_, TelegramState>,
-) -> Result<bool, String> {
+) -> Result<bool, DriveError> {
     // 1. Check if client exists and is responsive
     let client_msg_opt = {
         let guard = state.client.lock().await;
@@ -144,10 +215,13 @@ pub async fn cmd_check_connection(
                     log::info!("Auto-reconnect successful.");
                     return Ok(true);
                 } else {
-                    return Err("Reconnect succeeded but ping failed.".to_string());
+                    return Err(DriveError::Other("Reconnect succeeded but ping failed.".to_string()));
                 }
             },
-            Err(e) => return Err(format!("Auto-reconnect failed: {}", e))
+            Err(e) => {
+                log::warn!("Auto-reconnect failed: {}", e);
+                return Err(e);
+            }
         }
     }
 
@@ -158,7 +232,7 @@ pub async fn cmd_check_connection(
 pub async fn cmd_logout(
     app_handle: tauri::AppHandle,
     state: State<'_, TelegramState>,
-) -> Result<bool, String> {
+) -> Result<bool, DriveError> {
     log::info!("Logging out...");
     
     // 1. Shutdown the network runner FIRST to prevent any operations
@@ -178,17 +252,23 @@ pub async fn cmd_logout(
     }
 
     // 3. Clear State
+    if let Some(qr_login) = state.qr_login.lock().await.take() {
+        qr_login.abort();
+    }
     *state.client.lock().await = None;
+    *state.session.lock().await = None;
     *state.login_token.lock().await = None;
     *state.password_token.lock().await = None;
     *state.api_id.lock().await = None;
 
-    // 4. Remove Session File
-    let app_data_dir = app_handle.path().app_data_dir().unwrap();
-    let session_path = app_data_dir.join("telegram.session");
-    let _ = std::fs::remove_file(session_path);
-    let _ = std::fs::remove_file(app_data_dir.join("telegram.session-wal"));
-    let _ = std::fs::remove_file(app_data_dir.join("telegram.session-shm"));
+    // 4. Remove the account along with its session file
+    let accounts = app_handle.state::<AccountManager>();
+    if let Some(name) = accounts.active_name() {
+        app_handle.state::<SessionVault>().discard(&accounts.account_dir(&name));
+        accounts.remove(&name)?;
+        // Everything is gone with the account dir, so this leaves bot mode and starts from empty caches and counters
+        switch_account_state(&app_handle, &accounts.account_dir(&name));
+    }
 
     log::info!("Logout complete. Runner count: {}", state.runner_count.load(Ordering::SeqCst));
     Ok(true)
@@ -201,10 +281,10 @@ pub async fn cmd_auth_request_code(
     api_id: i32,
     api_hash: String,
     state: State<'_, TelegramState>,
-) -> Result<String, String> {
+) -> Result<String, DriveError> {
     
     if api_hash.trim().is_empty() {
-        return Err("API Hash cannot be empty.".to_string());
+        return Err("API Hash cannot be empty.".into());
     }
 
     // Store API ID
@@ -212,48 +292,39 @@ pub async fn cmd_auth_request_code(
 
     let client_handle = ensure_client_initialized(&app_handle, &state, api_id).await?;
     
+    let token = request_login_code(&client_handle, &phone, &api_hash).await?;
+    *state.login_token.lock().await = Some(token);
+    Ok("code_sent".to_string())
+}
+
+/// Requests a login code. Flood waits go through `with_flood_retry`, since a server
+/// error may still have sent a code, and AUTH_RESTART is retried once.
+pub async fn request_login_code(client: &Client, phone: &str, api_hash: &str) -> Result<LoginToken, DriveError> {
     log::info!("Requesting code for {}", phone);
-    
-    let mut last_error = String::new();
-    
-    // Retry up to 2 times for AUTH_RESTART or 500
-    for i in 1..=2 {
-        match client_handle.request_login_code(&phone, &api_hash).await {
-            Ok(token) => {
-                let mut token_guard = state.login_token.lock().await;
-                *token_guard = Some(token);
-                return Ok("code_sent".to_string());
-            },
-            Err(e) => {
-                let err_msg = e.to_string();
-                log::warn!("Error requesting code (Attempt {}): {}", i, err_msg);
-                
-                if err_msg.contains("AUTH_RESTART") || err_msg.contains("500") {
-                    log::info!("AUTH_RESTART error detected. Retrying...");
-                    last_error = err_msg;
-                    // Prepare for retry
-                    continue;
-                }
-                
-                // Other errors, fail immediately
-                return Err(map_error(e));
+
+    let mut restarted = false;
+    loop {
+        match with_flood_retry("auth.sendCode", || client.request_login_code(phone, api_hash)).await {
+            Err(DriveError::Telegram { name, .. }) if name == "AUTH_RESTART" && !restarted => {
+                log::info!("AUTH_RESTART error detected. Retrying...");
+                restarted = true;
             }
+            result => return result,
         }
     }
-
-    Err(format!("Telegram Error after retry: {}", last_error))
 }
 
 #[tauri::command]
 pub async fn cmd_auth_sign_in(
     code: String,
+    app_handle: tauri::AppHandle,
     state: State<'_, TelegramState>,
-) -> Result<AuthResult, String> {
+) -> Result<AuthResult, DriveError> {
     log::info!("Signing in with code...");
     
     let client = {
         let guard = state.client.lock().await;
-        guard.as_ref().ok_or("Client not initialized")?.clone()
+        guard.as_ref().ok_or(DriveError::NotConnected)?.clone()
     };
 
     let token_guard = state.login_token.lock().await;
@@ -262,6 +333,7 @@ pub async fn cmd_auth_sign_in(
     match client.sign_in(login_token, &code).await {
         Ok(_user) => {
              log::info!("Successfully logged in.");
+             checkpoint_session(&app_handle);
              Ok(AuthResult {
                 success: true,
                 next_step: Some("dashboard".to_string()),
@@ -278,9 +350,13 @@ pub async fn cmd_auth_sign_in(
                 error: None,
             })
         }
+        Err(SignInError::Other(e)) => {
+           log::error!("Sign in error: {}", e);
+           Err(e.into())
+        }
         Err(e) => {
            log::error!("Sign in error: {}", e);
-           Err(format!("Sign in failed: {}", e))
+           Err(DriveError::Other(format!("Sign in failed: {}", e)))
         }
     }
 }
@@ -288,11 +364,12 @@ pub async fn cmd_auth_sign_in(
 #[tauri::command]
 pub async fn cmd_auth_check_password(
     password: String,
+    app_handle: tauri::AppHandle,
     state: State<'_, TelegramState>,
-) -> Result<AuthResult, String> {
+) -> Result<AuthResult, DriveError> {
     let client = {
         let guard = state.client.lock().await;
-        guard.as_ref().ok_or("Client not initialized")?.clone()
+        guard.as_ref().ok_or(DriveError::NotConnected)?.clone()
     };
     
     let mut pw_guard = state.password_token.lock().await;
@@ -301,12 +378,14 @@ pub async fn cmd_auth_check_password(
     match client.check_password(pw_token, password.as_str()).await {
         Ok(_user) => {
              log::info!("2FA Success.");
+             checkpoint_session(&app_handle);
              Ok(AuthResult {
                 success: true,
                 next_step: Some("dashboard".to_string()),
                 error: None,
             })
         }
-        Err(e) => Err(format!("2FA Failed: {}", e))
+        Err(SignInError::Other(e)) => Err(e.into()),
+        Err(e) => Err(DriveError::Other(format!("2FA Failed: {}", e)))
     }
 }
@@ -0,0 +1,82 @@
+use serde::Serialize;
+use tauri::State;
+use crate::accounts::AccountManager;
+use crate::backups::{BackupJob, BackupManager, BackupRun, CatchUp};
+use crate::error::DriveError;
+use crate::paths::PathPolicy;
+
+/// A backup job with its next scheduled run (Unix time), if enabled
+#[derive(Debug, Serialize, Clone)]
+pub struct BackupJobInfo {
+    #[serde(flatten)]
+    pub job: BackupJob,
+    pub next_run: Option<i64>,
+}
+
+#[tauri::command]
+pub fn cmd_list_backup_jobs(backups: State<'_, BackupManager>) -> Vec<BackupJobInfo> {
+    backups.jobs().into_iter()
+        .map(|job| BackupJobInfo { next_run: backups.next_run(&job), job })
+        .collect()
+}
+
+/// Backs up the changed files of `path` to `folder_id` on a cron `schedule`, e.g. `0 2 * * *`
+#[tauri::command]
+pub fn cmd_add_backup_job(
+    name: String,
+    path: String,
+    folder_id: Option<i64>,
+    schedule: String,
+    catch_up: Option<CatchUp>,
+    keep_versions: Option<usize>,
+    keep_runs: Option<usize>,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    accounts: State<'_, AccountManager>,
+    policy: State<'_, PathPolicy>,
+    backups: State<'_, BackupManager>,
+) -> Result<BackupJob, DriveError> {
+    let source = policy.readable(&path)?;
+    backups.add_job(BackupJob {
+        id: 0,
+        name,
+        account: accounts.ensure_active().name,
+        source,
+        folder_id,
+        schedule,
+        catch_up: catch_up.unwrap_or(CatchUp::Run),
+        keep_versions: keep_versions.unwrap_or(0),
+        keep_runs: keep_runs.unwrap_or(30),
+        include: include.unwrap_or_default(),
+        exclude: exclude.unwrap_or_default(),
+        enabled: true,
+        last_slot: 0,
+    })
+}
+
+#[tauri::command]
+pub fn cmd_remove_backup_job(id: u32, backups: State<'_, BackupManager>) {
+    backups.remove_job(id);
+}
+
+#[tauri::command]
+pub fn cmd_set_backup_job_enabled(id: u32, enabled: bool, backups: State<'_, BackupManager>) -> Result<BackupJob, DriveError> {
+    backups.set_enabled(id, enabled)
+}
+
+/// Starts a job now. Progress is reported through `backup-run` events.
+#[tauri::command]
+pub fn cmd_run_backup_job(id: u32, backups: State<'_, BackupManager>) -> Result<(), DriveError> {
+    backups.run_now(id)
+}
+
+/// Run history, newest first
+#[tauri::command]
+pub fn cmd_list_backup_runs(job_id: Option<u32>, backups: State<'_, BackupManager>) -> Vec<BackupRun> {
+    backups.runs(job_id)
+}
+
+#[tauri::command]
+pub fn cmd_get_backup_run_log(run_id: u64, backups: State<'_, BackupManager>) -> Result<String, DriveError> {
+    backups.run_log(run_id)
+}
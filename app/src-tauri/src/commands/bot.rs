@@ -0,0 +1,117 @@
+use tauri::State;
+use grammers_client::Client;
+use grammers_client::types::Peer;
+use crate::TelegramState;
+use crate::bot::BotDrive;
+use crate::commands::{checkpoint_session, ensure_client_initialized};
+use crate::commands::utils::channel_peer;
+use crate::error::DriveError;
+use crate::models::{AuthResult, FolderMetadata};
+
+/// Bot API chat IDs for channels are the MTProto ID with a -100 prefix
+const BOT_API_CHANNEL_OFFSET: i64 = 1_000_000_000_000;
+
+/// Signs the active account in as a bot. The account must not already be signed in
+/// with a phone number, so the bot always gets a session of its own.
+#[tauri::command]
+pub async fn cmd_auth_bot_sign_in(
+    app_handle: tauri::AppHandle,
+    api_id: i32,
+    api_hash: String,
+    bot_token: String,
+    state: State<'_, TelegramState>,
+    drive: State<'_, BotDrive>,
+) -> Result<AuthResult, DriveError> {
+    if api_hash.trim().is_empty() || bot_token.trim().is_empty() {
+        return Err("API Hash and bot token are required.".into());
+    }
+
+    *state.api_id.lock().await = Some(api_id);
+    let client = ensure_client_initialized(&app_handle, &state, api_id).await?;
+
+    if !drive.is_bot() && client.is_authorized().await? {
+        return Err("This account is already signed in with a phone number. Add a new account for the bot.".into());
+    }
+
+    client.bot_sign_in(bot_token.trim(), &api_hash).await.map_err(|e| match DriveError::from(e) {
+        DriveError::Telegram { name, .. } => DriveError::Other(format!("Bot sign in failed: {}", name)),
+        other => other,
+    })?;
+    drive.enable();
+    log::info!("Signed in as bot.");
+    checkpoint_session(&app_handle);
+
+    Ok(AuthResult {
+        success: true,
+        next_step: Some("dashboard".to_string()),
+        error: None,
+    })
+}
+
+/// Registers a channel where the bot is admin as a drive folder.
+/// `channel` is a @username, a t.me link or a channel ID (Bot API `-100...` IDs work too).
+#[tauri::command]
+pub async fn cmd_bot_add_folder(
+    channel: String,
+    state: State<'_, TelegramState>,
+    drive: State<'_, BotDrive>,
+) -> Result<FolderMetadata, DriveError> {
+    let client = state.client.lock().await.clone().ok_or(DriveError::NotConnected)?;
+    add_bot_folder(&client, &drive, &channel).await
+}
+
+pub async fn add_bot_folder(client: &Client, drive: &BotDrive, channel: &str) -> Result<FolderMetadata, DriveError> {
+    if !drive.is_bot() {
+        return Err("Only bot accounts register folders, regular accounts find them automatically".into());
+    }
+
+    let peer = match parse_channel_ref(channel)? {
+        ChannelRef::Id(id) => channel_peer(client, id, 0).await?,
+        ChannelRef::Username(name) => client.resolve_username(&name).await?
+            .ok_or_else(|| DriveError::NotFound(format!("@{} not found", name)))?,
+    };
+    let Peer::Channel(c) = peer else {
+        return Err(DriveError::Other(format!("{} is not a channel", channel)));
+    };
+    if !c.raw.creator && c.raw.admin_rights.is_none() {
+        return Err(DriveError::Other(format!("The bot is not an admin of '{}'", c.raw.title)));
+    }
+
+    let name = c.raw.title.replace("[TD]", "").trim().to_string();
+    drive.add_folder(c.raw.id, c.raw.access_hash.unwrap_or(0), name.clone());
+    log::info!("Registered bot folder '{}' ({})", name, c.raw.id);
+
+    Ok(FolderMetadata { id: c.raw.id, name, parent_id: None })
+}
+
+enum ChannelRef {
+    Id(i64),
+    Username(String),
+}
+
+fn parse_channel_ref(input: &str) -> Result<ChannelRef, DriveError> {
+    let input = input.trim();
+    let path = input
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("t.me/")
+        .trim_start_matches("telegram.me/");
+
+    // Private message links look like t.me/c/<channel id>/<message id>
+    let candidate = match path.strip_prefix("c/") {
+        Some(rest) => rest.split('/').next().unwrap_or_default(),
+        None => path.split('/').next().unwrap_or_default(),
+    };
+
+    if let Ok(id) = candidate.parse::<i64>() {
+        let id = if id < -BOT_API_CHANNEL_OFFSET { -id - BOT_API_CHANNEL_OFFSET } else { id.abs() };
+        return Ok(ChannelRef::Id(id));
+    }
+
+    let username = candidate.trim_start_matches('@');
+    let valid = username.len() >= 4 && username.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(DriveError::Other(format!("'{}' is not a channel username, link or ID", input)));
+    }
+    Ok(ChannelRef::Username(username.to_string()))
+}
@@ -1,17 +1,37 @@
-use tauri::State;
-use grammers_client::types::{Media, Peer};
+use std::path::{Path, PathBuf};
+use tauri::{Emitter, State};
+use grammers_client::Client;
+use grammers_client::types::{Media, Message, Peer};
 use grammers_client::InputMessage;
 use grammers_tl_types as tl;
 use crate::TelegramState;
-use crate::models::{FolderMetadata, FileMetadata};
+use crate::models::{FolderMetadata, FileMetadata, SavedFile};
 use crate::bandwidth::BandwidthManager;
-use crate::commands::utils::{resolve_peer, map_error};
+use crate::bot::BotDrive;
+use crate::download::{FileSource, download_to_path};
+use crate::upload::{UploadProgress, upload_from_path};
+use crate::live::LiveFolders;
+use crate::messages::MessageCache;
+use crate::peers::{PeerCache, peer_id};
+use crate::transfers::TransferManager;
+use crate::paths::PathPolicy;
+use crate::filenames::{CollisionStrategy, resolve_collision, sanitize_filename};
+use crate::commands::utils::{resolve_peer, with_peer};
+use crate::error::{DriveError, with_flood_retry, with_retry};
+
+/// Largest file Telegram accepts (Premium accounts, 2000 MB otherwise)
+pub(crate) const MAX_UPLOAD_SIZE: u64 = 4000 * 1024 * 1024;
+/// Longest message caption without Premium
+const CAPTION_LIMIT: usize = 1024;
 
 #[tauri::command]
 pub async fn cmd_create_folder(
     name: String,
     state: State<'_, TelegramState>,
-) -> Result<FolderMetadata, String> {
+    drive: State<'_, BotDrive>,
+    peers: State<'_, PeerCache>,
+    live: State<'_, LiveFolders>,
+) -> Result<FolderMetadata, DriveError> {
     let client_opt = {
         state.client.lock().await.clone()
     };
@@ -28,9 +48,18 @@ pub async fn cmd_create_folder(
     }
     // -----------
     let client = client_opt.unwrap();
+    let folder = create_folder(&client, &drive, &peers, name).await?;
+    live.add_folder(folder.id);
+    Ok(folder)
+}
+
+pub async fn create_folder(client: &Client, drive: &BotDrive, peers: &PeerCache, name: String) -> Result<FolderMetadata, DriveError> {
+    if drive.is_bot() {
+        return Err("Bots cannot create channels. Create one in Telegram, add the bot as an admin and register it as a folder.".into());
+    }
     log::info!("Creating Telegram Channel: {}", name);
     
-    let result = client.invoke(&tl::functions::channels::CreateChannel {
+    let request = tl::functions::channels::CreateChannel {
         broadcast: true,
         megagroup: false,
         title: format!("{} [TD]", name),
@@ -40,17 +69,20 @@ pub async fn cmd_create_folder(
         for_import: false,
         forum: false,
         ttl_period: None, // Initial creation TTL
-    }).await.map_err(map_error)?;
+    };
+    let result = with_flood_retry("channels.createChannel", || client.invoke(&request)).await?;
     
     let (chat_id, access_hash) = match result {
         tl::enums::Updates::Updates(u) => {
              let chat = u.chats.first().ok_or("No chat in updates")?;
-             match chat {
+             let ids = match chat {
                  tl::enums::Chat::Channel(c) => (c.id, c.access_hash.unwrap_or(0)),
-                 _ => return Err("Created chat is not a channel".to_string()),
-             }
+                 _ => return Err("Created chat is not a channel".into()),
+             };
+             peers.remember(&Peer::from_raw(chat.clone()));
+             ids
         },
-        _ => return Err("Unexpected response (not Updates::Updates)".to_string()), 
+        _ => return Err("Unexpected response (not Updates::Updates)".into()), 
     };
 
     // Explicitly Disable TTL
@@ -75,7 +107,10 @@ pub async fn cmd_create_folder(
 pub async fn cmd_delete_folder(
     folder_id: i64,
     state: State<'_, TelegramState>,
-) -> Result<bool, String> {
+    drive: State<'_, BotDrive>,
+    peers: State<'_, PeerCache>,
+    live: State<'_, LiveFolders>,
+) -> Result<bool, DriveError> {
     let client_opt = {
         state.client.lock().await.clone()
     };
@@ -85,24 +120,40 @@ pub async fn cmd_delete_folder(
         return Ok(true);
     }
     let client = client_opt.unwrap();
+    let deleted = delete_folder(&client, &drive, &peers, folder_id).await?;
+    live.remove_folder(folder_id);
+    Ok(deleted)
+}
+
+pub async fn delete_folder(client: &Client, drive: &BotDrive, peers: &PeerCache, folder_id: i64) -> Result<bool, DriveError> {
+    // The bot does not own the channel, so only forget it
+    if drive.is_bot() {
+        log::info!("Unregistering bot folder: {}", folder_id);
+        drive.remove_folder(folder_id);
+        return Ok(true);
+    }
     log::info!("Deleting folder/channel: {}", folder_id);
 
-    let peer = resolve_peer(&client, Some(folder_id)).await?;
-    
-    let input_channel = match peer {
-        Peer::Channel(c) => {
-             let chan = &c.raw;
-             tl::enums::InputChannel::Channel(tl::types::InputChannel {
-                 channel_id: chan.id,
-                 access_hash: chan.access_hash.ok_or("No access hash for channel")?,
-             })
+    let input_channel = match peers.input_channel(folder_id) {
+        Some(input_channel) => input_channel,
+        None => match resolve_peer(client, drive, peers, Some(folder_id)).await? {
+            Peer::Channel(c) => {
+                 let chan = &c.raw;
+                 tl::enums::InputChannel::Channel(tl::types::InputChannel {
+                     channel_id: chan.id,
+                     access_hash: chan.access_hash.ok_or("No access hash for channel")?,
+                 })
+            },
+            _ => return Err("Only channels (folders) can be deleted.".into()),
         },
-        _ => return Err("Only channels (folders) can be deleted.".to_string()),
     };
     
-    client.invoke(&tl::functions::channels::DeleteChannel {
-        channel: input_channel,
-    }).await.map_err(|e| format!("Failed to delete channel: {}", e))?;
+    let request = tl::functions::channels::DeleteChannel { channel: input_channel };
+    let result = with_retry("channels.deleteChannel", || client.invoke(&request)).await;
+    if matches!(result, Ok(_) | Err(DriveError::NotFound(_) | DriveError::ChannelUnavailable { .. })) {
+        peers.invalidate(folder_id);
+    }
+    result?;
     
     Ok(true)
 }
@@ -112,96 +163,198 @@ pub async fn cmd_delete_folder(
 pub async fn cmd_upload_file(
     path: String,
     folder_id: Option<i64>,
+    app_handle: tauri::AppHandle,
     state: State<'_, TelegramState>,
     bw_state: State<'_, BandwidthManager>,
-) -> Result<String, String> {
-    let size = std::fs::metadata(&path).map_err(|e| e.to_string())?.len();
-    bw_state.can_transfer(size)?;
-
+    drive: State<'_, BotDrive>,
+    peers: State<'_, PeerCache>,
+    transfers: State<'_, TransferManager>,
+    policy: State<'_, PathPolicy>,
+) -> Result<String, DriveError> {
+    // Opened by its resolved path, so a symlink swapped in after the check is not followed
+    let resolved = policy.readable(&path)?;
     let client_opt = { state.client.lock().await.clone() };
     if client_opt.is_none() {
+        let size = std::fs::metadata(&resolved)?.len();
+        bw_state.can_transfer(size)?;
         log::info!("[MOCK] Uploaded file {} to {:?}", path, folder_id);
         bw_state.add_up(size);
         return Ok("Mock upload successful".to_string());
     }
     let client = client_opt.unwrap();
-    
-    let path_clone = path.clone();
-    let client_clone = client.clone();
-    
-    let uploaded_file = tauri::async_runtime::spawn(async move {
-        client_clone.upload_file(&path_clone).await
-    }).await.map_err(|e| format!("Task join error: {}", e))?
-      .map_err(map_error)?;
-        
-    let message = InputMessage::new().text("").file(uploaded_file);
-
-    let peer = resolve_peer(&client, folder_id).await?;
-    
-    client.send_message(&peer, message).await.map_err(map_error)?;
-    
-    bw_state.add_up(size);
+    upload_file(&client, &bw_state, &drive, &peers, &transfers, &resolved, folder_id, |progress| {
+        let _ = app_handle.emit("upload-progress", progress);
+    }).await?;
     Ok("File uploaded successfully".to_string())
 }
 
+/// Uploads a local file to `folder_id`, returning the ID of the message that holds it
+pub async fn upload_file(
+    client: &Client,
+    bw_state: &BandwidthManager,
+    drive: &BotDrive,
+    peers: &PeerCache,
+    transfers: &TransferManager,
+    path: &Path,
+    folder_id: Option<i64>,
+    on_progress: impl Fn(UploadProgress),
+) -> Result<i32, DriveError> {
+    upload_file_with_caption(client, bw_state, drive, peers, transfers, path, "", folder_id, on_progress).await
+}
+
+/// Like `upload_file`, with `caption` as the text of the message.
+/// Captions are cut to the last `CAPTION_LIMIT` characters, which Telegram accepts for every account.
+pub async fn upload_file_with_caption(
+    client: &Client,
+    bw_state: &BandwidthManager,
+    drive: &BotDrive,
+    peers: &PeerCache,
+    transfers: &TransferManager,
+    path: &Path,
+    caption: &str,
+    folder_id: Option<i64>,
+    on_progress: impl Fn(UploadProgress),
+) -> Result<i32, DriveError> {
+    let size = std::fs::metadata(path)?.len();
+    if size > MAX_UPLOAD_SIZE {
+        return Err(DriveError::FileTooLarge { size, limit: MAX_UPLOAD_SIZE });
+    }
+    bw_state.can_transfer(size)?;
+
+    let uploaded_file = upload_from_path(client, bw_state, path, transfers.upload_parallelism(), on_progress).await?;
+    let skip = caption.chars().count().saturating_sub(CAPTION_LIMIT);
+    let message = InputMessage::new().text(caption.chars().skip(skip).collect::<String>()).file(uploaded_file);
+
+    // A rejected send is safe to repeat, so a stale chat is resolved again and retried
+    let sent = with_peer(client, drive, peers, folder_id, |peer| {
+        let message = message.clone();
+        async move { with_flood_retry("messages.sendMedia", || client.send_message(&peer, message.clone())).await }
+    }).await?;
+    drive.track(folder_id, &[sent.id()]);
+    
+    Ok(sent.id())
+}
+
 #[tauri::command]
 pub async fn cmd_delete_file(
     message_id: i32,
     folder_id: Option<i64>,
     state: State<'_, TelegramState>,
-) -> Result<bool, String> {
+    drive: State<'_, BotDrive>,
+    peers: State<'_, PeerCache>,
+    messages: State<'_, MessageCache>,
+) -> Result<bool, DriveError> {
     let client_opt = { state.client.lock().await.clone() };
     if client_opt.is_none() { 
          log::info!("[MOCK] Deleted message {} from folder {:?}", message_id, folder_id);
         return Ok(true); 
     }
     let client = client_opt.unwrap();
+    delete_files(&client, &drive, &peers, &messages, &[message_id], folder_id).await
+}
 
-    let peer = resolve_peer(&client, folder_id).await?;
-    client.delete_messages(&peer, &[message_id]).await.map_err(|e| e.to_string())?;
+pub async fn delete_files(client: &Client, drive: &BotDrive, peers: &PeerCache, messages: &MessageCache, message_ids: &[i32], folder_id: Option<i64>) -> Result<bool, DriveError> {
+    let peer = with_peer(client, drive, peers, folder_id, |peer| async move {
+        with_retry("messages.deleteMessages", || client.delete_messages(&peer, message_ids)).await?;
+        Ok(peer)
+    }).await?;
+    messages.forget(&peer, message_ids);
+    drive.untrack(folder_id, message_ids);
     Ok(true)
 }
 
+/// Where `download_file` should write a file
+pub enum SaveTarget {
+    /// Exactly this path, e.g. from a save dialog, which already asked before replacing a file.
+    /// Only `Skip` applies to it, the path is never renamed.
+    Path(PathBuf),
+    /// Inside this directory, under the file's (sanitized) name on Telegram
+    Dir(PathBuf),
+}
+
+impl SaveTarget {
+    /// The path before collisions are handled
+    pub fn path(&self, name: &str) -> PathBuf {
+        match self {
+            SaveTarget::Path(path) => path.clone(),
+            SaveTarget::Dir(dir) => dir.join(sanitize_filename(name)),
+        }
+    }
+
+    /// Where the file called `name` on Telegram is written, `None` if it exists and is skipped
+    pub fn path_for(&self, name: &str, collision: CollisionStrategy) -> Option<PathBuf> {
+        let path = self.path(name);
+        match self {
+            // The user picked this path and the policy approved exactly it, so it is not renamed
+            SaveTarget::Path(_) => (collision != CollisionStrategy::Skip || !path.exists()).then_some(path),
+            SaveTarget::Dir(_) => resolve_collision(&path, collision),
+        }
+    }
+}
+
 #[tauri::command]
 pub async fn cmd_download_file(
     message_id: i32,
-    save_path: String,
+    save_path: Option<String>,
+    save_dir: Option<String>,
     folder_id: Option<i64>,
+    collision: Option<CollisionStrategy>,
     state: State<'_, TelegramState>,
     bw_state: State<'_, BandwidthManager>,
-) -> Result<String, String> {
+    drive: State<'_, BotDrive>,
+    peers: State<'_, PeerCache>,
+    messages: State<'_, MessageCache>,
+    transfers: State<'_, TransferManager>,
+    policy: State<'_, PathPolicy>,
+) -> Result<SavedFile, DriveError> {
+    // Save dialogs already ask before replacing a file
+    let collision = collision.unwrap_or(CollisionStrategy::Overwrite);
+    let target = match (save_path, save_dir) {
+        (Some(path), _) => SaveTarget::Path(policy.writable(&path)?),
+        (None, Some(dir)) => SaveTarget::Dir(policy.writable(&dir)?),
+        (None, None) => return Err(DriveError::Other("No download destination given".to_string())),
+    };
     let client_opt = { state.client.lock().await.clone() };
     if client_opt.is_none() { 
-        log::info!("[MOCK] Downloaded message {} from {:?} to {}", message_id, folder_id, save_path);
-        if let Err(e) = std::fs::write(&save_path, b"Mock Content") { return Err(e.to_string()); }
-        return Ok("Download successful".to_string());
+        let path = match target {
+            SaveTarget::Path(path) => path,
+            SaveTarget::Dir(dir) => dir.join(format!("mock_{}.bin", message_id)),
+        };
+        log::info!("[MOCK] Downloaded message {} from {:?} to {}", message_id, folder_id, path.display());
+        std::fs::write(&path, b"Mock Content")?;
+        return Ok(SavedFile { path: path.to_string_lossy().to_string(), skipped: false });
     }
     let client = client_opt.unwrap();
-    
-    let peer = resolve_peer(&client, folder_id).await?;
-    let mut msgs = client.iter_messages(&peer);
-    
-    let mut target_message = None;
-    while let Some(m) = msgs.next().await.map_err(|e| e.to_string())? { 
-        if m.id() == message_id { target_message = Some(m); break; } 
-    }
+    download_file(&client, &bw_state, &drive, &peers, &messages, &transfers, message_id, target, folder_id, collision).await
+}
 
-    if let Some(msg) = target_message {
-        if let Some(media) = msg.media() {
-            let size = match &media {
-                Media::Document(d) => d.size() as u64,
-                Media::Photo(_) => 1024 * 1024,
-                _ => 0,
+pub async fn download_file(
+    client: &Client,
+    bw_state: &BandwidthManager,
+    drive: &BotDrive,
+    peers: &PeerCache,
+    messages: &MessageCache,
+    transfers: &TransferManager,
+    message_id: i32,
+    target: SaveTarget,
+    folder_id: Option<i64>,
+    collision: CollisionStrategy,
+) -> Result<SavedFile, DriveError> {
+    let peer = resolve_peer(client, drive, peers, folder_id).await?;
+    
+    if let Some(msg) = peers.check(&peer, messages.get(client, &peer, message_id).await)? {
+        if let (Some(media), Some(meta)) = (msg.media(), file_metadata(&msg, folder_id)) {
+            let Some(path) = target.path_for(&meta.name, collision) else {
+                let path = target.path(&meta.name);
+                log::info!("Skipped message {}, {} already exists", message_id, path.display());
+                return Ok(SavedFile { path: path.to_string_lossy().to_string(), skipped: true });
             };
-            
-            bw_state.can_transfer(size)?;
-
-            client.download_media(&media, &save_path).await.map_err(map_error)?;
-            bw_state.add_down(size);
-            return Ok("Download successful".to_string());
+            let source = FileSource { peer: &peer, messages, message_id };
+            download_to_path(client, bw_state, &media, &source, &path, transfers.download_parallelism()).await?;
+            return Ok(SavedFile { path: path.to_string_lossy().to_string(), skipped: false });
         }
     }
-    Err("Not found".to_string())
+    Err(DriveError::NotFound(format!("File {} not found", message_id)))
 }
 
 #[tauri::command]
@@ -210,7 +363,10 @@ pub async fn cmd_move_files(
     source_folder_id: Option<i64>,
     target_folder_id: Option<i64>,
     state: State<'_, TelegramState>,
-) -> Result<bool, String> {
+    drive: State<'_, BotDrive>,
+    peers: State<'_, PeerCache>,
+    messages: State<'_, MessageCache>,
+) -> Result<bool, DriveError> {
     if source_folder_id == target_folder_id { return Ok(true); }
     let client_opt = { state.client.lock().await.clone() };
     if client_opt.is_none() { 
@@ -218,19 +374,46 @@ pub async fn cmd_move_files(
         return Ok(true); 
     }
     let client = client_opt.unwrap();
+    move_files(&client, &drive, &peers, &messages, &message_ids, source_folder_id, target_folder_id).await
+}
 
-    let source_peer = resolve_peer(&client, source_folder_id).await?;
-    let target_peer = resolve_peer(&client, target_folder_id).await?;
+pub async fn move_files(
+    client: &Client,
+    drive: &BotDrive,
+    peers: &PeerCache,
+    messages: &MessageCache,
+    message_ids: &[i32],
+    source_folder_id: Option<i64>,
+    target_folder_id: Option<i64>,
+) -> Result<bool, DriveError> {
+    if source_folder_id == target_folder_id { return Ok(true); }
+    let mut source_peer = resolve_peer(client, drive, peers, source_folder_id).await?;
+    let mut target_peer = resolve_peer(client, drive, peers, target_folder_id).await?;
 
-    match client.forward_messages(&target_peer, &message_ids, &source_peer).await {
-        Ok(_) => {},
-        Err(e) => return Err(format!("Forward failed: {}", e)),
-    }
+    let forward = |source_peer: Peer, target_peer: Peer| async move {
+        with_flood_retry("messages.forwardMessages", || {
+            client.forward_messages(&target_peer, message_ids, &source_peer)
+        }).await
+    };
+    let forwarded = match forward(source_peer.clone(), target_peer.clone()).await {
+        // Either chat may be the stale one, so both are dropped and resolved again
+        Err(e) if e.is_channel_unavailable() => {
+            log::info!("Retrying the move with both folders resolved again ({})", e);
+            for id in [&source_peer, &target_peer].into_iter().filter_map(peer_id) {
+                peers.invalidate(id);
+            }
+            source_peer = resolve_peer(client, drive, peers, source_folder_id).await?;
+            target_peer = resolve_peer(client, drive, peers, target_folder_id).await?;
+            peers.check(&target_peer, forward(source_peer.clone(), target_peer.clone()).await)?
+        }
+        result => result?,
+    };
+    let new_ids: Vec<i32> = forwarded.iter().flatten().map(|m| m.id()).collect();
+    drive.track(target_folder_id, &new_ids);
     
-    match client.delete_messages(&source_peer, &message_ids).await {
-        Ok(_) => {},
-        Err(e) => return Err(format!("Delete original failed: {}", e)),
-    }
+    peers.check(&source_peer, with_retry("messages.deleteMessages", || client.delete_messages(&source_peer, message_ids)).await)?;
+    messages.forget(&source_peer, message_ids);
+    drive.untrack(source_folder_id, message_ids);
 
     Ok(true)
 }
@@ -239,35 +422,50 @@ pub async fn cmd_move_files(
 pub async fn cmd_get_files(
     folder_id: Option<i64>,
     state: State<'_, TelegramState>,
-) -> Result<Vec<FileMetadata>, String> {
+    drive: State<'_, BotDrive>,
+    peers: State<'_, PeerCache>,
+    messages: State<'_, MessageCache>,
+    live: State<'_, LiveFolders>,
+) -> Result<Vec<FileMetadata>, DriveError> {
     let client_opt = { state.client.lock().await.clone() };
     if client_opt.is_none() { 
         log::info!("[MOCK] Returning mock files for folder {:?}", folder_id);
         return Ok(Vec::new()); // No mock files for now
     }
     let client = client_opt.unwrap();
+    let files = list_files(&client, &drive, &peers, &messages, folder_id).await?;
+    if folder_id.is_none() {
+        live.set_saved_files(files.iter().map(|f| f.id as i32));
+    }
+    Ok(files)
+}
+
+pub async fn list_files(client: &Client, drive: &BotDrive, peers: &PeerCache, messages: &MessageCache, folder_id: Option<i64>) -> Result<Vec<FileMetadata>, DriveError> {
     let mut files = Vec::new();
     
-    let peer = resolve_peer(&client, folder_id).await?;
+    let peer = resolve_peer(client, drive, peers, folder_id).await?;
+
+    // Bots cannot read history, so fetch the messages the index knows about (newest first)
+    if drive.is_bot() {
+        let mut ids = drive.message_ids(folder_id.unwrap_or_default());
+        ids.reverse();
+        let mut gone = Vec::new();
+        for (id, msg) in ids.iter().zip(peers.check(&peer, messages.get_many(client, &peer, &ids).await)?) {
+            match msg {
+                Some(msg) => files.extend(file_metadata(&msg, folder_id)),
+                None => gone.push(*id),
+            }
+        }
+        // Deleted from Telegram by someone else
+        drive.untrack(folder_id, &gone);
+        return Ok(files);
+    }
 
     let mut msgs = client.iter_messages(&peer);
     let mut count = 0;
-    while let Some(msg) = msgs.next().await.map_err(|e| e.to_string())? {
-        if let Some(doc) = msg.media() {
-                let (name, size, mime, ext) = match doc {
-                    Media::Document(d) => {
-                            let n = d.name().to_string();
-                            let s = d.size();
-                            let m = d.mime_type().map(|s| s.to_string());
-                            let e = std::path::Path::new(&n).extension().map(|os| os.to_str().unwrap_or("").to_string());
-                            (n, s, m, e)
-                    },
-                    Media::Photo(_) => ("Photo.jpg".to_string(), 0, Some("image/jpeg".into()), Some("jpg".into())),
-                    _ => ("Unknown".to_string(), 0, None, None),
-                };
-                files.push(FileMetadata {
-                    id: msg.id() as i64, folder_id, name, size: size as u64, mime_type: mime, file_ext: ext, created_at: msg.date().to_string(), icon_type: "file".into()
-                });
+    while let Some(msg) = peers.check(&peer, msgs.next().await.map_err(DriveError::from))? {
+        if let Some(file) = file_metadata(&msg, folder_id) {
+                files.push(file);
                 count += 1;
         }
         if count > 100 { break; }
@@ -276,21 +474,56 @@ pub async fn cmd_get_files(
     Ok(files)
 }
 
+pub(crate) fn file_metadata(msg: &Message, folder_id: Option<i64>) -> Option<FileMetadata> {
+    let doc = msg.media()?;
+    let (name, size, mime, ext) = match doc {
+        Media::Document(d) => {
+                let n = d.name().to_string();
+                let s = d.size();
+                let m = d.mime_type().map(|s| s.to_string());
+                let e = std::path::Path::new(&n).extension().map(|os| os.to_str().unwrap_or("").to_string());
+                (n, s, m, e)
+        },
+        Media::Photo(_) => ("Photo.jpg".to_string(), 0, Some("image/jpeg".into()), Some("jpg".into())),
+        _ => ("Unknown".to_string(), 0, None, None),
+    };
+    Some(FileMetadata {
+        id: msg.id() as i64, folder_id, name, size: size as u64, mime_type: mime, file_ext: ext, created_at: msg.date().to_string(), icon_type: "file".into()
+    })
+}
+
 #[tauri::command]
 pub async fn cmd_search_global(
     query: String,
     state: State<'_, TelegramState>,
-) -> Result<Vec<FileMetadata>, String> {
+    drive: State<'_, BotDrive>,
+    peers: State<'_, PeerCache>,
+    messages: State<'_, MessageCache>,
+) -> Result<Vec<FileMetadata>, DriveError> {
     let client_opt = { state.client.lock().await.clone() };
     if client_opt.is_none() { 
         return Ok(Vec::new());
     }
     let client = client_opt.unwrap();
+    search_global(&client, &drive, &peers, &messages, query).await
+}
+
+pub async fn search_global(client: &Client, drive: &BotDrive, peers: &PeerCache, messages: &MessageCache, query: String) -> Result<Vec<FileMetadata>, DriveError> {
     let mut files = Vec::new();
     
     log::info!("Searching global for: {}", query);
 
-    let result = client.invoke(&tl::functions::messages::SearchGlobal {
+    // messages.searchGlobal is not available to bots; match names across the indexed folders instead
+    if drive.is_bot() {
+        let needle = query.to_lowercase();
+        for folder in drive.folders() {
+            let listed = list_files(client, drive, peers, messages, Some(folder.id)).await?;
+            files.extend(listed.into_iter().filter(|f| f.name.to_lowercase().contains(&needle)));
+        }
+        return Ok(files);
+    }
+
+    let request = tl::functions::messages::SearchGlobal {
         q: query,
         filter: tl::enums::MessagesFilter::InputMessagesFilterDocument,
         min_date: 0,
@@ -303,7 +536,8 @@ pub async fn cmd_search_global(
         broadcasts_only: false,
         groups_only: false,
         users_only: false,
-    }).await.map_err(map_error)?;
+    };
+    let result = with_retry("messages.searchGlobal", || client.invoke(&request)).await?;
 
     if let tl::enums::messages::Messages::Messages(msgs) = result {
         for msg in msgs.messages {
@@ -365,19 +599,35 @@ pub async fn cmd_search_global(
 #[tauri::command]
 pub async fn cmd_scan_folders(
     state: State<'_, TelegramState>,
-) -> Result<Vec<FolderMetadata>, String> {
+    drive: State<'_, BotDrive>,
+    peers: State<'_, PeerCache>,
+    live: State<'_, LiveFolders>,
+) -> Result<Vec<FolderMetadata>, DriveError> {
     let client_opt = { state.client.lock().await.clone() };
     if client_opt.is_none() { 
         return Ok(Vec::new());
     }
     let client = client_opt.unwrap();
-    
+    let folders = scan_folders(&client, &drive, &peers).await?;
+    live.set_folders(&folders);
+    Ok(folders)
+}
+
+pub async fn scan_folders(client: &Client, drive: &BotDrive, peers: &PeerCache) -> Result<Vec<FolderMetadata>, DriveError> {
+    if drive.is_bot() {
+        return Ok(drive.folders().into_iter()
+            .map(|f| FolderMetadata { id: f.id, name: f.name, parent_id: None })
+            .collect());
+    }
+
     let mut folders = Vec::new();
+    let mut seen = Vec::new();
     let mut dialogs = client.iter_dialogs();
     
     log::info!("Starting Folder Scan...");
 
-    while let Some(dialog) = dialogs.next().await.map_err(|e| e.to_string())? {
+    while let Some(dialog) = dialogs.next().await? {
+        seen.push(dialog.peer.clone());
         match &dialog.peer {
             Peer::Channel(c) => {
                 let id = c.raw.id;
@@ -420,6 +670,54 @@ pub async fn cmd_scan_folders(
         }
     }
     
+    // A full scan sees every chat, so later folder lookups need no dialog walk
+    peers.remember_all(&seen);
     log::info!("Scan complete. Found {} folders.", folders.len());
     Ok(folders)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("telegram-drive-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::canonicalize(dir).unwrap()
+    }
+
+    #[test]
+    fn picked_name_with_reserved_characters_is_saved_as_is() {
+        let dir = temp_dir("picked-name");
+        let picked = dir.join(" report: Q1? <final>*|\"draft\"..");
+        let policy = PathPolicy::load(&dir);
+        policy.grant_save(&picked).unwrap();
+
+        let path = policy.writable(&picked.to_string_lossy()).unwrap();
+        let target = SaveTarget::Path(path);
+        assert_eq!(target.path_for("other name.txt", CollisionStrategy::Overwrite), Some(picked.clone()));
+    }
+
+    #[test]
+    fn picked_path_is_never_renamed() {
+        let dir = temp_dir("picked-rename");
+        let picked = dir.join("a:b.txt");
+        std::fs::write(&picked, b"old").unwrap();
+        let target = SaveTarget::Path(picked.clone());
+        assert_eq!(target.path_for("a.txt", CollisionStrategy::Rename), Some(picked.clone()));
+        assert_eq!(target.path_for("a.txt", CollisionStrategy::Overwrite), Some(picked));
+        assert_eq!(target.path_for("a.txt", CollisionStrategy::Skip), None);
+    }
+
+    #[test]
+    fn telegram_names_are_sanitized_and_numbered() {
+        let dir = temp_dir("telegram-name");
+        let target = SaveTarget::Dir(dir.clone());
+        assert_eq!(target.path_for("a:b?.txt", CollisionStrategy::Rename), Some(dir.join("a_b_.txt")));
+
+        std::fs::write(dir.join("a_b_.txt"), b"old").unwrap();
+        assert_eq!(target.path_for("a:b?.txt", CollisionStrategy::Rename), Some(dir.join("a_b_ (1).txt")));
+        assert_eq!(target.path_for("a:b?.txt", CollisionStrategy::Skip), None);
+    }
+}
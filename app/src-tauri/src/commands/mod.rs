@@ -1,7 +1,15 @@
+use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use grammers_client::{Client};
 use grammers_client::types::{LoginToken, PasswordToken};
+use grammers_client::update::Update;
+use grammers_session::storages::SqliteSession;
+use crate::bandwidth::BandwidthManager;
+use crate::bot::BotDrive;
+use crate::messages::MessageCache;
+use crate::peers::PeerCache;
 
 /// Tracks the lifecycle of the Telegram connection
 /// 
@@ -18,16 +26,99 @@ pub struct TelegramState {
     pub runner_shutdown: Arc<Mutex<Option<tokio::sync::oneshot::Sender<()>>>>,
     /// Counter for debugging runner lifecycle
     pub runner_count: Arc<std::sync::atomic::AtomicU32>,
+    /// Still-connected clients of inactive accounts, keyed by account name, so switching back is instant
+    pub parked: Arc<Mutex<HashMap<String, ParkedClient>>>,
+    /// Session backing the active client, needed when a login moves the account to another DC
+    pub session: Arc<Mutex<Option<Arc<SqliteSession>>>>,
+    /// Updates from every connected client, tagged with their account
+    pub updates: tokio::sync::broadcast::Sender<AccountUpdate>,
+    /// Background task driving a QR-code login, if one is running
+    pub qr_login: Arc<Mutex<Option<tauri::async_runtime::JoinHandle<()>>>>,
 }
 
+/// An update received by the client of `account`
+#[derive(Clone)]
+pub struct AccountUpdate {
+    pub account: String,
+    pub update: Arc<Update>,
+}
+
+/// A connected client put aside while another account is active.
+/// Its runner keeps going until `runner_shutdown` is used.
+pub struct ParkedClient {
+    pub client: Client,
+    pub session: Option<Arc<SqliteSession>>,
+    pub api_id: i32,
+    pub runner_shutdown: tokio::sync::oneshot::Sender<()>,
+    /// The managed caches follow the active account, so the parked one gets its own
+    pub caches: Arc<AccountCaches>,
+}
+
+/// Caches and counters of a parked account, loaded from its directory when it is parked.
+/// Streams served for it use these instead of the active account's.
+pub struct AccountCaches {
+    pub peers: PeerCache,
+    pub messages: MessageCache,
+    /// Folders a bot account registered, so its streams find them
+    pub drive: BotDrive,
+    /// Streams count against the streaming account's daily limit
+    pub bandwidth: BandwidthManager,
+}
+
+impl AccountCaches {
+    pub fn load(dir: &Path) -> Self {
+        Self {
+            peers: PeerCache::load(dir),
+            messages: MessageCache::new(),
+            drive: BotDrive::load(dir),
+            bandwidth: BandwidthManager::load(dir),
+        }
+    }
+}
+
+impl TelegramState {
+    /// Client for `account`, whether it is the active one or parked in the background.
+    /// Parked accounts come with their own caches; the active one uses the managed state.
+    pub async fn client_for(&self, account: &str, active_account: Option<&str>) -> Option<(Client, Option<Arc<AccountCaches>>)> {
+        if active_account == Some(account) {
+            return self.client.lock().await.clone().map(|client| (client, None));
+        }
+        self.parked.lock().await.get(account).map(|p| (p.client.clone(), Some(p.caches.clone())))
+    }
+}
+
+pub mod accounts;
+pub mod archive;
+pub mod backups;
 pub mod auth;
+pub mod bot;
 pub mod fs;
 pub mod preview;
+pub mod qr;
+pub mod restic;
+pub mod snapshots;
 pub mod utils;
 pub mod network;
+pub mod paths;
+pub mod stream;
+pub mod tree;
+pub mod vault;
+pub mod watch;
 
+pub use accounts::*;
+pub use archive::*;
+pub use backups::*;
 pub use auth::*;
+pub use bot::*;
 pub use fs::*;
 pub use preview::*;
+pub use qr::*;
+pub use restic::*;
+pub use snapshots::*;
 pub use utils::*;
 pub use network::*;
+pub use paths::*;
+pub use stream::*;
+pub use tree::*;
+pub use vault::*;
+pub use watch::*;
@@ -1,24 +1,72 @@
-use std::net::TcpStream;
 use std::time::Duration;
+use tauri::State;
+use crate::TelegramState;
+use crate::commands::ensure_client_initialized;
+use crate::error::DriveError;
+use crate::proxy::{self, ProxyConfig, ProxyManager};
+use crate::transfers::{TransferManager, TransferSettings};
+
+/// Telegram's production DC2, used as the reachability probe
+const PROBE_ADDR: &str = "149.154.167.50:443";
 
 /// Ultra-lightweight network check
 /// 
 /// Simply tries to connect to Telegram's servers without using grammers.
 /// This avoids the stack overflow bug from grammers reconnection logic.
+/// Goes through the configured proxy, so a blocked direct route is not reported as offline.
 #[tauri::command]
-pub async fn cmd_is_network_available() -> Result<bool, String> {
-    // Try to connect to Telegram's production DC
-    // Using a very short timeout to keep it lightweight
-    tokio::task::spawn_blocking(|| {
-        // Try connecting to Telegram DC2 (149.154.167.50:443)
-        match TcpStream::connect_timeout(
-            &"149.154.167.50:443".parse().unwrap(),
-            Duration::from_secs(2),
-        ) {
-            Ok(_) => Ok(true),
-            Err(_) => Ok(false),
+pub async fn cmd_is_network_available(
+    proxy_state: State<'_, ProxyManager>,
+) -> Result<bool, DriveError> {
+    let config = proxy_state.config();
+    // Using a very short timeout to keep it lightweight (a proxy handshake needs a little longer)
+    let timeout = if config == ProxyConfig::None { Duration::from_secs(2) } else { Duration::from_secs(5) };
+
+    match tokio::time::timeout(timeout, proxy::connect(&config, PROBE_ADDR)).await {
+        Ok(Ok(_)) => Ok(true),
+        Ok(Err(e)) => {
+            log::debug!("Network check failed: {}", e);
+            Ok(false)
         }
-    })
-    .await
-    .map_err(|e| e.to_string())?
+        Err(_) => Ok(false),
+    }
+}
+
+#[tauri::command]
+pub fn cmd_get_proxy(proxy_state: State<'_, ProxyManager>) -> ProxyConfig {
+    proxy_state.config()
+}
+
+/// Saves the proxy settings and reconnects the active client through them
+#[tauri::command]
+pub async fn cmd_set_proxy(
+    config: ProxyConfig,
+    app_handle: tauri::AppHandle,
+    state: State<'_, TelegramState>,
+    proxy_state: State<'_, ProxyManager>,
+) -> Result<bool, DriveError> {
+    proxy_state.set_config(config).await?;
+
+    let api_id = *state.api_id.lock().await;
+    if let Some(api_id) = api_id {
+        log::info!("Proxy settings changed, reconnecting...");
+        *state.client.lock().await = None;
+        ensure_client_initialized(&app_handle, &state, api_id).await?;
+    }
+    Ok(true)
+}
+
+#[tauri::command]
+pub fn cmd_get_transfer_settings(transfers: State<'_, TransferManager>) -> TransferSettings {
+    transfers.settings()
+}
+
+/// Takes effect with the next transfer, running ones keep their parallelism
+#[tauri::command]
+pub fn cmd_set_transfer_settings(
+    settings: TransferSettings,
+    transfers: State<'_, TransferManager>,
+) -> Result<bool, DriveError> {
+    transfers.set_settings(settings)?;
+    Ok(true)
 }
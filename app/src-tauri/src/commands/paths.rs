@@ -0,0 +1,116 @@
+use std::path::{Path, PathBuf};
+use tauri::State;
+use tauri_plugin_dialog::{DialogExt, FilePath};
+use tokio::sync::oneshot;
+use crate::error::DriveError;
+use crate::paths::PathPolicy;
+
+// The webview picks local files through these instead of the dialog plugin,
+// so the backend knows which paths the user actually chose.
+
+#[tauri::command]
+pub async fn cmd_pick_files(
+    title: Option<String>,
+    app_handle: tauri::AppHandle,
+    policy: State<'_, PathPolicy>,
+) -> Result<Option<Vec<String>>, DriveError> {
+    let (tx, rx) = oneshot::channel();
+    let mut dialog = app_handle.dialog().file();
+    if let Some(title) = title {
+        dialog = dialog.set_title(title);
+    }
+    dialog.pick_files(move |picked| {
+        let _ = tx.send(picked);
+    });
+
+    let Some(picked) = rx.await.ok().flatten() else { return Ok(None) };
+    picked.into_iter()
+        .map(|p| grant(p, |path| policy.grant(path)))
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
+#[tauri::command]
+pub async fn cmd_pick_folder(
+    title: Option<String>,
+    app_handle: tauri::AppHandle,
+    policy: State<'_, PathPolicy>,
+) -> Result<Option<String>, DriveError> {
+    let (tx, rx) = oneshot::channel();
+    let mut dialog = app_handle.dialog().file();
+    if let Some(title) = title {
+        dialog = dialog.set_title(title);
+    }
+    dialog.pick_folder(move |picked| {
+        let _ = tx.send(picked);
+    });
+
+    match rx.await.ok().flatten() {
+        Some(picked) => grant(picked, |path| policy.grant_folder(path)).map(Some),
+        None => Ok(None),
+    }
+}
+
+#[tauri::command]
+pub async fn cmd_pick_save_path(
+    default_name: Option<String>,
+    title: Option<String>,
+    extensions: Option<Vec<String>>,
+    app_handle: tauri::AppHandle,
+    policy: State<'_, PathPolicy>,
+) -> Result<Option<String>, DriveError> {
+    let (tx, rx) = oneshot::channel();
+    let mut dialog = app_handle.dialog().file();
+    if let Some(name) = default_name {
+        dialog = dialog.set_file_name(name);
+    }
+    if let Some(title) = title {
+        dialog = dialog.set_title(title);
+    }
+    if let Some(extensions) = extensions.filter(|e| !e.is_empty()) {
+        let extensions: Vec<&str> = extensions.iter().map(String::as_str).collect();
+        dialog = dialog.add_filter(extensions.join(", "), &extensions);
+    }
+    dialog.save_file(move |picked| {
+        let _ = tx.send(picked);
+    });
+
+    match rx.await.ok().flatten() {
+        Some(picked) => grant(picked, |path| policy.grant_save(path)).map(Some),
+        None => Ok(None),
+    }
+}
+
+#[tauri::command]
+pub fn cmd_list_allowed_folders(policy: State<'_, PathPolicy>) -> Vec<String> {
+    policy.roots().iter().map(|p| p.to_string_lossy().to_string()).collect()
+}
+
+/// Asks the user for a folder the app may always read and write inside
+#[tauri::command]
+pub async fn cmd_add_allowed_folder(
+    app_handle: tauri::AppHandle,
+    policy: State<'_, PathPolicy>,
+) -> Result<Option<String>, DriveError> {
+    let (tx, rx) = oneshot::channel();
+    app_handle.dialog().file().set_title("Allow Telegram Drive to access a folder").pick_folder(move |picked| {
+        let _ = tx.send(picked);
+    });
+
+    let Some(picked) = rx.await.ok().flatten() else { return Ok(None) };
+    let path = picked.as_path().ok_or("The selected folder is not on this device")?;
+    let root = policy.add_root(path)?;
+    Ok(Some(root.to_string_lossy().to_string()))
+}
+
+#[tauri::command]
+pub fn cmd_remove_allowed_folder(path: String, policy: State<'_, PathPolicy>) {
+    policy.remove_root(Path::new(&path));
+}
+
+/// Records a dialog pick with `allow`, returning the path as the user saw it
+fn grant(picked: FilePath, allow: impl FnOnce(&Path) -> Result<PathBuf, DriveError>) -> Result<String, DriveError> {
+    let path = picked.as_path().ok_or("The selected file is not on this device")?;
+    allow(path)?;
+    Ok(path.to_string_lossy().to_string())
+}
@@ -4,7 +4,14 @@ use grammers_client::types::Media;
 use base64::{Engine as _, engine::general_purpose};
 use crate::TelegramState;
 use crate::bandwidth::BandwidthManager;
+use crate::bot::BotDrive;
+use crate::download::{FileSource, download_to_path};
+use crate::messages::MessageCache;
+use crate::peers::PeerCache;
+use crate::transfers::TransferManager;
 use crate::commands::utils::resolve_peer;
+use crate::error::DriveError;
+use crate::filenames::sanitize_extension;
 
 #[tauri::command]
 pub async fn cmd_get_preview(
@@ -13,9 +20,13 @@ pub async fn cmd_get_preview(
     app_handle: tauri::AppHandle,
     state: State<'_, TelegramState>,
     bw_state: State<'_, BandwidthManager>,
-) -> Result<String, String> {
+    drive: State<'_, BotDrive>,
+    peers: State<'_, PeerCache>,
+    messages: State<'_, MessageCache>,
+    transfers: State<'_, TransferManager>,
+) -> Result<String, DriveError> {
     
-    let cache_dir = app_handle.path().app_data_dir().map_err(|e: tauri::Error| e.to_string())?.join("previews");
+    let cache_dir = app_handle.path().app_data_dir().map_err(|e: tauri::Error| DriveError::Io(e.to_string()))?.join("previews");
     if !cache_dir.exists() { let _ = std::fs::create_dir_all(&cache_dir); }
     log::info!("Using preview cache dir: {:?}", cache_dir);
     log::info!("Preview Request: msg_id={}", message_id);
@@ -24,18 +35,13 @@ pub async fn cmd_get_preview(
     if client_opt.is_none() { return Ok("".to_string()); }
     let client = client_opt.unwrap();
     
-    let peer = resolve_peer(&client, folder_id).await?;
-    let mut msgs = client.iter_messages(&peer);
-    let mut target_message = None;
-    while let Some(m) = msgs.next().await.map_err(|e| e.to_string())? { 
-        if m.id() == message_id { target_message = Some(m); break; } 
-    }
-    
-    if let Some(msg) = target_message {
+    let peer = resolve_peer(&client, &drive, &peers, folder_id).await?;
+    if let Some(msg) = peers.check(&peer, messages.get(&client, &peer, message_id).await)? {
         if let Some(media) = msg.media() {
              let ext = match &media {
                  Media::Document(d) => {
-                     let mut e = std::path::Path::new(d.name()).extension().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+                     // The name comes from whoever uploaded the file, so only a plain extension is kept
+                     let mut e = std::path::Path::new(d.name()).extension().and_then(|s| sanitize_extension(&s.to_string_lossy())).unwrap_or_default();
                      if e.is_empty() {
                          if let Some(mime) = d.mime_type() {
                               e = match mime {
@@ -54,34 +60,28 @@ pub async fn cmd_get_preview(
                  _ => "bin".to_string(),
              };
              
-             let save_path = cache_dir.join(format!("{}.{}", message_id, ext));
+             let save_path = cache_dir.join(format!("{}.{}", cache_stem(folder_id, message_id), ext));
              let save_path_str = save_path.to_string_lossy().to_string();
              
              let file_ready = if save_path.exists() {
                  log::info!("File ({}) exists in cache.", message_id);
                  true
              } else {
-                 let size = match &media {
-                    Media::Document(d) => d.size() as u64,
-                    Media::Photo(_) => 1024 * 1024,
-                    _ => 0,
-                };
-                
-                log::info!("Downloading preview... Size: {}", size);
-                if let Err(e) = bw_state.can_transfer(size) {
-                    log::warn!("Bandwidth limit hit for preview: {}", e);
-                    false
-                } else {
-                    match client.download_media(&media, &save_path_str).await {
-                        Ok(_) => {
-                            log::info!("Preview download complete.");
-                            bw_state.add_down(size);
-                            true
-                        },
-                        Err(e) => {
-                            log::error!("Preview Download Error: {}", e);
-                            false
-                        }
+                log::info!("Downloading preview...");
+                // Goes through a .part file, so an interrupted preview is never mistaken for a cached one
+                let source = FileSource { peer: &peer, messages: &messages, message_id };
+                match download_to_path(&client, &bw_state, &media, &source, &save_path, transfers.download_parallelism()).await {
+                    Ok(size) => {
+                        log::info!("Preview download complete. Size: {}", size);
+                        true
+                    },
+                    Err(DriveError::BandwidthExceeded(e)) => {
+                        log::warn!("Bandwidth limit hit for preview: {}", e);
+                        false
+                    },
+                    Err(e) => {
+                        log::error!("Preview Download Error: {}", e);
+                        false
                     }
                 }
              };
@@ -115,14 +115,14 @@ pub async fn cmd_get_preview(
         }
     }
 
-    Err("File not found or failed to download".to_string())
+    Err(DriveError::NotFound("File not found or failed to download".to_string()))
 }
 
 #[tauri::command]
 pub async fn cmd_clean_cache(
     app_handle: tauri::AppHandle,
-) -> Result<(), String> {
-    let cache_dir = app_handle.path().app_cache_dir().map_err(|e| e.to_string())?.join("previews");
+) -> Result<(), DriveError> {
+    let cache_dir = app_handle.path().app_cache_dir().map_err(|e| DriveError::Io(e.to_string()))?.join("previews");
     if cache_dir.exists() {
          let _ = std::fs::remove_dir_all(cache_dir);
     }
@@ -138,31 +138,29 @@ pub async fn cmd_get_thumbnail(
     folder_id: Option<i64>,
     app_handle: tauri::AppHandle,
     state: State<'_, TelegramState>,
-) -> Result<String, String> {
+    bw_state: State<'_, BandwidthManager>,
+    drive: State<'_, BotDrive>,
+    peers: State<'_, PeerCache>,
+    messages: State<'_, MessageCache>,
+    transfers: State<'_, TransferManager>,
+) -> Result<String, DriveError> {
     // Check if thumbnail already in cache
-    let cache_dir = app_handle.path().app_data_dir().map_err(|e: tauri::Error| e.to_string())?.join("thumbnails");
+    let cache_dir = app_handle.path().app_data_dir().map_err(|e: tauri::Error| DriveError::Io(e.to_string()))?.join("thumbnails");
     if !cache_dir.exists() { let _ = std::fs::create_dir_all(&cache_dir); }
     
-    // Check for any cached thumbnail for this message
-    
-    // Look for existing cached file
-    if let Ok(entries) = std::fs::read_dir(&cache_dir) {
-        for entry in entries.flatten() {
-            let name = entry.file_name().to_string_lossy().to_string();
-            if name.starts_with(&format!("{}.", message_id)) {
-                // Found cached thumbnail, return as base64
-                if let Ok(bytes) = std::fs::read(entry.path()) {
-                    let ext = name.rsplit('.').next().unwrap_or("jpg");
-                    let mime = match ext {
-                        "png" => "image/png",
-                        "gif" => "image/gif", 
-                        "webp" => "image/webp",
-                        _ => "image/jpeg",
-                    };
-                    let b64 = general_purpose::STANDARD.encode(&bytes);
-                    return Ok(format!("data:{};base64,{}", mime, b64));
-                }
-            }
+    // Look for an existing cached file. Exact names only, a `.part` download in progress shares the stem.
+    for ext in ["jpg", "png", "gif", "webp"] {
+        let cached = cache_dir.join(format!("{}.{}", cache_stem(folder_id, message_id), ext));
+        if let Ok(bytes) = std::fs::read(&cached) {
+            // Found cached thumbnail, return as base64
+            let mime = match ext {
+                "png" => "image/png",
+                "gif" => "image/gif",
+                "webp" => "image/webp",
+                _ => "image/jpeg",
+            };
+            let b64 = general_purpose::STANDARD.encode(&bytes);
+            return Ok(format!("data:{};base64,{}", mime, b64));
         }
     }
     
@@ -171,56 +169,59 @@ pub async fn cmd_get_thumbnail(
     if client_opt.is_none() { return Ok("".to_string()); }
     let client = client_opt.unwrap();
     
-    let peer = resolve_peer(&client, folder_id).await?;
-    let mut msgs = client.iter_messages(&peer);
-    
-    while let Some(m) = msgs.next().await.map_err(|e| e.to_string())? {
-        if m.id() == message_id {
-            if let Some(media) = m.media() {
-                // Only get thumbnails for photos and documents with photo thumbnails
-                let (is_image, ext) = match &media {
-                    Media::Photo(_) => (true, "jpg".to_string()),
-                    Media::Document(d) => {
-                        let mime = d.mime_type().unwrap_or("");
-                        if mime.starts_with("image/") {
-                            let e = match mime {
-                                "image/png" => "png",
-                                "image/gif" => "gif",
-                                "image/webp" => "webp",
-                                _ => "jpg",
-                            };
-                            (true, e.to_string())
-                        } else {
-                            // Not an image, return empty - FileCard will show icon
-                            return Ok("".to_string());
-                        }
-                    },
-                    _ => return Ok("".to_string()),
-                };
-                
-                if is_image {
-                    // Get photo thumbnail (smallest size for speed)
-                    let save_path = cache_dir.join(format!("{}.{}", message_id, ext));
-                    let save_path_str = save_path.to_string_lossy().to_string();
-                    
-                    // Download the thumbnail/photo
-                    if client.download_media(&media, &save_path_str).await.is_ok() {
-                        if let Ok(bytes) = std::fs::read(&save_path) {
-                            let mime = match ext.as_str() {
-                                "png" => "image/png",
-                                "gif" => "image/gif",
-                                "webp" => "image/webp",
-                                _ => "image/jpeg",
-                            };
-                            let b64 = general_purpose::STANDARD.encode(&bytes);
-                            return Ok(format!("data:{};base64,{}", mime, b64));
-                        }
+    let peer = resolve_peer(&client, &drive, &peers, folder_id).await?;
+    if let Some(m) = peers.check(&peer, messages.get(&client, &peer, message_id).await)? {
+        if let Some(media) = m.media() {
+            // Only get thumbnails for photos and documents with photo thumbnails
+            let (is_image, ext) = match &media {
+                Media::Photo(_) => (true, "jpg".to_string()),
+                Media::Document(d) => {
+                    let mime = d.mime_type().unwrap_or("");
+                    if mime.starts_with("image/") {
+                        let e = match mime {
+                            "image/png" => "png",
+                            "image/gif" => "gif",
+                            "image/webp" => "webp",
+                            _ => "jpg",
+                        };
+                        (true, e.to_string())
+                    } else {
+                        // Not an image, return empty - FileCard will show icon
+                        return Ok("".to_string());
+                    }
+                },
+                _ => return Ok("".to_string()),
+            };
+            
+            if is_image {
+                // Get photo thumbnail (smallest size for speed)
+                let save_path = cache_dir.join(format!("{}.{}", cache_stem(folder_id, message_id), ext));
+
+                // Through a .part file like previews, so the cache lookup above never finds a partial one
+                let source = FileSource { peer: &peer, messages: &messages, message_id };
+                if download_to_path(&client, &bw_state, &media, &source, &save_path, transfers.download_parallelism()).await.is_ok() {
+                    if let Ok(bytes) = std::fs::read(&save_path) {
+                        let mime = match ext.as_str() {
+                            "png" => "image/png",
+                            "gif" => "image/gif",
+                            "webp" => "image/webp",
+                            _ => "image/jpeg",
+                        };
+                        let b64 = general_purpose::STANDARD.encode(&bytes);
+                        return Ok(format!("data:{};base64,{}", mime, b64));
                     }
                 }
             }
-            break;
         }
     }
     
     Ok("".to_string())
 }
+
+/// Cache file name without extension. Message IDs are only unique within a chat.
+fn cache_stem(folder_id: Option<i64>, message_id: i32) -> String {
+    match folder_id {
+        Some(id) => format!("{}-{}", id, message_id),
+        None => format!("home-{}", message_id),
+    }
+}
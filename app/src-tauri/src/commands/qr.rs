@@ -0,0 +1,190 @@
+use tauri::{Emitter, Manager, State};
+use grammers_client::Client;
+use grammers_client::update::Update;
+use grammers_mtsender::InvocationError;
+use grammers_session::Session;
+use grammers_tl_types as tl;
+use base64::{Engine as _, engine::general_purpose};
+use tokio::sync::broadcast;
+use tokio::time::Duration;
+
+use crate::TelegramState;
+use crate::accounts::AccountManager;
+use crate::commands::{AccountUpdate, checkpoint_session, ensure_client_initialized};
+use crate::error::DriveError;
+use crate::models::QrLoginToken;
+
+/// Pixels per QR module in the rendered PNG
+const QR_SCALE: usize = 8;
+/// Blank border around the code, in modules, so phone cameras can find it
+const QR_QUIET_ZONE: usize = 4;
+
+/// Starts a QR-code login for the active account.
+///
+/// Progress is reported through events:
+/// `qr-login-token` (a fresh code to show, sent again whenever it expires),
+/// `qr-login-accepted`, `qr-login-password-required` (continue with `cmd_auth_check_password`)
+/// and `qr-login-failed`.
+#[tauri::command]
+pub async fn cmd_auth_qr_start(
+    app_handle: tauri::AppHandle,
+    api_id: i32,
+    api_hash: String,
+    state: State<'_, TelegramState>,
+) -> Result<bool, DriveError> {
+    if api_hash.trim().is_empty() {
+        return Err("API Hash cannot be empty.".into());
+    }
+
+    *state.api_id.lock().await = Some(api_id);
+    let client = ensure_client_initialized(&app_handle, &state, api_id).await?;
+    let account = app_handle.state::<AccountManager>().ensure_active().name;
+
+    // Subscribe before the first export so an early acceptance is not missed
+    let updates = state.updates.subscribe();
+
+    let mut qr_guard = state.qr_login.lock().await;
+    if let Some(previous) = qr_guard.take() {
+        previous.abort();
+    }
+    let task_handle = app_handle.clone();
+    *qr_guard = Some(tauri::async_runtime::spawn(async move {
+        if let Err(e) = run_qr_login(&task_handle, &client, api_id, &api_hash, &account, updates).await {
+            log::error!("QR login failed: {}", e);
+            let _ = task_handle.emit("qr-login-failed", e.to_string());
+        }
+    }));
+
+    Ok(true)
+}
+
+#[tauri::command]
+pub async fn cmd_auth_qr_cancel(state: State<'_, TelegramState>) -> Result<bool, DriveError> {
+    if let Some(qr_login) = state.qr_login.lock().await.take() {
+        qr_login.abort();
+        log::info!("QR login cancelled");
+    }
+    Ok(true)
+}
+
+/// Exports login tokens until one is accepted on another device, then finishes the login
+async fn run_qr_login(
+    app_handle: &tauri::AppHandle,
+    client: &Client,
+    api_id: i32,
+    api_hash: &str,
+    account: &str,
+    mut updates: broadcast::Receiver<AccountUpdate>,
+) -> Result<(), DriveError> {
+    loop {
+        let exported = client.invoke(&tl::functions::auth::ExportLoginToken {
+            api_id,
+            api_hash: api_hash.to_string(),
+            except_ids: Vec::new(),
+        }).await;
+
+        let token = match exported {
+            Ok(tl::enums::auth::LoginToken::Token(t)) => t,
+            Ok(tl::enums::auth::LoginToken::MigrateTo(m)) => {
+                log::info!("QR login accepted, account lives on DC {}", m.dc_id);
+                return import_in_dc(app_handle, client, m.dc_id, m.token).await;
+            }
+            Ok(tl::enums::auth::LoginToken::Success(_)) => return finish(app_handle),
+            Err(e) => return handle_error(app_handle, client, e).await,
+        };
+
+        let url = format!("tg://login?token={}", general_purpose::URL_SAFE.encode(&token.token));
+        let png = qr_png_data_url(&url)?;
+        let _ = app_handle.emit("qr-login-token", QrLoginToken { url, png, expires: token.expires });
+
+        // Re-export when the phone accepts (Telegram pushes updateLoginToken) or the code expires
+        let ttl = (token.expires as i64 - chrono::Utc::now().timestamp()).max(1) as u64;
+        let expiry = tokio::time::sleep(Duration::from_secs(ttl));
+        tokio::pin!(expiry);
+        loop {
+            tokio::select! {
+                _ = &mut expiry => break,
+                received = updates.recv() => match received {
+                    Ok(u) if u.account == account && is_login_token_update(&u.update) => break,
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return Err("Update stream closed".into()),
+                },
+            }
+        }
+    }
+}
+
+/// The account is on another DC: import the token there and make that DC the home one
+async fn import_in_dc(
+    app_handle: &tauri::AppHandle,
+    client: &Client,
+    dc_id: i32,
+    token: Vec<u8>,
+) -> Result<(), DriveError> {
+    let imported = client.invoke_in_dc(dc_id, &tl::functions::auth::ImportLoginToken { token }).await;
+    match imported {
+        Ok(tl::enums::auth::LoginToken::Success(_)) => {
+            let session = app_handle.state::<TelegramState>().session.lock().await.clone()
+                .ok_or("Session closed during QR login")?;
+            session.set_home_dc_id(dc_id).await;
+            finish(app_handle)
+        }
+        Ok(_) => Err("Unexpected response while importing the login token".into()),
+        Err(e) => handle_error(app_handle, client, e).await,
+    }
+}
+
+fn finish(app_handle: &tauri::AppHandle) -> Result<(), DriveError> {
+    log::info!("QR login successful.");
+    checkpoint_session(app_handle);
+    let _ = app_handle.emit("qr-login-accepted", ());
+    Ok(())
+}
+
+/// Accounts with 2FA get SESSION_PASSWORD_NEEDED here; hand over to the password step
+async fn handle_error(app_handle: &tauri::AppHandle, client: &Client, e: InvocationError) -> Result<(), DriveError> {
+    match &e {
+        InvocationError::Rpc(rpc) if rpc.name == "SESSION_PASSWORD_NEEDED" => {
+            let token = client.get_password_information().await?;
+            let hint = token.hint().map(|h| h.to_string());
+            *app_handle.state::<TelegramState>().password_token.lock().await = Some(token);
+            let _ = app_handle.emit("qr-login-password-required", hint);
+            Ok(())
+        }
+        _ => Err(e.into()),
+    }
+}
+
+fn is_login_token_update(update: &Update) -> bool {
+    matches!(update, Update::Raw(raw) if matches!(raw.raw, tl::enums::Update::LoginToken))
+}
+
+/// Renders `data` as a black-on-white QR code and returns it as a PNG data URL
+fn qr_png_data_url(data: &str) -> Result<String, DriveError> {
+    let code = qrcode::QrCode::new(data.as_bytes()).map_err(|e| DriveError::Other(e.to_string()))?;
+    let modules = code.width();
+    let colors = code.to_colors();
+    let size = (modules + 2 * QR_QUIET_ZONE) * QR_SCALE;
+
+    let mut pixels = vec![0xffu8; size * size];
+    for (i, color) in colors.iter().enumerate() {
+        if *color != qrcode::Color::Dark {
+            continue;
+        }
+        let (mx, my) = (i % modules + QR_QUIET_ZONE, i / modules + QR_QUIET_ZONE);
+        for y in my * QR_SCALE..(my + 1) * QR_SCALE {
+            pixels[y * size + mx * QR_SCALE..y * size + (mx + 1) * QR_SCALE].fill(0);
+        }
+    }
+
+    let mut png_bytes = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png_bytes, size as u32, size as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| DriveError::Other(e.to_string()))?;
+        writer.write_image_data(&pixels).map_err(|e| DriveError::Other(e.to_string()))?;
+    }
+
+    Ok(format!("data:image/png;base64,{}", general_purpose::STANDARD.encode(png_bytes)))
+}
@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+use grammers_client::Client;
+use serde::Serialize;
+use tauri::State;
+use crate::TelegramState;
+use crate::accounts::AccountManager;
+use crate::bot::BotDrive;
+use crate::commands::fs::{file_metadata, list_files};
+use crate::commands::utils::resolve_peer;
+use crate::error::DriveError;
+use crate::messages::MessageCache;
+use crate::models::FileMetadata;
+use crate::peers::PeerCache;
+use crate::restic::{self, ResticObject, ResticRepo, ResticRepoConfig};
+use crate::server::StreamServer;
+
+/// The restic repository and the URL to give restic (`restic -r <url>`), once the server runs
+#[derive(Debug, Serialize, Clone)]
+pub struct ResticRepoInfo {
+    pub account: String,
+    pub folder_id: i64,
+    pub url: Option<String>,
+}
+
+fn repo_info(repo: ResticRepoConfig, stream_server: &StreamServer) -> ResticRepoInfo {
+    let url = stream_server.port().map(|port| {
+        format!("rest:http://{}:{}@127.0.0.1:{}/restic/", restic::USERNAME, repo.password, port)
+    });
+    ResticRepoInfo { account: repo.account, folder_id: repo.folder_id, url }
+}
+
+#[tauri::command]
+pub fn cmd_get_restic_repo(
+    restic_repo: State<'_, ResticRepo>,
+    stream_server: State<'_, StreamServer>,
+) -> Option<ResticRepoInfo> {
+    restic_repo.config().map(|repo| repo_info(repo, &stream_server))
+}
+
+/// Serves `folder_id` to restic through the streaming server, with a new password.
+/// Objects restic stored there before are found again by their document names.
+#[tauri::command]
+pub async fn cmd_enable_restic_repo(
+    folder_id: i64,
+    state: State<'_, TelegramState>,
+    accounts: State<'_, AccountManager>,
+    drive: State<'_, BotDrive>,
+    peers: State<'_, PeerCache>,
+    messages: State<'_, MessageCache>,
+    restic_repo: State<'_, ResticRepo>,
+    stream_server: State<'_, StreamServer>,
+) -> Result<ResticRepoInfo, DriveError> {
+    let client = state.client.lock().await.clone().ok_or(DriveError::NotConnected)?;
+    let account = accounts.active_name().ok_or(DriveError::NotConnected)?;
+
+    let mut objects = BTreeMap::new();
+    // Newest first, so a leftover duplicate never hides the latest upload
+    for file in folder_files(&client, &drive, &peers, &messages, folder_id).await? {
+        if let Some(key) = restic::key_from_document(&file.name) {
+            objects.entry(key).or_insert(ResticObject { message_id: file.id as i32, size: file.size });
+        }
+    }
+    let repo = restic_repo.enable(account, folder_id, objects);
+    Ok(repo_info(repo, &stream_server))
+}
+
+/// Every file in a folder, newest first. `list_files` only returns the latest ones.
+async fn folder_files(client: &Client, drive: &BotDrive, peers: &PeerCache, messages: &MessageCache, folder_id: i64) -> Result<Vec<FileMetadata>, DriveError> {
+    // The bot index already lists every file
+    if drive.is_bot() {
+        return list_files(client, drive, peers, messages, Some(folder_id)).await;
+    }
+    let peer = resolve_peer(client, drive, peers, Some(folder_id)).await?;
+    let mut files = Vec::new();
+    let mut history = client.iter_messages(&peer);
+    while let Some(msg) = history.next().await? {
+        files.extend(file_metadata(&msg, Some(folder_id)));
+    }
+    Ok(files)
+}
+
+/// Stops serving the restic repository. Its objects stay in the folder.
+#[tauri::command]
+pub fn cmd_disable_restic_repo(restic_repo: State<'_, ResticRepo>) {
+    restic_repo.disable();
+}
@@ -0,0 +1,88 @@
+use tauri::State;
+use crate::accounts::AccountManager;
+use crate::error::DriveError;
+use crate::filenames::CollisionStrategy;
+use crate::paths::PathPolicy;
+use crate::snapshots::{PruneResult, RestoreResult, SnapshotDiff, SnapshotInfo, SnapshotManager};
+
+/// Snapshots `paths` into the account's snapshot channel, uploading only new chunks.
+/// Progress is reported through `snapshot-progress` events.
+#[tauri::command]
+pub async fn cmd_create_snapshot(
+    paths: Vec<String>,
+    app_handle: tauri::AppHandle,
+    policy: State<'_, PathPolicy>,
+    snapshots: State<'_, SnapshotManager>,
+) -> Result<SnapshotInfo, DriveError> {
+    if paths.is_empty() {
+        return Err(DriveError::Other("No paths to snapshot".to_string()));
+    }
+    let paths = paths.iter().map(|p| policy.readable(p)).collect::<Result<Vec<_>, _>>()?;
+    snapshots.create(&app_handle, paths).await
+}
+
+/// Snapshots of the active account, oldest first
+#[tauri::command]
+pub fn cmd_list_snapshots(
+    accounts: State<'_, AccountManager>,
+    snapshots: State<'_, SnapshotManager>,
+) -> Result<Vec<SnapshotInfo>, DriveError> {
+    snapshots.list(&accounts)
+}
+
+/// What changed going from snapshot `from` to snapshot `to`
+#[tauri::command]
+pub fn cmd_diff_snapshots(
+    from: u32,
+    to: u32,
+    accounts: State<'_, AccountManager>,
+    snapshots: State<'_, SnapshotManager>,
+) -> Result<SnapshotDiff, DriveError> {
+    snapshots.diff(&accounts, from, to)
+}
+
+/// Restores `path` (a file or directory in the snapshot, everything if omitted) into `dest`
+#[tauri::command]
+pub async fn cmd_restore_snapshot(
+    id: u32,
+    path: Option<String>,
+    dest: String,
+    collision: Option<CollisionStrategy>,
+    app_handle: tauri::AppHandle,
+    policy: State<'_, PathPolicy>,
+    snapshots: State<'_, SnapshotManager>,
+) -> Result<RestoreResult, DriveError> {
+    let dest = policy.writable(&dest)?;
+    let collision = collision.unwrap_or(CollisionStrategy::Rename);
+    snapshots.restore(&app_handle, id, path, &dest, collision).await
+}
+
+/// Recreates the local snapshot index from the snapshot channel `folder_id`,
+/// e.g. after reinstalling. Returns the recovered snapshots.
+#[tauri::command]
+pub async fn cmd_rebuild_snapshot_index(
+    folder_id: i64,
+    app_handle: tauri::AppHandle,
+    snapshots: State<'_, SnapshotManager>,
+) -> Result<Vec<SnapshotInfo>, DriveError> {
+    snapshots.rebuild(&app_handle, folder_id).await
+}
+
+/// Deletes a snapshot. Run `cmd_prune_snapshots` afterwards to free its chunks.
+#[tauri::command]
+pub async fn cmd_forget_snapshot(
+    id: u32,
+    app_handle: tauri::AppHandle,
+    snapshots: State<'_, SnapshotManager>,
+) -> Result<(), DriveError> {
+    snapshots.forget(&app_handle, id).await
+}
+
+/// Deletes packs no snapshot refers to any more
+#[tauri::command]
+pub async fn cmd_prune_snapshots(
+    app_handle: tauri::AppHandle,
+    snapshots: State<'_, SnapshotManager>,
+) -> Result<PruneResult, DriveError> {
+    snapshots.prune(&app_handle).await
+}
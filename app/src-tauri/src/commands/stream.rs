@@ -0,0 +1,64 @@
+use tauri::State;
+use crate::accounts::AccountManager;
+use crate::archive::{ZipMode, selection_to_path};
+use crate::error::DriveError;
+use crate::models::ZipSelection;
+use crate::server::{self, StreamAuth, StreamServer};
+
+/// Builds a signed, short-lived URL the media player can load from the streaming server.
+#[tauri::command]
+pub fn cmd_get_stream_url(
+    message_id: i32,
+    folder_id: Option<i64>,
+    auth: State<'_, StreamAuth>,
+    stream_server: State<'_, StreamServer>,
+    accounts: State<'_, AccountManager>,
+) -> Result<String, DriveError> {
+    let port = stream_server.port().ok_or("Streaming server is not running")?;
+    let account = accounts.active_name().ok_or(DriveError::NotConnected)?;
+    let folder = folder_id.map(|id| id.to_string()).unwrap_or_else(|| "home".to_string());
+    let path = format!("/stream/{}/{}/{}", account, folder, message_id);
+    Ok(format!("http://127.0.0.1:{}{}", port, auth.sign_path(&path)))
+}
+
+/// Signed URL the browser can download a ZIP of the selected files from.
+#[tauri::command]
+pub fn cmd_get_zip_url(
+    selection: Vec<ZipSelection>,
+    mode: Option<ZipMode>,
+    auth: State<'_, StreamAuth>,
+    stream_server: State<'_, StreamServer>,
+    accounts: State<'_, AccountManager>,
+) -> Result<String, DriveError> {
+    if selection.iter().all(|group| group.message_ids.is_empty()) {
+        return Err("No files selected".into());
+    }
+    let port = stream_server.port().ok_or("Streaming server is not running")?;
+    let account = accounts.active_name().ok_or(DriveError::NotConnected)?;
+    let mode = mode.unwrap_or_default().as_str();
+    let path = format!("/zip/{}/{}/{}", account, mode, selection_to_path(&selection));
+    Ok(format!("http://127.0.0.1:{}{}", port, auth.sign_path(&path)))
+}
+
+#[tauri::command]
+pub fn cmd_get_stream_port(stream_server: State<'_, StreamServer>) -> Option<u16> {
+    stream_server.port()
+}
+
+/// Stops the streaming server and starts it again, optionally on a different preferred port.
+/// Returns once the server listens, with the port it got.
+#[tauri::command]
+pub async fn cmd_restart_stream_server(
+    port: Option<u16>,
+    app_handle: tauri::AppHandle,
+    stream_server: State<'_, StreamServer>,
+) -> Result<u16, DriveError> {
+    stream_server.stop().await;
+    if let Some(port) = port {
+        stream_server.set_preferred_port(port);
+    }
+    let port = server::spawn_server(app_handle)
+        .await
+        .map_err(|_| DriveError::Other("Streaming server stopped before it started".to_string()))??;
+    Ok(port)
+}
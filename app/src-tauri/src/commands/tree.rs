@@ -0,0 +1,503 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tauri::{Emitter, State};
+use grammers_client::Client;
+use grammers_client::types::{Media, Message, Peer};
+use walkdir::WalkDir;
+use crate::TelegramState;
+use crate::models::{
+    DirectoryUploadPlan, DirectoryUploadProgress, DirectoryUploadSummary, FolderDownloadProgress, FolderDownloadSummary,
+    PlannedUpload, TransferIssue,
+};
+use crate::bandwidth::BandwidthManager;
+use crate::bot::BotDrive;
+use crate::download::{FileSource, RemoteFile, download_to_path};
+use crate::filenames::{CollisionStrategy, numbered_name, sanitize_filename};
+use crate::filters::PathFilter;
+use crate::live::LiveFolders;
+use crate::messages::MessageCache;
+use crate::paths::PathPolicy;
+use crate::peers::PeerCache;
+use crate::transfers::TransferManager;
+use crate::upload::UploadProgress;
+use crate::commands::fs::{MAX_UPLOAD_SIZE, create_folder, file_metadata, scan_folders, upload_file};
+use crate::commands::utils::resolve_peer;
+use crate::error::DriveError;
+
+struct LocalFile {
+    path: String,
+    size: u64,
+}
+
+/// Files of a local directory tree, grouped by the drive folder they go to
+#[derive(Default)]
+struct LocalTree {
+    folders: BTreeMap<String, Vec<LocalFile>>,
+    skipped: Vec<TransferIssue>,
+    failed: Vec<TransferIssue>,
+}
+
+impl LocalTree {
+    fn skip(&mut self, path: String, reason: impl Into<String>) {
+        self.skipped.push(TransferIssue { path, reason: reason.into() });
+    }
+}
+
+/// Prepares a directory upload for the upload queue.
+///
+/// Walks `path`, creates the drive folders it needs and returns every file with the
+/// folder it goes to. The files themselves are queued by the frontend and uploaded
+/// one by one with `cmd_upload_file`, like any other upload.
+#[tauri::command]
+pub async fn cmd_upload_directory(
+    path: String,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    state: State<'_, TelegramState>,
+    drive: State<'_, BotDrive>,
+    peers: State<'_, PeerCache>,
+    policy: State<'_, PathPolicy>,
+    live: State<'_, LiveFolders>,
+) -> Result<DirectoryUploadPlan, DriveError> {
+    let root = policy.readable(&path)?;
+    let filter = PathFilter::new(&include.unwrap_or_default(), &exclude.unwrap_or_default())?;
+    let client_opt = { state.client.lock().await.clone() };
+    if client_opt.is_none() {
+        log::info!("[MOCK] Prepared upload of directory {}", path);
+        return Ok(DirectoryUploadPlan::default());
+    }
+    let client = client_opt.unwrap();
+    plan_directory_upload(&client, &drive, &peers, Some(&live), &root, &filter).await
+}
+
+/// Finds every file under `root` that passes `filter` and the drive folder it goes to.
+///
+/// Each directory maps to a drive folder named after its path, starting with the
+/// name of `root` itself (`Photos`, `Photos/2024`, ...). Existing folders with that
+/// name are reused, missing ones are created. Created folders are announced to
+/// `live`, so their new files show up as events.
+pub async fn plan_directory_upload(
+    client: &Client,
+    drive: &BotDrive,
+    peers: &PeerCache,
+    live: Option<&LiveFolders>,
+    root: &Path,
+    filter: &PathFilter,
+) -> Result<DirectoryUploadPlan, DriveError> {
+    if !root.is_dir() {
+        return Err(DriveError::NotFound(format!("{} is not a directory", root.display())));
+    }
+    let tree = walk_directory(root, filter)?;
+    let mut plan = DirectoryUploadPlan {
+        skipped: tree.skipped,
+        failed: tree.failed,
+        ..Default::default()
+    };
+    if tree.folders.is_empty() {
+        return Ok(plan);
+    }
+
+    let mut existing: HashMap<String, i64> = scan_folders(client, drive, peers).await?
+        .into_iter()
+        .map(|f| (f.name, f.id))
+        .collect();
+
+    for (folder, files) in tree.folders {
+        let folder_id = match existing.get(&folder) {
+            Some(id) => *id,
+            None => match create_folder(client, drive, peers, folder.clone()).await {
+                Ok(created) => {
+                    if let Some(live) = live {
+                        live.add_folder(created.id);
+                    }
+                    existing.insert(folder.clone(), created.id);
+                    let id = created.id;
+                    plan.created_folders.push(created);
+                    id
+                }
+                // No other folder could be created either
+                Err(e) if stops_transfer(&e) => return Err(e),
+                Err(e) => {
+                    let reason = format!("Could not create folder '{}': {}", folder, e);
+                    log::warn!("{}", reason);
+                    plan.failed.extend(files.into_iter().map(|file| TransferIssue { path: file.path, reason: reason.clone() }));
+                    continue;
+                }
+            },
+        };
+        plan.files.extend(files.into_iter().map(|file| PlannedUpload { path: file.path, folder_id, size: file.size }));
+    }
+    Ok(plan)
+}
+
+/// Uploads every file under `root` that passes `filter`, for callers without an upload queue.
+///
+/// Folders are set up by `plan_directory_upload`. Files go up one at a time, each
+/// with the parallel part uploads of `upload_file`. A failing file does not stop the
+/// rest unless every later one would fail the same way (bandwidth, auth).
+pub async fn upload_directory(
+    client: &Client,
+    bw_state: &BandwidthManager,
+    drive: &BotDrive,
+    peers: &PeerCache,
+    transfers: &TransferManager,
+    root: &Path,
+    filter: &PathFilter,
+    on_progress: impl Fn(DirectoryUploadProgress),
+) -> Result<DirectoryUploadSummary, DriveError> {
+    let plan = plan_directory_upload(client, drive, peers, None, root, filter).await?;
+    let files_total = plan.files.len();
+    let mut summary = DirectoryUploadSummary {
+        skipped: plan.skipped,
+        failed: plan.failed,
+        created_folders: plan.created_folders,
+        ..Default::default()
+    };
+    let on_progress = &on_progress;
+    // Set once an error means every remaining file would fail too
+    let mut stopped: Option<String> = None;
+
+    for (files_done, file) in plan.files.into_iter().enumerate() {
+        let outcome = match &stopped {
+            Some(reason) => Err(DriveError::Other(reason.clone())),
+            None => {
+                let report = |p: UploadProgress| on_progress(DirectoryUploadProgress {
+                    path: p.path,
+                    files_done,
+                    files_total,
+                    bytes_sent: p.bytes_sent,
+                    size: p.size,
+                });
+                upload_file(client, bw_state, drive, peers, transfers, Path::new(&file.path), Some(file.folder_id), report).await
+                    .map(|_| ())
+            }
+        };
+        match outcome {
+            Ok(()) => summary.uploaded.push(file.path.clone()),
+            Err(e) => {
+                if stops_transfer(&e) {
+                    stopped = Some(e.to_string());
+                }
+                log::warn!("Failed to upload {}: {}", file.path, e);
+                summary.failed.push(TransferIssue { path: file.path.clone(), reason: e.to_string() });
+            }
+        }
+        on_progress(DirectoryUploadProgress {
+            path: file.path,
+            files_done: files_done + 1,
+            files_total,
+            bytes_sent: file.size,
+            size: file.size,
+        });
+    }
+
+    log::info!(
+        "Uploaded {}: {} uploaded, {} skipped, {} failed",
+        root.display(), summary.uploaded.len(), summary.skipped.len(), summary.failed.len()
+    );
+    Ok(summary)
+}
+
+/// Errors after which every remaining file of a transfer would fail the same way
+pub(crate) fn stops_transfer(e: &DriveError) -> bool {
+    matches!(e, DriveError::BandwidthExceeded(_) | DriveError::AuthRequired | DriveError::NotConnected)
+}
+
+/// Collects the files under `root` that pass `filter`, without descending into excluded directories
+fn walk_directory(root: &Path, filter: &PathFilter) -> Result<LocalTree, DriveError> {
+    let root_name = root.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or("Cannot upload a filesystem root")?;
+
+    let mut tree = LocalTree::default();
+    let entries = WalkDir::new(root)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !filter.is_excluded(&relative_path(root, e.path())));
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                let path = e.path().map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+                tree.failed.push(TransferIssue { path, reason: e.to_string() });
+                continue;
+            }
+        };
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        let relative = relative_path(root, entry.path());
+        if !filter.accepts(&relative) {
+            continue;
+        }
+
+        let path = entry.path().to_string_lossy().to_string();
+        if !entry.file_type().is_file() {
+            tree.skip(path, "Not a regular file");
+            continue;
+        }
+        let size = match entry.metadata() {
+            Ok(m) => m.len(),
+            Err(e) => {
+                tree.failed.push(TransferIssue { path, reason: e.to_string() });
+                continue;
+            }
+        };
+        if size == 0 {
+            tree.skip(path, "Empty file");
+            continue;
+        }
+        if size > MAX_UPLOAD_SIZE {
+            tree.skip(path, format!("Larger than the {} MB upload limit", MAX_UPLOAD_SIZE / 1024 / 1024));
+            continue;
+        }
+
+        let folder = match relative.rsplit_once('/') {
+            Some((dir, _)) => format!("{}/{}", root_name, dir),
+            None => root_name.clone(),
+        };
+        tree.folders.entry(folder).or_default().push(LocalFile { path, size });
+    }
+    Ok(tree)
+}
+
+/// `path` relative to `root`, with `/` separators on every platform
+pub(crate) fn relative_path(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// A drive file and the local path it is saved to
+struct DownloadJob {
+    peer: Peer,
+    message_id: i32,
+    media: Media,
+    path: PathBuf,
+    size: Option<u64>,
+}
+
+/// Where a drive file goes in the local directory
+enum LocalTarget {
+    Download(PathBuf),
+    /// A file with this name and size is already there
+    Identical(PathBuf),
+    /// A different file has this name and the collision strategy is `skip`
+    Taken(PathBuf),
+}
+
+#[tauri::command]
+pub async fn cmd_download_folder(
+    folder_id: Option<i64>,
+    dest: String,
+    collision: Option<CollisionStrategy>,
+    app_handle: tauri::AppHandle,
+    state: State<'_, TelegramState>,
+    bw_state: State<'_, BandwidthManager>,
+    drive: State<'_, BotDrive>,
+    peers: State<'_, PeerCache>,
+    messages: State<'_, MessageCache>,
+    transfers: State<'_, TransferManager>,
+    policy: State<'_, PathPolicy>,
+) -> Result<FolderDownloadSummary, DriveError> {
+    let dest_dir = policy.writable(&dest)?;
+    let client_opt = { state.client.lock().await.clone() };
+    if client_opt.is_none() {
+        log::info!("[MOCK] Downloaded folder {:?} to {}", folder_id, dest);
+        return Ok(FolderDownloadSummary::default());
+    }
+    let client = client_opt.unwrap();
+    let collision = collision.unwrap_or(CollisionStrategy::Rename);
+    download_folder(&client, &bw_state, &drive, &peers, &messages, &transfers, folder_id, &dest_dir, collision, |progress| {
+        let _ = app_handle.emit("folder-download-progress", progress);
+    }).await
+}
+
+/// Downloads every file of `folder_id` into `dest`.
+///
+/// Subfolders named like `upload_directory` creates them (`Photos/2024` below
+/// `Photos`) go into matching directories under `dest`. A local file with the same
+/// name and size counts as already downloaded; any other name clash is handled by
+/// `collision`, where `Rename` picks a free name like `report (1).pdf`. Files are
+/// listed oldest first, so the numbering stays the same between runs. Unfinished
+/// downloads resume from their `.part` file.
+pub async fn download_folder(
+    client: &Client,
+    bw_state: &BandwidthManager,
+    drive: &BotDrive,
+    peers: &PeerCache,
+    messages: &MessageCache,
+    transfers: &TransferManager,
+    folder_id: Option<i64>,
+    dest: &Path,
+    collision: CollisionStrategy,
+    on_progress: impl Fn(FolderDownloadProgress),
+) -> Result<FolderDownloadSummary, DriveError> {
+    let mut summary = FolderDownloadSummary::default();
+    let mut jobs = Vec::new();
+    let mut claimed = HashSet::new();
+
+    for (source, dir) in folder_layout(client, drive, peers, folder_id, dest).await? {
+        let (peer, listed) = match folder_messages(client, drive, peers, messages, source).await {
+            Ok(found) => found,
+            // The folder itself must be readable, a broken subfolder only loses its own files
+            Err(e) if source == folder_id => return Err(e),
+            Err(e) => {
+                summary.failed.push(TransferIssue { path: dir.to_string_lossy().to_string(), reason: e.to_string() });
+                continue;
+            }
+        };
+        for message in listed {
+            let Some(media) = message.media() else { continue };
+            let Some(file) = RemoteFile::from_media(&media) else { continue };
+            let Some(meta) = file_metadata(&message, source) else { continue };
+            match local_target(&dir, &meta.name, file.size, collision, &mut claimed) {
+                LocalTarget::Download(path) => jobs.push(DownloadJob { peer: peer.clone(), message_id: message.id(), media, path, size: file.size }),
+                LocalTarget::Identical(path) => summary.skipped.push(TransferIssue {
+                    path: path.to_string_lossy().to_string(),
+                    reason: "Already downloaded".to_string(),
+                }),
+                LocalTarget::Taken(path) => summary.skipped.push(TransferIssue {
+                    path: path.to_string_lossy().to_string(),
+                    reason: "A different file with this name exists".to_string(),
+                }),
+            }
+        }
+    }
+
+    let files_total = jobs.len();
+    let bytes_total = jobs.iter().filter_map(|j| j.size).sum();
+    let mut bytes_done = 0;
+    let mut stopped: Option<String> = None;
+
+    for (index, job) in jobs.into_iter().enumerate() {
+        let path = job.path.to_string_lossy().to_string();
+        let result = match &stopped {
+            Some(reason) => Err(DriveError::Other(reason.clone())),
+            None => match job.path.parent().map(std::fs::create_dir_all).transpose() {
+                Ok(_) => {
+                    let source = FileSource { peer: &job.peer, messages, message_id: job.message_id };
+                    download_to_path(client, bw_state, &job.media, &source, &job.path, transfers.download_parallelism()).await
+                }
+                Err(e) => Err(e.into()),
+            },
+        };
+        match result {
+            Ok(_) => {
+                bytes_done += job.size.unwrap_or(0);
+                summary.downloaded.push(path.clone());
+            }
+            Err(e) => {
+                if stops_transfer(&e) {
+                    stopped = Some(e.to_string());
+                }
+                log::warn!("Failed to download message {} to {}: {}", job.message_id, path, e);
+                summary.failed.push(TransferIssue { path: path.clone(), reason: e.to_string() });
+            }
+        }
+        on_progress(FolderDownloadProgress {
+            path,
+            files_done: index + 1,
+            files_total,
+            bytes_done,
+            bytes_total,
+        });
+    }
+
+    log::info!(
+        "Downloaded folder {:?} to {}: {} downloaded, {} skipped, {} failed",
+        folder_id, dest.display(), summary.downloaded.len(), summary.skipped.len(), summary.failed.len()
+    );
+    Ok(summary)
+}
+
+/// `folder_id` and its subfolders, each with the local directory its files go to
+async fn folder_layout(
+    client: &Client,
+    drive: &BotDrive,
+    peers: &PeerCache,
+    folder_id: Option<i64>,
+    dest: &Path,
+) -> Result<Vec<(Option<i64>, PathBuf)>, DriveError> {
+    let mut layout = vec![(folder_id, dest.to_path_buf())];
+    // Saved Messages has no subfolders
+    let Some(id) = folder_id else { return Ok(layout) };
+
+    let folders = scan_folders(client, drive, peers).await?;
+    let Some(root) = folders.iter().find(|f| f.id == id) else { return Ok(layout) };
+    let prefix = format!("{}/", root.name);
+    for folder in &folders {
+        let Some(relative) = folder.name.strip_prefix(&prefix) else { continue };
+        let mut dir = dest.to_path_buf();
+        for part in relative.split('/') {
+            dir.push(sanitize_filename(part));
+        }
+        layout.push((Some(folder.id), dir));
+    }
+    Ok(layout)
+}
+
+/// The peer of `folder_id` and every message with media in it, oldest first
+async fn folder_messages(
+    client: &Client,
+    drive: &BotDrive,
+    peers: &PeerCache,
+    messages: &MessageCache,
+    folder_id: Option<i64>,
+) -> Result<(Peer, Vec<Message>), DriveError> {
+    let peer = resolve_peer(client, drive, peers, folder_id).await?;
+
+    // Bots cannot read history, so fetch the messages the index knows about
+    let mut found: Vec<Message> = if drive.is_bot() {
+        let ids = drive.message_ids(folder_id.unwrap_or_default());
+        peers.check(&peer, messages.get_many(client, &peer, &ids).await)?.into_iter().flatten().collect()
+    } else {
+        let mut found = Vec::new();
+        let mut history = client.iter_messages(&peer);
+        while let Some(msg) = peers.check(&peer, history.next().await.map_err(DriveError::from))? {
+            if msg.media().is_some() {
+                found.push(msg);
+            }
+        }
+        found
+    };
+    found.sort_by_key(|m| m.id());
+    Ok((peer, found))
+}
+
+/// Picks the path for a file called `name` in `dir`. Names already claimed by
+/// another download of this run are never reused; for an existing different file
+/// `collision` decides, with `Rename` trying `name (1).ext`, `name (2).ext`, ...
+fn local_target(
+    dir: &Path,
+    name: &str,
+    size: Option<u64>,
+    collision: CollisionStrategy,
+    claimed: &mut HashSet<PathBuf>,
+) -> LocalTarget {
+    let name = sanitize_filename(name);
+    for n in 0.. {
+        let candidate = dir.join(numbered_name(&name, n));
+        if claimed.contains(&candidate) {
+            continue;
+        }
+        let target = match std::fs::metadata(&candidate) {
+            Err(_) => LocalTarget::Download(candidate.clone()),
+            Ok(meta) if !meta.is_file() => continue,
+            // Photos of unknown size are taken to match by name alone
+            Ok(meta) if size.is_none() || size == Some(meta.len()) => LocalTarget::Identical(candidate.clone()),
+            Ok(_) => match collision {
+                CollisionStrategy::Overwrite => LocalTarget::Download(candidate.clone()),
+                CollisionStrategy::Skip => LocalTarget::Taken(candidate.clone()),
+                CollisionStrategy::Rename => continue,
+            },
+        };
+        claimed.insert(candidate);
+        return target;
+    }
+    unreachable!("ran out of file names")
+}
@@ -1,28 +1,122 @@
+use std::future::Future;
 use grammers_client::Client;
 use grammers_client::types::Peer;
+use grammers_tl_types as tl;
 use tauri::State;
 use crate::bandwidth::BandwidthManager;
+use crate::bot::BotDrive;
+use crate::error::{DriveError, with_retry};
+use crate::peers::{PeerCache, PeerKind, peer_id};
 
-pub async fn resolve_peer(client: &Client, folder_id: Option<i64>) -> Result<Peer, String> {
-    if let Some(fid) = folder_id {
-        let mut dialogs = client.iter_dialogs();
-        while let Some(dialog) = dialogs.next().await.map_err(|e| e.to_string())? {
-            // We use .raw.id() based on compiler suggestions that .id() might be missing on wrapper types in this version
-            match &dialog.peer {
-                Peer::Channel(c) => if c.raw.id == fid { return Ok(dialog.peer.clone()); },
-                Peer::User(u) => if u.raw.id() == fid { return Ok(dialog.peer.clone()); },
-                _ => {}
+/// Finds the chat behind a folder ID, `None` being Saved Messages.
+///
+/// Cached access hashes are tried first. The dialog list is only walked for chats the
+/// cache does not know, or whose cached entry stopped working.
+pub async fn resolve_peer(client: &Client, drive: &BotDrive, peers: &PeerCache, folder_id: Option<i64>) -> Result<Peer, DriveError> {
+    // Bots cannot list dialogs, their folders come from the bot index
+    if drive.is_bot() {
+        let fid = folder_id.ok_or("Bot accounts have no Saved Messages, choose a folder")?;
+        let folder = drive.folder(fid)
+            .ok_or_else(|| DriveError::NotFound(format!("Folder {} is not registered for this bot", fid)))?;
+        return channel_peer(client, folder.id, folder.access_hash).await;
+    }
+
+    let Some(fid) = folder_id else {
+        let me = with_retry("users.getFullUser", || client.get_me()).await?;
+        return Ok(Peer::User(me));
+    };
+
+    if let Some(peer) = peers.resolved(fid) {
+        return Ok(peer);
+    }
+    if let Some(cached) = peers.get(fid) {
+        let result = match cached.kind {
+            PeerKind::Channel => channel_peer(client, fid, cached.access_hash).await,
+            PeerKind::Group => group_peer(client, fid).await,
+        };
+        match result {
+            Ok(peer) => {
+                peers.remember(&peer);
+                return Ok(peer);
+            }
+            // Left the chat or the hash went stale; anything else (flood wait, server or network errors) is not the cache's fault
+            Err(DriveError::NotFound(_) | DriveError::ChannelUnavailable { .. }) => {
+                log::warn!("Cached peer {} no longer resolves, scanning dialogs", fid);
+                peers.invalidate(fid);
             }
+            Err(e) => return Err(e),
         }
-        Err(format!("Folder/Chat {} not found", fid))
-    } else {
-        match client.get_me().await {
-            Ok(me) => Ok(Peer::User(me)),
-            Err(e) => Err(e.to_string()),
+    }
+
+    let mut dialogs = client.iter_dialogs();
+    let mut seen = Vec::new();
+    let mut found = None;
+    while let Some(dialog) = dialogs.next().await? {
+        if peer_id(&dialog.peer) == Some(fid) {
+            found = Some(dialog.peer.clone());
+        }
+        seen.push(dialog.peer);
+        if found.is_some() { break; }
+    }
+    // Everything walked past is cached too, so the next folder is likely a hit
+    peers.remember_all(&seen);
+    found.ok_or_else(|| DriveError::NotFound(format!("Folder/Chat {} not found", fid)))
+}
+
+/// Runs `op` against the folder's chat. If the chat turns out to be unavailable, its cached
+/// entry is dropped and `op` runs once more against the chat resolved afresh.
+pub async fn with_peer<T, F, Fut>(
+    client: &Client,
+    drive: &BotDrive,
+    peers: &PeerCache,
+    folder_id: Option<i64>,
+    mut op: F,
+) -> Result<T, DriveError>
+where
+    F: FnMut(Peer) -> Fut,
+    Fut: Future<Output = Result<T, DriveError>>,
+{
+    let peer = resolve_peer(client, drive, peers, folder_id).await?;
+    match peers.check(&peer, op(peer.clone()).await) {
+        Err(e) if e.is_channel_unavailable() => {
+            log::info!("Retrying with folder {:?} resolved again ({})", folder_id, e);
+            let peer = resolve_peer(client, drive, peers, folder_id).await?;
+            peers.check(&peer, op(peer.clone()).await)
         }
+        result => result,
     }
 }
 
+/// Looks up a channel by ID. Bots may pass an access hash of 0 for channels they are a member of.
+pub async fn channel_peer(client: &Client, channel_id: i64, access_hash: i64) -> Result<Peer, DriveError> {
+    let request = tl::functions::channels::GetChannels {
+        id: vec![tl::enums::InputChannel::Channel(tl::types::InputChannel { channel_id, access_hash })],
+    };
+    let result = with_retry("channels.getChannels", || client.invoke(&request)).await?;
+
+    let chats = match result {
+        tl::enums::messages::Chats::Chats(c) => c.chats,
+        tl::enums::messages::Chats::Slice(c) => c.chats,
+    };
+    let chat = chats.into_iter().next()
+        .ok_or_else(|| DriveError::NotFound(format!("Channel {} not found", channel_id)))?;
+    Ok(Peer::from_raw(chat))
+}
+
+/// Looks up a basic group by ID. Basic groups need no access hash.
+pub async fn group_peer(client: &Client, chat_id: i64) -> Result<Peer, DriveError> {
+    let request = tl::functions::messages::GetChats { id: vec![chat_id] };
+    let result = with_retry("messages.getChats", || client.invoke(&request)).await?;
+
+    let chats = match result {
+        tl::enums::messages::Chats::Chats(c) => c.chats,
+        tl::enums::messages::Chats::Slice(c) => c.chats,
+    };
+    let chat = chats.into_iter().next()
+        .ok_or_else(|| DriveError::NotFound(format!("Group {} not found", chat_id)))?;
+    Ok(Peer::from_raw(chat))
+}
+
 #[tauri::command]
 pub fn cmd_log(message: String) {
     log::info!("[FRONTEND] {}", message);
@@ -32,21 +126,3 @@ pub fn cmd_log(message: String) {
 pub fn cmd_get_bandwidth(bw_state: State<'_, BandwidthManager>) -> crate::bandwidth::BandwidthStats {
     bw_state.get_stats()
 }
-
-pub fn map_error(e: impl std::fmt::Display) -> String {
-    let err_str = e.to_string();
-    if err_str.contains("FLOOD_WAIT") {
-        // Expected format: ... (value: 1234)
-        if let Some(start) = err_str.find("(value: ") {
-             let rest = &err_str[start + 8..];
-             if let Some(end) = rest.find(')') {
-                 if let Ok(seconds) = rest[..end].parse::<i64>() {
-                     return format!("FLOOD_WAIT_{}", seconds);
-                 }
-             }
-        }
-        // Fallback if parsing fails but we know it's a flood wait
-        return "FLOOD_WAIT_60".to_string();
-    }
-    err_str
-}
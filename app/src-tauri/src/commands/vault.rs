@@ -0,0 +1,108 @@
+use tauri::{Manager, State};
+use tokio::time::Duration;
+use crate::TelegramState;
+use crate::accounts::AccountManager;
+use crate::error::DriveError;
+use crate::models::VaultStatus;
+use crate::vault::SessionVault;
+
+#[tauri::command]
+pub fn cmd_vault_status(vault: State<'_, SessionVault>) -> VaultStatus {
+    VaultStatus {
+        enabled: vault.is_enabled(),
+        unlocked: vault.is_unlocked(),
+    }
+}
+
+/// Unlock step on app start. Fails with "Wrong passphrase" without touching any session.
+#[tauri::command]
+pub async fn cmd_vault_unlock(passphrase: String, app_handle: tauri::AppHandle) -> Result<bool, DriveError> {
+    // Deriving the key takes a while on purpose, keep it off the async workers
+    tokio::task::spawn_blocking(move || app_handle.state::<SessionVault>().unlock(&passphrase))
+        .await
+        .map_err(|e| DriveError::Other(e.to_string()))??;
+    log::info!("Session vault unlocked");
+    Ok(true)
+}
+
+/// Turns on session encryption and seals every session that is not currently open
+#[tauri::command]
+pub async fn cmd_vault_enable(
+    passphrase: String,
+    app_handle: tauri::AppHandle,
+    state: State<'_, TelegramState>,
+    accounts: State<'_, AccountManager>,
+    vault: State<'_, SessionVault>,
+) -> Result<bool, DriveError> {
+    let handle = app_handle.clone();
+    tokio::task::spawn_blocking(move || handle.state::<SessionVault>().enable(&passphrase))
+        .await
+        .map_err(|e| DriveError::Other(e.to_string()))??;
+
+    let mut in_use: Vec<String> = state.parked.lock().await.keys().cloned().collect();
+    if state.client.lock().await.is_some() {
+        in_use.extend(accounts.active_name());
+    }
+    for account in accounts.list() {
+        let dir = accounts.account_dir(&account.name);
+        if in_use.contains(&account.name) {
+            // Still open: encrypt it now, the plain file goes when the app exits
+            vault.checkpoint(&dir)?;
+        } else {
+            vault.seal(&dir)?;
+        }
+    }
+    log::info!("Session encryption enabled");
+    Ok(true)
+}
+
+#[tauri::command]
+pub async fn cmd_vault_disable(passphrase: String, app_handle: tauri::AppHandle) -> Result<bool, DriveError> {
+    tokio::task::spawn_blocking(move || {
+        let accounts = app_handle.state::<AccountManager>();
+        let dirs: Vec<_> = accounts.list().iter().map(|a| accounts.account_dir(&a.name)).collect();
+        app_handle.state::<SessionVault>().disable(&passphrase, &dirs)
+    })
+    .await
+    .map_err(|e| DriveError::Other(e.to_string()))??;
+    log::info!("Session encryption disabled");
+    Ok(true)
+}
+
+/// Brings the sealed copy of the active account's session up to date, e.g. right after a login
+pub fn checkpoint_session(app_handle: &tauri::AppHandle) {
+    let Some(name) = app_handle.state::<AccountManager>().active_name() else { return };
+    let dir = app_handle.state::<AccountManager>().account_dir(&name);
+    if let Err(e) = app_handle.state::<SessionVault>().checkpoint(&dir) {
+        log::error!("Failed to encrypt session of '{}': {}", name, e);
+    }
+}
+
+/// Closes every client and encrypts all sessions, removing their decrypted copies. Called once when the app exits.
+pub fn seal_sessions(app_handle: &tauri::AppHandle) {
+    let vault = app_handle.state::<SessionVault>();
+    if vault.is_enabled() && !vault.is_unlocked() {
+        return;
+    }
+
+    let state = app_handle.state::<TelegramState>();
+    tauri::async_runtime::block_on(async {
+        if let Some(shutdown_tx) = state.runner_shutdown.lock().await.take() {
+            let _ = shutdown_tx.send(());
+        }
+        *state.client.lock().await = None;
+        *state.session.lock().await = None;
+        for (_, parked) in state.parked.lock().await.drain() {
+            let _ = parked.runner_shutdown.send(());
+        }
+        // Let the runners drop their session handles so SQLite closes the files
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    });
+
+    let accounts = app_handle.state::<AccountManager>();
+    for account in accounts.list() {
+        if let Err(e) = vault.seal(&accounts.account_dir(&account.name)) {
+            log::error!("Failed to encrypt session of '{}': {}", account.name, e);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use tauri::State;
+use crate::accounts::AccountManager;
+use crate::error::DriveError;
+use crate::paths::PathPolicy;
+use crate::watch::{AfterUpload, WatchManager, WatchRule};
+
+#[tauri::command]
+pub fn cmd_list_watch_rules(watches: State<'_, WatchManager>) -> Vec<WatchRule> {
+    watches.rules()
+}
+
+/// Starts uploading everything saved into `path` to `folder_id`
+#[tauri::command]
+pub fn cmd_add_watch_rule(
+    path: String,
+    folder_id: Option<i64>,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    after_upload: Option<AfterUpload>,
+    accounts: State<'_, AccountManager>,
+    policy: State<'_, PathPolicy>,
+    watches: State<'_, WatchManager>,
+) -> Result<WatchRule, DriveError> {
+    let after_upload = after_upload.unwrap_or(AfterUpload::Keep);
+    // Deleting or archiving uploaded files writes to the folder as well
+    let local_path = match after_upload {
+        AfterUpload::Keep => policy.readable(&path)?,
+        AfterUpload::Delete | AfterUpload::Archive => {
+            policy.readable(&path)?;
+            policy.writable(&path)?
+        }
+    };
+    let account = accounts.ensure_active().name;
+    watches.add_rule(account, local_path, folder_id, include.unwrap_or_default(), exclude.unwrap_or_default(), after_upload)
+}
+
+#[tauri::command]
+pub fn cmd_remove_watch_rule(id: u32, watches: State<'_, WatchManager>) {
+    watches.remove_rule(id);
+}
+
+#[tauri::command]
+pub fn cmd_set_watch_rule_enabled(id: u32, enabled: bool, watches: State<'_, WatchManager>) -> Result<WatchRule, DriveError> {
+    watches.set_enabled(id, enabled)
+}
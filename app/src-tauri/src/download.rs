@@ -0,0 +1,466 @@
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{Duration, Instant};
+use futures::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use base64::{Engine as _, engine::general_purpose};
+use grammers_client::Client;
+use grammers_client::types::{Media, Peer};
+use grammers_mtsender::InvocationError;
+use grammers_tl_types as tl;
+use crate::bandwidth::BandwidthManager;
+use crate::error::{DriveError, with_retry};
+use crate::messages::MessageCache;
+
+/// Bytes per `upload.getFile` request. Must divide 1 MB, and offsets stay multiples of it.
+pub const CHUNK_SIZE: usize = 512 * 1024;
+/// The `.part.json` sidecar is rewritten after this many bytes, or after `PROGRESS_INTERVAL`
+const PROGRESS_BYTES: u64 = 8 * 1024 * 1024;
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+/// Times one download renews an expired file reference before giving up
+const MAX_RENEWALS: u32 = 3;
+
+/// Progress of an unfinished download, stored next to the `.part` file
+#[derive(Debug, Serialize, Deserialize)]
+struct PartInfo {
+    message_id: i32,
+    file_id: i64,
+    size: Option<u64>,
+    offset: u64,
+    /// Base64 file reference the download was started with
+    file_reference: String,
+}
+
+/// A document or photo that can be fetched in ranges with `upload.getFile`
+pub struct RemoteFile {
+    pub file_id: i64,
+    /// Unknown for photos until the last chunk arrives
+    pub size: Option<u64>,
+    /// Both replaced by `renew` once the reference expires
+    file_reference: Mutex<Vec<u8>>,
+    location: Mutex<tl::enums::InputFileLocation>,
+    /// DC the file lives on once the home DC answered FILE_MIGRATE, 0 before that
+    migrated_dc: AtomicI32,
+}
+
+impl RemoteFile {
+    pub fn from_media(media: &Media) -> Option<Self> {
+        let (file_id, size, file_reference, location) = match media {
+            Media::Document(d) => {
+                let tl::enums::Document::Document(doc) = d.raw.document.clone()? else { return None };
+                let location = tl::enums::InputFileLocation::InputDocumentFileLocation(tl::types::InputDocumentFileLocation {
+                    id: doc.id,
+                    access_hash: doc.access_hash,
+                    file_reference: doc.file_reference.clone(),
+                    thumb_size: String::new(),
+                });
+                (doc.id, Some(doc.size as u64), doc.file_reference, location)
+            }
+            Media::Photo(p) => {
+                let tl::enums::Photo::Photo(photo) = p.raw.photo.clone()? else { return None };
+                let (thumb_size, size) = largest_photo_size(&photo.sizes)?;
+                let location = tl::enums::InputFileLocation::InputPhotoFileLocation(tl::types::InputPhotoFileLocation {
+                    id: photo.id,
+                    access_hash: photo.access_hash,
+                    file_reference: photo.file_reference.clone(),
+                    thumb_size,
+                });
+                (photo.id, size, photo.file_reference, location)
+            }
+            _ => return None,
+        };
+        Some(Self {
+            file_id,
+            size,
+            file_reference: Mutex::new(file_reference),
+            location: Mutex::new(location),
+            migrated_dc: AtomicI32::new(0),
+        })
+    }
+
+    /// Takes the fresh file reference from `media`, a newer copy of the same message.
+    /// Returns false if it holds a different file.
+    fn renew(&self, media: &Media) -> bool {
+        let Some(fresh) = Self::from_media(media) else { return false };
+        if fresh.file_id != self.file_id {
+            return false;
+        }
+        *self.file_reference.lock().unwrap() = fresh.file_reference.into_inner().unwrap();
+        *self.location.lock().unwrap() = fresh.location.into_inner().unwrap();
+        true
+    }
+
+    /// Fetches `limit` bytes at `offset`. Shorter results mean the end of the file.
+    pub async fn fetch_chunk(&self, client: &Client, offset: u64, limit: usize) -> Result<Vec<u8>, DriveError> {
+        with_retry("upload.getFile", || self.get_file(client, offset, limit)).await
+    }
+
+    async fn get_file(&self, client: &Client, offset: u64, limit: usize) -> Result<Vec<u8>, DriveError> {
+        let request = tl::functions::upload::GetFile {
+            precise: false,
+            cdn_supported: false,
+            location: self.location.lock().unwrap().clone(),
+            offset: offset as i64,
+            limit: limit as i32,
+        };
+
+        let result = match self.migrated_dc.load(Ordering::Relaxed) {
+            0 => client.invoke(&request).await,
+            dc_id => client.invoke_in_dc(dc_id, &request).await,
+        };
+        // Files stored on another DC are answered with FILE_MIGRATE_<dc>
+        let result = match result {
+            Err(InvocationError::Rpc(rpc)) if rpc.name == "FILE_MIGRATE" && rpc.value.is_some() => {
+                let dc_id = rpc.value.unwrap_or_default() as i32;
+                log::info!("File {} lives on DC {}", self.file_id, dc_id);
+                self.migrated_dc.store(dc_id, Ordering::Relaxed);
+                client.invoke_in_dc(dc_id, &request).await
+            }
+            other => other,
+        };
+
+        match result? {
+            tl::enums::upload::File::File(file) => Ok(file.bytes),
+            tl::enums::upload::File::CdnRedirect(_) => Err("Telegram redirected the download to a CDN, which is not supported".into()),
+        }
+    }
+}
+
+/// Fetches `start..size` with up to `parallelism` requests in flight.
+///
+/// Chunks are yielded in file order however the requests finish, so the caller can
+/// append them to a file or an HTTP body as they come.
+pub fn chunk_stream(
+    client: Client,
+    file: Arc<RemoteFile>,
+    start: u64,
+    size: u64,
+    parallelism: usize,
+) -> impl Stream<Item = Result<Vec<u8>, DriveError>> + Send + 'static {
+    stream::iter((start..size).step_by(CHUNK_SIZE))
+        .map(move |offset| {
+            let client = client.clone();
+            let file = file.clone();
+            async move { file.fetch_chunk(&client, offset, CHUNK_SIZE).await }
+        })
+        .buffered(parallelism.max(1))
+}
+
+/// The whole of `file`, through `chunk_stream` when its size is known.
+///
+/// Photo sizes are not always known, so then it reads one chunk at a time until a short one.
+pub fn file_stream(
+    client: Client,
+    file: Arc<RemoteFile>,
+    parallelism: usize,
+) -> impl Stream<Item = Result<Vec<u8>, DriveError>> + Send + 'static {
+    async_stream::try_stream! {
+        match file.size {
+            Some(size) => {
+                let mut chunks = Box::pin(chunk_stream(client, file, 0, size, parallelism));
+                while let Some(chunk) = chunks.next().await {
+                    yield chunk?;
+                }
+            }
+            None => {
+                let mut offset = 0;
+                loop {
+                    let chunk = file.fetch_chunk(&client, offset, CHUNK_SIZE).await?;
+                    offset += chunk.len() as u64;
+                    let last = chunk.len() < CHUNK_SIZE;
+                    yield chunk;
+                    if last { break; }
+                }
+            }
+        }
+    }
+}
+
+/// Picks the largest photo size, returning its type and byte size
+fn largest_photo_size(sizes: &[tl::enums::PhotoSize]) -> Option<(String, Option<u64>)> {
+    sizes.iter()
+        .filter_map(|s| match s {
+            tl::enums::PhotoSize::Size(s) => Some((s.w * s.h, s.r#type.clone(), Some(s.size as u64))),
+            tl::enums::PhotoSize::Progressive(s) => Some((s.w * s.h, s.r#type.clone(), s.sizes.last().map(|b| *b as u64))),
+            _ => None,
+        })
+        .max_by_key(|(area, _, _)| *area)
+        .map(|(_, kind, size)| (kind, size))
+}
+
+pub(crate) fn part_path(save_path: &Path) -> PathBuf {
+    let mut name = save_path.as_os_str().to_owned();
+    name.push(".part");
+    PathBuf::from(name)
+}
+
+fn sidecar_path(save_path: &Path) -> PathBuf {
+    let mut name = save_path.as_os_str().to_owned();
+    name.push(".part.json");
+    PathBuf::from(name)
+}
+
+/// Where an earlier attempt at the same file stopped, or 0
+fn resume_offset(save_path: &Path, file: &RemoteFile, message_id: i32) -> u64 {
+    let info: Option<PartInfo> = fs::read_to_string(sidecar_path(save_path))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok());
+    let Some(info) = info else { return 0 };
+    if info.message_id != message_id || info.file_id != file.file_id {
+        return 0;
+    }
+
+    // The sidecar is written after the data, so the part file may be ahead of it but never behind
+    let written = fs::metadata(part_path(save_path)).map(|m| m.len()).unwrap_or(0);
+    let offset = info.offset.min(written);
+    offset - offset % CHUNK_SIZE as u64
+}
+
+fn save_progress(save_path: &Path, file: &RemoteFile, message_id: i32, offset: u64) {
+    let info = PartInfo {
+        message_id,
+        file_id: file.file_id,
+        size: file.size,
+        offset,
+        file_reference: general_purpose::STANDARD.encode(&*file.file_reference.lock().unwrap()),
+    };
+    if let Ok(json) = serde_json::to_string(&info) {
+        let _ = fs::write(sidecar_path(save_path), json);
+    }
+}
+
+/// Writes the sidecar at most every `PROGRESS_BYTES` or `PROGRESS_INTERVAL`
+struct ProgressSaver {
+    offset: u64,
+    at: Instant,
+}
+
+impl ProgressSaver {
+    fn new(offset: u64) -> Self {
+        Self { offset, at: Instant::now() }
+    }
+
+    fn update(&mut self, save_path: &Path, file: &RemoteFile, message_id: i32, offset: u64) {
+        if offset - self.offset >= PROGRESS_BYTES || self.at.elapsed() >= PROGRESS_INTERVAL {
+            self.save(save_path, file, message_id, offset);
+        }
+    }
+
+    fn save(&mut self, save_path: &Path, file: &RemoteFile, message_id: i32, offset: u64) {
+        save_progress(save_path, file, message_id, offset);
+        *self = Self::new(offset);
+    }
+}
+
+/// The message a download comes from, so an expired file reference can be renewed
+pub struct FileSource<'a> {
+    pub peer: &'a Peer,
+    pub messages: &'a MessageCache,
+    pub message_id: i32,
+}
+
+impl FileSource<'_> {
+    /// Fetches the message again, bypassing the cache, and hands its file reference to `file`
+    async fn renew(&self, client: &Client, file: &RemoteFile) -> Result<(), DriveError> {
+        self.messages.forget(self.peer, &[self.message_id]);
+        let media = self.messages.get(client, self.peer, self.message_id).await?
+            .and_then(|msg| msg.media())
+            .ok_or_else(|| DriveError::NotFound(format!("File {} not found", self.message_id)))?;
+        if !file.renew(&media) {
+            return Err(DriveError::Other(format!("The file of message {} was replaced during the download", self.message_id)));
+        }
+        Ok(())
+    }
+}
+
+/// Telegram rejects file references after a while, the message then has a new one
+fn is_reference_expired(e: &DriveError) -> bool {
+    matches!(e, DriveError::Telegram { name, .. } if name.starts_with("FILE_REFERENCE_"))
+}
+
+/// Downloads `media` to `save_path` through `<save_path>.part`.
+///
+/// Progress is recorded in `<save_path>.part.json` every few megabytes and when the
+/// download fails, so a failed download picks up where it stopped next time.
+/// `save_path` only appears once the file is complete. An expired file reference is
+/// renewed from `source` and the download goes on from the same offset. Files of
+/// known size are fetched `parallelism` chunks at a time. Returns the file size.
+pub async fn download_to_path(
+    client: &Client,
+    bw_state: &BandwidthManager,
+    media: &Media,
+    source: &FileSource<'_>,
+    save_path: &Path,
+    parallelism: usize,
+) -> Result<u64, DriveError> {
+    let file = Arc::new(RemoteFile::from_media(media).ok_or("This message has no downloadable file")?);
+    let message_id = source.message_id;
+    let part = part_path(save_path);
+
+    let mut offset = resume_offset(save_path, &file, message_id);
+    if offset > 0 {
+        log::info!("Resuming download of message {} at {} bytes", message_id, offset);
+    }
+    let remaining = file.size.map(|s| s.saturating_sub(offset)).unwrap_or(CHUNK_SIZE as u64);
+    bw_state.can_transfer(remaining)?;
+
+    let mut out = OpenOptions::new().create(true).write(true).truncate(false).open(&part)?;
+    out.set_len(offset)?;
+    out.seek(SeekFrom::Start(offset))?;
+
+    let mut progress = ProgressSaver::new(offset);
+    let mut renewals = 0;
+    loop {
+        let fetched = fetch_rest(client, bw_state, &file, &mut out, &mut offset, parallelism, |offset| {
+            progress.update(save_path, &file, message_id, offset);
+        }).await;
+        match fetched {
+            Ok(()) => break,
+            Err(e) if is_reference_expired(&e) && renewals < MAX_RENEWALS => {
+                log::info!("File reference of message {} expired at {} bytes, renewing", message_id, offset);
+                renewals += 1;
+                if let Err(e) = source.renew(client, &file).await {
+                    progress.save(save_path, &file, message_id, offset);
+                    return Err(e);
+                }
+            }
+            Err(e) => {
+                progress.save(save_path, &file, message_id, offset);
+                return Err(e);
+            }
+        }
+    }
+
+    out.sync_all()?;
+    drop(out);
+    fs::rename(&part, save_path)?;
+    let _ = fs::remove_file(sidecar_path(save_path));
+    Ok(offset)
+}
+
+/// Appends `file` from `offset` to the end to `out`, advancing `offset` with every chunk written
+async fn fetch_rest(
+    client: &Client,
+    bw_state: &BandwidthManager,
+    file: &Arc<RemoteFile>,
+    out: &mut fs::File,
+    offset: &mut u64,
+    parallelism: usize,
+    mut on_chunk: impl FnMut(u64),
+) -> Result<(), DriveError> {
+    match file.size {
+        Some(size) => {
+            let mut chunks = chunk_stream(client.clone(), file.clone(), *offset, size, parallelism);
+            while let Some(chunk) = chunks.next().await {
+                let chunk = chunk?;
+                // Only the last chunk may come back short, anything else would shift the rest of the file
+                if chunk.len() < CHUNK_SIZE && *offset + (chunk.len() as u64) < size {
+                    return Err(DriveError::Io(format!("Telegram returned a short chunk at offset {}", offset)));
+                }
+                out.write_all(&chunk)?;
+                *offset += chunk.len() as u64;
+                bw_state.add_down(chunk.len() as u64);
+                on_chunk(*offset);
+            }
+        }
+        // Photo sizes are not always known, so read until a short chunk
+        None => loop {
+            let chunk = file.fetch_chunk(client, *offset, CHUNK_SIZE).await?;
+            out.write_all(&chunk)?;
+            *offset += chunk.len() as u64;
+            bw_state.add_down(chunk.len() as u64);
+            on_chunk(*offset);
+            if chunk.len() < CHUNK_SIZE { break; }
+        },
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHUNK: u64 = CHUNK_SIZE as u64;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("telegram-drive-test-download-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::canonicalize(dir).unwrap()
+    }
+
+    fn remote_file(file_id: i64) -> RemoteFile {
+        let location = tl::enums::InputFileLocation::InputDocumentFileLocation(tl::types::InputDocumentFileLocation {
+            id: file_id,
+            access_hash: 0,
+            file_reference: vec![1, 2, 3],
+            thumb_size: String::new(),
+        });
+        RemoteFile {
+            file_id,
+            size: Some(10 * CHUNK),
+            file_reference: Mutex::new(vec![1, 2, 3]),
+            location: Mutex::new(location),
+            migrated_dc: AtomicI32::new(0),
+        }
+    }
+
+    #[test]
+    fn part_files_sit_next_to_the_target() {
+        let save_path = Path::new("/downloads/report.pdf");
+        assert_eq!(part_path(save_path), Path::new("/downloads/report.pdf.part"));
+        assert_eq!(sidecar_path(save_path), Path::new("/downloads/report.pdf.part.json"));
+    }
+
+    #[test]
+    fn resumes_at_the_last_whole_chunk_on_disk() {
+        let dir = test_dir("resume");
+        let save_path = dir.join("video.mp4");
+        let file = remote_file(7);
+        assert_eq!(resume_offset(&save_path, &file, 42), 0);
+
+        // The sidecar may lag behind the data, but the data never lags behind the sidecar
+        fs::write(part_path(&save_path), vec![0u8; (3 * CHUNK + 100) as usize]).unwrap();
+        save_progress(&save_path, &file, 42, 2 * CHUNK);
+        assert_eq!(resume_offset(&save_path, &file, 42), 2 * CHUNK);
+        save_progress(&save_path, &file, 42, 5 * CHUNK);
+        assert_eq!(resume_offset(&save_path, &file, 42), 3 * CHUNK);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn progress_of_another_file_is_ignored() {
+        let dir = test_dir("other");
+        let save_path = dir.join("video.mp4");
+        fs::write(part_path(&save_path), vec![0u8; (2 * CHUNK) as usize]).unwrap();
+        save_progress(&save_path, &remote_file(7), 42, 2 * CHUNK);
+
+        assert_eq!(resume_offset(&save_path, &remote_file(7), 43), 0);
+        assert_eq!(resume_offset(&save_path, &remote_file(8), 42), 0);
+        fs::write(sidecar_path(&save_path), "{ not json").unwrap();
+        assert_eq!(resume_offset(&save_path, &remote_file(7), 42), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn progress_is_saved_every_few_megabytes() {
+        let dir = test_dir("saver");
+        let save_path = dir.join("video.mp4");
+        let file = remote_file(7);
+        fs::write(part_path(&save_path), vec![0u8; (PROGRESS_BYTES + CHUNK) as usize]).unwrap();
+        let mut saver = ProgressSaver::new(0);
+
+        saver.update(&save_path, &file, 42, CHUNK);
+        assert!(!sidecar_path(&save_path).exists());
+        saver.update(&save_path, &file, 42, PROGRESS_BYTES);
+        assert_eq!(resume_offset(&save_path, &file, 42), PROGRESS_BYTES);
+        saver.update(&save_path, &file, 42, PROGRESS_BYTES + CHUNK);
+        assert_eq!(resume_offset(&save_path, &file, 42), PROGRESS_BYTES);
+        saver.save(&save_path, &file, 42, PROGRESS_BYTES + CHUNK);
+        assert_eq!(resume_offset(&save_path, &file, 42), PROGRESS_BYTES + CHUNK);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,325 @@
+use std::fmt;
+use std::future::Future;
+use std::sync::OnceLock;
+use grammers_client::SignInError;
+use grammers_mtsender::InvocationError;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use tauri::Emitter;
+use tokio::time::Duration;
+
+/// Flood waits longer than this are returned to the caller instead of slept through
+const MAX_FLOOD_WAIT_SECS: u32 = 300;
+/// Attempts for a single operation, counting the first one
+const MAX_ATTEMPTS: u32 = 5;
+/// Backoff for internal server errors doubles from here: 1s, 2s, 4s, ...
+const SERVER_ERROR_BACKOFF_SECS: u32 = 1;
+
+/// Error returned by the file and auth commands.
+///
+/// Serialized as `{ "kind": "FloodWait", "message": "...", "seconds": 42 }` so the
+/// frontend can branch on `kind` and still show `message` as-is.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DriveError {
+    NotConnected,
+    AuthRequired,
+    FloodWait { seconds: u32 },
+    NotFound(String),
+    /// The chat was deleted, this account lost access to it, or its access hash went stale.
+    /// `name` is the RPC error, e.g. `CHANNEL_PRIVATE`.
+    ChannelUnavailable { name: String },
+    BandwidthExceeded(String),
+    FileTooLarge { size: u64, limit: u64 },
+    /// A local path outside what the user picked or approved (see `PathPolicy`)
+    PathNotAllowed(String),
+    Io(String),
+    /// Any other RPC error, e.g. `CHAT_WRITE_FORBIDDEN`
+    Telegram { code: i32, name: String },
+    Other(String),
+}
+
+impl DriveError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DriveError::NotConnected => "NotConnected",
+            DriveError::AuthRequired => "AuthRequired",
+            DriveError::FloodWait { .. } => "FloodWait",
+            DriveError::NotFound(_) => "NotFound",
+            DriveError::ChannelUnavailable { .. } => "ChannelUnavailable",
+            DriveError::BandwidthExceeded(_) => "BandwidthExceeded",
+            DriveError::FileTooLarge { .. } => "FileTooLarge",
+            DriveError::PathNotAllowed(_) => "PathNotAllowed",
+            DriveError::Io(_) => "Io",
+            DriveError::Telegram { .. } => "Telegram",
+            DriveError::Other(_) => "Other",
+        }
+    }
+
+    /// The channel was deleted or this account lost access to it
+    pub fn is_channel_unavailable(&self) -> bool {
+        matches!(self, DriveError::ChannelUnavailable { .. })
+    }
+
+    /// How long to wait before retrying, or `None` if the error is not transient.
+    ///
+    /// A server error does not say whether the request was applied, so only
+    /// `idempotent` requests are retried after one. Flood waits are always safe.
+    fn retry_delay(&self, attempt: u32, idempotent: bool) -> Option<u32> {
+        match self {
+            DriveError::FloodWait { seconds } if *seconds <= MAX_FLOOD_WAIT_SECS => Some(*seconds),
+            DriveError::Telegram { code, .. } if idempotent && (*code >= 500 || *code == -503) => {
+                Some(SERVER_ERROR_BACKOFF_SECS << (attempt - 1))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DriveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DriveError::NotConnected => write!(f, "Not connected to Telegram"),
+            DriveError::AuthRequired => write!(f, "Not logged in to Telegram"),
+            DriveError::FloodWait { seconds } => write!(f, "Too many requests, try again in {} seconds", seconds),
+            DriveError::NotFound(what) => write!(f, "{}", what),
+            DriveError::ChannelUnavailable { name } => write!(f, "Folder is no longer available on Telegram ({})", name),
+            DriveError::BandwidthExceeded(msg) => write!(f, "{}", msg),
+            DriveError::FileTooLarge { size, limit } => {
+                write!(f, "File is too large ({} MB, the limit is {} MB)", size / (1024 * 1024), limit / (1024 * 1024))
+            }
+            DriveError::PathNotAllowed(path) => {
+                write!(f, "Access to {} is not allowed. Pick it in a dialog or add its folder to the allowed folders", path)
+            }
+            DriveError::Io(msg) => write!(f, "{}", msg),
+            DriveError::Telegram { code, name } => write!(f, "Telegram error {}: {}", code, name),
+            DriveError::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DriveError {}
+
+impl Serialize for DriveError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("kind", self.kind())?;
+        map.serialize_entry("message", &self.to_string())?;
+        match self {
+            DriveError::FloodWait { seconds } => map.serialize_entry("seconds", seconds)?,
+            DriveError::FileTooLarge { size, limit } => {
+                map.serialize_entry("size", size)?;
+                map.serialize_entry("limit", limit)?;
+            }
+            DriveError::ChannelUnavailable { name } => map.serialize_entry("name", name)?,
+            DriveError::Telegram { code, name } => {
+                map.serialize_entry("code", code)?;
+                map.serialize_entry("name", name)?;
+            }
+            _ => {}
+        }
+        map.end()
+    }
+}
+
+impl From<InvocationError> for DriveError {
+    fn from(e: InvocationError) -> Self {
+        match e {
+            InvocationError::Rpc(rpc) => match (rpc.code, rpc.name.as_str()) {
+                (420, _) | (_, "FLOOD_WAIT") | (_, "FLOOD_PREMIUM_WAIT") => {
+                    DriveError::FloodWait { seconds: rpc.value.unwrap_or(1) }
+                }
+                (401, _) => DriveError::AuthRequired,
+                (_, "MESSAGE_ID_INVALID") => DriveError::NotFound(format!("Not found on Telegram ({})", rpc.name)),
+                (_, name @ ("CHANNEL_INVALID" | "CHANNEL_PRIVATE" | "CHAT_ID_INVALID" | "PEER_ID_INVALID")) => {
+                    DriveError::ChannelUnavailable { name: name.to_string() }
+                }
+                (code, name) => DriveError::Telegram { code, name: name.to_string() },
+            },
+            other => DriveError::Io(other.to_string()),
+        }
+    }
+}
+
+impl From<SignInError> for DriveError {
+    fn from(e: SignInError) -> Self {
+        match e {
+            SignInError::Other(e) => e.into(),
+            other => DriveError::Other(other.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for DriveError {
+    fn from(e: std::io::Error) -> Self {
+        DriveError::Io(e.to_string())
+    }
+}
+
+impl From<String> for DriveError {
+    fn from(msg: String) -> Self {
+        DriveError::Other(msg)
+    }
+}
+
+impl From<&str> for DriveError {
+    fn from(msg: &str) -> Self {
+        DriveError::Other(msg.to_string())
+    }
+}
+
+/// Lets the CLI, and helpers that report plain messages, use `?` on a `DriveError`
+impl From<DriveError> for String {
+    fn from(e: DriveError) -> Self {
+        e.to_string()
+    }
+}
+
+/// Payload of the `telegram-retry` event
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RetryNotice {
+    pub operation: String,
+    pub seconds: u32,
+    pub attempt: u32,
+    pub reason: &'static str,
+}
+
+/// Where retry notices go. Unset in the CLI, which only logs them.
+static RETRY_EVENTS: OnceLock<tauri::AppHandle> = OnceLock::new();
+
+pub fn set_retry_event_target(app_handle: tauri::AppHandle) {
+    let _ = RETRY_EVENTS.set(app_handle);
+}
+
+/// Runs `op`, sleeping through flood waits and transient server errors before trying again.
+///
+/// Only for requests that can safely run twice: reads, file parts, deletions.
+/// Each wait is announced as a `telegram-retry` event so the UI can show a countdown.
+pub async fn with_retry<T, E, F, Fut>(operation: &str, op: F) -> Result<T, DriveError>
+where
+    E: Into<DriveError>,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    retry(operation, true, op).await
+}
+
+/// Like `with_retry`, but for requests that must not be applied twice, such as
+/// sending or forwarding a message: only flood waits, which reject the request, are retried.
+pub async fn with_flood_retry<T, E, F, Fut>(operation: &str, op: F) -> Result<T, DriveError>
+where
+    E: Into<DriveError>,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    retry(operation, false, op).await
+}
+
+async fn retry<T, E, F, Fut>(operation: &str, idempotent: bool, mut op: F) -> Result<T, DriveError>
+where
+    E: Into<DriveError>,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 1;
+    loop {
+        let err: DriveError = match op().await {
+            Ok(value) => return Ok(value),
+            Err(e) => e.into(),
+        };
+
+        let delay = match err.retry_delay(attempt, idempotent) {
+            Some(delay) if attempt < MAX_ATTEMPTS => delay,
+            _ => return Err(err),
+        };
+        let notice = RetryNotice {
+            operation: operation.to_string(),
+            seconds: delay,
+            attempt,
+            reason: if matches!(err, DriveError::FloodWait { .. }) { "flood_wait" } else { "server_error" },
+        };
+        log::warn!("{} failed ({}), retrying in {}s (attempt {}/{})", operation, err, delay, attempt, MAX_ATTEMPTS);
+        if let Some(app_handle) = RETRY_EVENTS.get() {
+            let _ = app_handle.emit("telegram-retry", &notice);
+        }
+
+        tokio::time::sleep(Duration::from_secs(delay as u64)).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn server_error(code: i32) -> DriveError {
+        DriveError::Telegram { code, name: "INTERNAL".to_string() }
+    }
+
+    #[test]
+    fn flood_waits_wait_the_requested_time() {
+        let flood = DriveError::FloodWait { seconds: 42 };
+        assert_eq!(flood.retry_delay(1, true), Some(42));
+        assert_eq!(flood.retry_delay(3, false), Some(42));
+        assert_eq!(DriveError::FloodWait { seconds: MAX_FLOOD_WAIT_SECS }.retry_delay(1, true), Some(MAX_FLOOD_WAIT_SECS));
+        assert_eq!(DriveError::FloodWait { seconds: MAX_FLOOD_WAIT_SECS + 1 }.retry_delay(1, true), None);
+    }
+
+    #[test]
+    fn server_errors_back_off_for_idempotent_requests_only() {
+        assert_eq!(server_error(500).retry_delay(1, true), Some(1));
+        assert_eq!(server_error(500).retry_delay(2, true), Some(2));
+        assert_eq!(server_error(-503).retry_delay(4, true), Some(8));
+        assert_eq!(server_error(500).retry_delay(1, false), None);
+        assert_eq!(server_error(-503).retry_delay(1, false), None);
+    }
+
+    #[test]
+    fn other_errors_are_never_retried() {
+        for e in [
+            DriveError::NotConnected,
+            DriveError::AuthRequired,
+            DriveError::NotFound("message".to_string()),
+            DriveError::ChannelUnavailable { name: "CHANNEL_PRIVATE".to_string() },
+            DriveError::BandwidthExceeded("limit".to_string()),
+            DriveError::FileTooLarge { size: 2, limit: 1 },
+            DriveError::PathNotAllowed("/etc".to_string()),
+            DriveError::Io("broken pipe".to_string()),
+            DriveError::Telegram { code: 400, name: "CHAT_WRITE_FORBIDDEN".to_string() },
+            DriveError::Other("other".to_string()),
+        ] {
+            assert_eq!(e.retry_delay(1, true), None, "{:?}", e);
+        }
+    }
+
+    /// Runs `retry`, failing with `error` until `failures` attempts are used up. Returns the result and the attempts made.
+    async fn attempts(idempotent: bool, error: DriveError, failures: u32) -> (Result<u32, DriveError>, u32) {
+        let calls = Cell::new(0);
+        let result = retry("test", idempotent, || {
+            calls.set(calls.get() + 1);
+            let call = calls.get();
+            let error = error.clone();
+            async move { if call <= failures { Err(error) } else { Ok(call) } }
+        }).await;
+        (result, calls.get())
+    }
+
+    #[tokio::test]
+    async fn retries_until_the_request_succeeds() {
+        let flood = DriveError::FloodWait { seconds: 0 };
+        assert_eq!(attempts(true, flood.clone(), 2).await, (Ok(3), 3));
+        assert_eq!(attempts(false, flood, MAX_ATTEMPTS - 1).await, (Ok(MAX_ATTEMPTS), MAX_ATTEMPTS));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_attempt_limit() {
+        let flood = DriveError::FloodWait { seconds: 0 };
+        assert_eq!(attempts(true, flood.clone(), u32::MAX).await, (Err(flood), MAX_ATTEMPTS));
+    }
+
+    #[tokio::test]
+    async fn permanent_errors_and_unsafe_retries_fail_at_once() {
+        let not_found = DriveError::NotFound("message".to_string());
+        assert_eq!(attempts(true, not_found.clone(), 1).await, (Err(not_found), 1));
+        assert_eq!(attempts(false, server_error(500), 1).await, (Err(server_error(500)), 1));
+    }
+}
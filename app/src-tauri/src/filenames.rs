@@ -0,0 +1,169 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+
+/// Longest file name most filesystems accept, in bytes
+const MAX_NAME_BYTES: usize = 255;
+/// Device names Windows refuses as file names, with or without an extension
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// What to do when a download would land on an existing file
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CollisionStrategy {
+    Overwrite,
+    Skip,
+    /// Save as `name (1).ext`, `name (2).ext`, ...
+    Rename,
+}
+
+impl FromStr for CollisionStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "overwrite" => Ok(CollisionStrategy::Overwrite),
+            "skip" => Ok(CollisionStrategy::Skip),
+            "rename" => Ok(CollisionStrategy::Rename),
+            _ => Err(format!("Unknown collision strategy '{}' (overwrite, skip or rename)", s)),
+        }
+    }
+}
+
+/// Turns a name from Telegram into one that is safe to create in a local directory.
+///
+/// Path separators, characters Windows reserves and control characters become `_`,
+/// trailing dots and spaces are dropped, device names like `CON` get a `_` prefix,
+/// and overly long names are shortened while keeping the extension. Never empty.
+pub fn sanitize_filename(name: &str) -> String {
+    let mut clean: String = name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    clean = clean.trim_start().trim_end_matches(['.', ' ']).to_string();
+
+    // Also catches "." and "..", which lose their dots above
+    if clean.is_empty() {
+        return "file".to_string();
+    }
+    let stem = clean.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        clean.insert(0, '_');
+    }
+    truncate_name(&clean)
+}
+
+/// A file extension from Telegram, reduced to letters and digits, or `None` if nothing is left
+pub fn sanitize_extension(ext: &str) -> Option<String> {
+    let clean: String = ext.chars().filter(|c| c.is_ascii_alphanumeric()).take(16).collect();
+    (!clean.is_empty()).then_some(clean)
+}
+
+/// `name` with ` (n)` inserted before the extension, or `name` itself for 0
+pub fn numbered_name(name: &str, n: usize) -> String {
+    if n == 0 {
+        return name.to_string();
+    }
+    match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{} ({}).{}", stem, n, ext),
+        _ => format!("{} ({})", name, n),
+    }
+}
+
+/// Applies `strategy` to `path`, returning where to write, or `None` to leave the existing file alone
+pub fn resolve_collision(path: &Path, strategy: CollisionStrategy) -> Option<PathBuf> {
+    if !path.exists() {
+        return Some(path.to_path_buf());
+    }
+    match strategy {
+        CollisionStrategy::Overwrite => Some(path.to_path_buf()),
+        CollisionStrategy::Skip => None,
+        CollisionStrategy::Rename => {
+            let name = path.file_name()?.to_string_lossy().to_string();
+            (1..)
+                .map(|n| path.with_file_name(numbered_name(&name, n)))
+                .find(|candidate| !candidate.exists())
+        }
+    }
+}
+
+fn truncate_name(name: &str) -> String {
+    if name.len() <= MAX_NAME_BYTES {
+        return name.to_string();
+    }
+    let ext = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && ext.len() < 32 => format!(".{}", ext),
+        _ => String::new(),
+    };
+    let mut stem_end = MAX_NAME_BYTES - ext.len();
+    while !name.is_char_boundary(stem_end) {
+        stem_end -= 1;
+    }
+    format!("{}{}", &name[..stem_end], ext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_separators_and_reserved_characters() {
+        assert_eq!(sanitize_filename("../../etc/passwd"), ".._.._etc_passwd");
+        assert_eq!(sanitize_filename("a\\b:c*d?e\"f<g>h|i"), "a_b_c_d_e_f_g_h_i");
+        assert_eq!(sanitize_filename("line\nbreak\u{0}.txt"), "line_break_.txt");
+    }
+
+    #[test]
+    fn trims_dots_and_spaces() {
+        assert_eq!(sanitize_filename("  report.pdf. . "), "report.pdf");
+        assert_eq!(sanitize_filename(".hidden"), ".hidden");
+        assert_eq!(sanitize_filename(".."), "file");
+        assert_eq!(sanitize_filename("   "), "file");
+        assert_eq!(sanitize_filename(""), "file");
+    }
+
+    #[test]
+    fn prefixes_windows_device_names() {
+        assert_eq!(sanitize_filename("CON"), "_CON");
+        assert_eq!(sanitize_filename("nul.txt"), "_nul.txt");
+        assert_eq!(sanitize_filename("com1.tar.gz"), "_com1.tar.gz");
+        assert_eq!(sanitize_filename("console.log"), "console.log");
+    }
+
+    #[test]
+    fn shortens_long_names_but_keeps_the_extension() {
+        let long = format!("{}.mp4", "a".repeat(300));
+        let clean = sanitize_filename(&long);
+        assert_eq!(clean.len(), MAX_NAME_BYTES);
+        assert!(clean.ends_with("a.mp4"));
+
+        // Never cuts a character in half
+        let wide = format!("{}.txt", "é".repeat(200));
+        let clean = sanitize_filename(&wide);
+        assert!(clean.len() <= MAX_NAME_BYTES);
+        assert!(clean.ends_with("é.txt"));
+    }
+
+    #[test]
+    fn numbers_before_the_extension() {
+        assert_eq!(numbered_name("report.pdf", 0), "report.pdf");
+        assert_eq!(numbered_name("report.pdf", 1), "report (1).pdf");
+        assert_eq!(numbered_name("archive.tar.gz", 2), "archive.tar (2).gz");
+        assert_eq!(numbered_name("README", 3), "README (3)");
+        assert_eq!(numbered_name(".bashrc", 1), ".bashrc (1)");
+    }
+
+    #[test]
+    fn keeps_only_plain_extensions() {
+        assert_eq!(sanitize_extension("mp4"), Some("mp4".to_string()));
+        assert_eq!(sanitize_extension("../exe"), Some("exe".to_string()));
+        assert_eq!(sanitize_extension("./"), None);
+    }
+}
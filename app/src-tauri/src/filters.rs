@@ -0,0 +1,102 @@
+use glob::{MatchOptions, Pattern};
+use crate::error::DriveError;
+
+/// `*` also crosses `/`, so `*.jpg` matches JPEGs at any depth
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+/// Include and exclude globs for local files.
+///
+/// Patterns are matched against the path relative to the directory being
+/// transferred (with `/` separators) and against the bare file name, so both
+/// `photos/raw/*` and `node_modules` work as expected.
+#[derive(Debug, Default)]
+pub struct PathFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl PathFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, DriveError> {
+        Ok(Self {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    /// Excluded directories are not descended into
+    pub fn is_excluded(&self, relative: &str) -> bool {
+        matches_any(&self.exclude, relative)
+    }
+
+    /// Files must match an include pattern, if there are any, and no exclude pattern
+    pub fn accepts(&self, relative: &str) -> bool {
+        (self.include.is_empty() || matches_any(&self.include, relative)) && !self.is_excluded(relative)
+    }
+}
+
+fn compile(globs: &[String]) -> Result<Vec<Pattern>, DriveError> {
+    globs.iter()
+        .map(|g| Pattern::new(g).map_err(|e| DriveError::Other(format!("Invalid pattern '{}': {}", g, e))))
+        .collect()
+}
+
+fn matches_any(patterns: &[Pattern], relative: &str) -> bool {
+    let name = relative.rsplit('/').next().unwrap_or(relative);
+    patterns.iter().any(|p| p.matches_with(relative, MATCH_OPTIONS) || p.matches_with(name, MATCH_OPTIONS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(include: &[&str], exclude: &[&str]) -> PathFilter {
+        let strings = |globs: &[&str]| globs.iter().map(|g| g.to_string()).collect::<Vec<_>>();
+        PathFilter::new(&strings(include), &strings(exclude)).unwrap()
+    }
+
+    #[test]
+    fn accepts_everything_without_patterns() {
+        let f = filter(&[], &[]);
+        assert!(f.accepts("a.txt"));
+        assert!(f.accepts("deep/down/b.bin"));
+        assert!(!f.is_excluded("deep"));
+    }
+
+    #[test]
+    fn star_crosses_directories() {
+        let f = filter(&["*.jpg"], &[]);
+        assert!(f.accepts("a.jpg"));
+        assert!(f.accepts("2024/summer/b.jpg"));
+        assert!(!f.accepts("2024/summer/b.png"));
+        // Matching is case-sensitive
+        assert!(!f.accepts("C.JPG"));
+    }
+
+    #[test]
+    fn matches_relative_paths_and_bare_names() {
+        let f = filter(&[], &["node_modules", "photos/raw/*"]);
+        assert!(f.is_excluded("node_modules"));
+        assert!(f.is_excluded("app/node_modules"));
+        assert!(f.is_excluded("photos/raw/img.cr2"));
+        assert!(!f.is_excluded("photos/edited/img.jpg"));
+        assert!(!f.is_excluded("node_modules_backup"));
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let f = filter(&["*.log"], &["debug.log"]);
+        assert!(f.accepts("server.log"));
+        assert!(!f.accepts("logs/debug.log"));
+        assert!(!f.accepts("notes.txt"));
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        assert!(PathFilter::new(&["a[".to_string()], &[]).is_err());
+        assert!(PathFilter::new(&[], &["a[".to_string()]).is_err());
+    }
+}
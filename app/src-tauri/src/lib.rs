@@ -2,9 +2,32 @@ pub mod models;
 
 pub mod commands;
 pub mod bandwidth;
+pub mod bot;
+pub mod peers;
+pub mod messages;
+pub mod download;
+pub mod upload;
+pub mod transfers;
+pub mod filters;
+pub mod filenames;
+pub mod atomic;
+pub mod archive;
+pub mod live;
+pub mod watch;
+pub mod schedule;
+pub mod backups;
+pub mod chunker;
+pub mod snapshots;
+pub mod restic;
+pub mod paths;
+pub mod accounts;
+pub mod proxy;
+pub mod vault;
+pub mod error;
 
 use tauri::Manager;
 use tokio::sync::Mutex;
+use std::collections::HashMap;
 use std::sync::Arc;
 use commands::TelegramState;
 
@@ -33,19 +56,36 @@ pub fn run() {
                 api_id: Arc::new(Mutex::new(None)),
                 runner_shutdown: Arc::new(Mutex::new(None)),
                 runner_count: Arc::new(std::sync::atomic::AtomicU32::new(0)),
+                parked: Arc::new(Mutex::new(HashMap::new())),
+                session: Arc::new(Mutex::new(None)),
+                updates: tokio::sync::broadcast::channel(64).0,
+                qr_login: Arc::new(Mutex::new(None)),
             });
+            error::set_retry_event_target(app.handle().clone());
+            app.manage(accounts::AccountManager::new(app.handle()));
             app.manage(bandwidth::BandwidthManager::new(app.handle()));
+            app.manage(bot::BotDrive::new(app.handle()));
+            app.manage(peers::PeerCache::new(app.handle()));
+            app.manage(messages::MessageCache::new());
+            app.manage(live::LiveFolders::new());
+            app.manage(transfers::TransferManager::new(app.handle()));
+            app.manage(paths::PathPolicy::new(app.handle()));
+            app.manage(watch::WatchManager::new(app.handle()));
+            app.manage(backups::BackupManager::new(app.handle()));
+            app.manage(snapshots::SnapshotManager::new());
+            app.manage(restic::ResticRepo::new(app.handle()));
+            app.manage(proxy::ProxyManager::new(app.handle()));
+            app.manage(vault::SessionVault::new(app.handle()));
+            app.manage(server::StreamAuth::new());
+            app.manage(server::StreamServer::new());
             
-            // Start Streaming Server on dedicated thread (Actix needs its own runtime)
-            let state = Arc::new(app.state::<TelegramState>().inner().clone());
-            std::thread::spawn(move || {
-                let sys = actix_rt::System::new();
-                sys.block_on(async move {
-                    if let Err(e) = server::start_server(state, 14200).await {
-                        log::error!("Streaming server failed: {}", e);
-                    }
-                });
-            });
+            // Start Streaming Server (falls back to a free port if the default is taken)
+            server::spawn_server(app.handle().clone());
+            // Push folder changes made on other devices to the file list
+            live::spawn_watcher(app.handle().clone());
+            // Upload files saved into watched folders
+            watch::spawn_worker(app.handle().clone());
+            backups::spawn_scheduler(app.handle().clone());
             
             Ok(())
         })
@@ -53,12 +93,19 @@ pub fn run() {
             commands::cmd_auth_request_code,
             commands::cmd_auth_sign_in,
             commands::cmd_auth_check_password,
+            commands::cmd_auth_qr_start,
+            commands::cmd_auth_qr_cancel,
+            commands::cmd_auth_bot_sign_in,
+            commands::cmd_bot_add_folder,
             commands::cmd_get_files,
             commands::cmd_upload_file,
+            commands::cmd_upload_directory,
             commands::cmd_connect,
             commands::cmd_log,
             commands::cmd_delete_file,
             commands::cmd_download_file,
+            commands::cmd_download_folder,
+            commands::cmd_download_zip,
             commands::cmd_move_files,
             commands::cmd_create_folder,
             commands::cmd_delete_folder,
@@ -69,9 +116,58 @@ pub fn run() {
             commands::cmd_search_global,
             commands::cmd_check_connection,
             commands::cmd_is_network_available,
+            commands::cmd_get_proxy,
+            commands::cmd_set_proxy,
+            commands::cmd_get_transfer_settings,
+            commands::cmd_set_transfer_settings,
             commands::cmd_clean_cache,
             commands::cmd_get_thumbnail,
+            commands::cmd_get_stream_url,
+            commands::cmd_get_zip_url,
+            commands::cmd_get_stream_port,
+            commands::cmd_restart_stream_server,
+            commands::cmd_list_accounts,
+            commands::cmd_add_account,
+            commands::cmd_switch_account,
+            commands::cmd_remove_account,
+            commands::cmd_vault_status,
+            commands::cmd_vault_unlock,
+            commands::cmd_vault_enable,
+            commands::cmd_vault_disable,
+            commands::cmd_pick_files,
+            commands::cmd_pick_folder,
+            commands::cmd_pick_save_path,
+            commands::cmd_list_allowed_folders,
+            commands::cmd_add_allowed_folder,
+            commands::cmd_remove_allowed_folder,
+            commands::cmd_list_watch_rules,
+            commands::cmd_add_watch_rule,
+            commands::cmd_remove_watch_rule,
+            commands::cmd_set_watch_rule_enabled,
+            commands::cmd_list_backup_jobs,
+            commands::cmd_add_backup_job,
+            commands::cmd_remove_backup_job,
+            commands::cmd_set_backup_job_enabled,
+            commands::cmd_run_backup_job,
+            commands::cmd_list_backup_runs,
+            commands::cmd_get_backup_run_log,
+            commands::cmd_create_snapshot,
+            commands::cmd_list_snapshots,
+            commands::cmd_diff_snapshots,
+            commands::cmd_restore_snapshot,
+            commands::cmd_forget_snapshot,
+            commands::cmd_prune_snapshots,
+            commands::cmd_rebuild_snapshot_index,
+            commands::cmd_get_restic_repo,
+            commands::cmd_enable_restic_repo,
+            commands::cmd_disable_restic_repo,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app_handle, event| {
+            // Encrypted sessions must not stay readable after the app closes
+            if let tauri::RunEvent::Exit = event {
+                commands::seal_sessions(app_handle);
+            }
+        });
 }
@@ -0,0 +1,205 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use grammers_client::Client;
+use grammers_client::types::{Message, Peer};
+use grammers_client::update::Update;
+use grammers_tl_types as tl;
+use tauri::{Emitter, Manager};
+use tokio::sync::broadcast;
+use crate::accounts::AccountManager;
+use crate::bot::BotDrive;
+use crate::commands::fs::file_metadata;
+use crate::commands::{AccountUpdate, TelegramState};
+use crate::error::{DriveError, with_retry};
+use crate::messages::MessageCache;
+use crate::models::{FileChange, FolderMetadata};
+use crate::peers::peer_id;
+
+/// The chat a message or deletion belongs to, as Telegram reports it
+enum ChatRef {
+    User(i64),
+    Group(i64),
+    Channel(i64),
+    /// Deletions outside channels only carry message IDs, which are unique
+    /// across all private chats and basic groups of the account
+    Common,
+}
+
+/// Turns Telegram updates for drive folders into `file-change` events.
+///
+/// Updates of the active account are checked against the folders from the last
+/// scan (or the bot index) and Saved Messages; everything else is ignored. The
+/// message cache and the bot index are kept in step with what the events report.
+pub struct LiveFolders {
+    /// Chats known to be drive folders, replaced by every folder scan
+    folders: Mutex<HashSet<i64>>,
+    /// Files of Saved Messages in the last listing, plus the ones added since. A deletion
+    /// outside channels does not say which chat it was in, so only these are reported.
+    saved_files: Mutex<HashSet<i32>>,
+    /// User ID of each account, to recognise Saved Messages
+    own_ids: Mutex<HashMap<String, i64>>,
+}
+
+impl Default for LiveFolders {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LiveFolders {
+    pub fn new() -> Self {
+        Self {
+            folders: Mutex::new(HashSet::new()),
+            saved_files: Mutex::new(HashSet::new()),
+            own_ids: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Replaces the known folders with the result of a scan
+    pub fn set_folders(&self, folders: &[FolderMetadata]) {
+        *self.folders.lock().unwrap() = folders.iter().map(|f| f.id).collect();
+    }
+
+    /// Replaces the known Saved Messages files with a fresh listing
+    pub fn set_saved_files(&self, message_ids: impl IntoIterator<Item = i32>) {
+        *self.saved_files.lock().unwrap() = message_ids.into_iter().collect();
+    }
+
+    /// Forgets the folders and files of the previous account
+    pub fn clear(&self) {
+        self.folders.lock().unwrap().clear();
+        self.saved_files.lock().unwrap().clear();
+    }
+
+    pub fn add_folder(&self, id: i64) {
+        self.folders.lock().unwrap().insert(id);
+    }
+
+    pub fn remove_folder(&self, id: i64) {
+        self.folders.lock().unwrap().remove(&id);
+    }
+
+    /// The drive folder behind `chat`, `Some(None)` being Saved Messages
+    async fn folder_of(&self, client: &Client, drive: &BotDrive, account: &str, chat: &ChatRef) -> Result<Option<Option<i64>>, DriveError> {
+        let folder = match *chat {
+            ChatRef::Channel(id) if drive.is_bot() => drive.folder(id).map(|_| Some(id)),
+            ChatRef::Channel(id) | ChatRef::Group(id) => self.is_folder(id).then_some(Some(id)),
+            // Bots have no Saved Messages
+            ChatRef::User(_) | ChatRef::Common if drive.is_bot() => None,
+            ChatRef::User(id) => (id == self.own_id(client, account).await?).then_some(None),
+            ChatRef::Common => Some(None),
+        };
+        Ok(folder)
+    }
+
+    fn is_folder(&self, id: i64) -> bool {
+        self.folders.lock().unwrap().contains(&id)
+    }
+
+    async fn own_id(&self, client: &Client, account: &str) -> Result<i64, DriveError> {
+        let cached = self.own_ids.lock().unwrap().get(account).copied();
+        if let Some(id) = cached {
+            return Ok(id);
+        }
+        let me = with_retry("users.getFullUser", || client.get_me()).await?;
+        let id = peer_id(&Peer::User(me)).unwrap_or_default();
+        self.own_ids.lock().unwrap().insert(account.to_string(), id);
+        Ok(id)
+    }
+}
+
+/// Listens to the updates of every connected client for as long as the app runs
+pub fn spawn_watcher(app_handle: tauri::AppHandle) {
+    let mut updates = app_handle.state::<TelegramState>().updates.subscribe();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match updates.recv().await {
+                Ok(received) => {
+                    if let Err(e) = handle_update(&app_handle, &received).await {
+                        log::warn!("Failed to process an update: {}", e);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    // Some changes are lost, so the file lists have to be fetched again
+                    log::warn!("Missed {} updates", missed);
+                    let _ = app_handle.emit("file-changes-missed", ());
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+async fn handle_update(app_handle: &tauri::AppHandle, received: &AccountUpdate) -> Result<(), DriveError> {
+    // The file list only shows the active account
+    if app_handle.state::<AccountManager>().active_name().as_deref() != Some(received.account.as_str()) {
+        return Ok(());
+    }
+    let (chat, edited) = match received.update.as_ref() {
+        Update::NewMessage(msg) => (message_chat(msg), false),
+        Update::MessageEdited(msg) => (message_chat(msg), true),
+        Update::MessageDeleted(deletion) => match deletion.channel_id() {
+            Some(id) => (Some(ChatRef::Channel(id)), false),
+            None => (Some(ChatRef::Common), false),
+        },
+        _ => return Ok(()),
+    };
+    let Some(chat) = chat else { return Ok(()) };
+
+    let state = app_handle.state::<TelegramState>();
+    let Some(client) = state.client.lock().await.clone() else { return Ok(()) };
+    let live = app_handle.state::<LiveFolders>();
+    let drive = app_handle.state::<BotDrive>();
+    let messages = app_handle.state::<MessageCache>();
+    let Some(folder_id) = live.folder_of(&client, &drive, &received.account, &chat).await? else { return Ok(()) };
+    let chat_id = match chat {
+        ChatRef::User(id) | ChatRef::Group(id) | ChatRef::Channel(id) => id,
+        ChatRef::Common => live.own_id(&client, &received.account).await?,
+    };
+
+    let change = match received.update.as_ref() {
+        Update::NewMessage(msg) | Update::MessageEdited(msg) => {
+            let message_id = msg.id();
+            messages.forget_in(chat_id, &[message_id]);
+            match file_metadata(msg, folder_id) {
+                Some(file) if edited => FileChange::Updated { file },
+                Some(file) => {
+                    drive.track(folder_id, &[message_id]);
+                    if folder_id.is_none() {
+                        live.saved_files.lock().unwrap().insert(message_id);
+                    }
+                    FileChange::Added { file }
+                }
+                // Plain text, or an edit that removed the file
+                None if edited => FileChange::Deleted { folder_id, message_ids: vec![message_id] },
+                None => return Ok(()),
+            }
+        }
+        Update::MessageDeleted(deletion) => {
+            let mut message_ids = deletion.messages().to_vec();
+            if matches!(chat, ChatRef::Common) {
+                // Could be any private chat or group, so keep only IDs known to be Saved Messages files
+                let mut saved = live.saved_files.lock().unwrap();
+                message_ids.retain(|id| saved.remove(id) || messages.contains(chat_id, *id));
+                if message_ids.is_empty() {
+                    return Ok(());
+                }
+            }
+            messages.forget_in(chat_id, &message_ids);
+            drive.untrack(folder_id, &message_ids);
+            FileChange::Deleted { folder_id, message_ids }
+        }
+        _ => return Ok(()),
+    };
+    let _ = app_handle.emit("file-change", change);
+    Ok(())
+}
+
+fn message_chat(msg: &Message) -> Option<ChatRef> {
+    let tl::enums::Message::Message(raw) = &msg.raw else { return None };
+    Some(match &raw.peer_id {
+        tl::enums::Peer::User(u) => ChatRef::User(u.user_id),
+        tl::enums::Peer::Chat(c) => ChatRef::Group(c.chat_id),
+        tl::enums::Peer::Channel(c) => ChatRef::Channel(c.channel_id),
+    })
+}
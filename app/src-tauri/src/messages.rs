@@ -0,0 +1,106 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use grammers_client::Client;
+use grammers_client::types::{Message, Peer};
+use crate::error::{DriveError, with_retry};
+use crate::peers::peer_id;
+
+/// Messages kept around for repeat lookups (preview, then thumbnail, then download)
+const CAPACITY: usize = 256;
+/// File references inside cached media expire, so entries are refetched after this
+const MAX_AGE: Duration = Duration::from_secs(10 * 60);
+/// Most IDs `messages.getMessages` and `channels.getMessages` accept per request
+const BATCH_SIZE: usize = 100;
+
+struct Entry {
+    chat_id: i64,
+    message_id: i32,
+    message: Message,
+    fetched: Instant,
+}
+
+/// Looks up messages by ID instead of paging through the chat history.
+///
+/// Recently fetched messages are cached in memory, most recent last.
+pub struct MessageCache {
+    recent: Mutex<VecDeque<Entry>>,
+}
+
+impl Default for MessageCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MessageCache {
+    pub fn new() -> Self {
+        Self { recent: Mutex::new(VecDeque::with_capacity(CAPACITY)) }
+    }
+
+    /// Fetches one message, `None` if it does not exist (any more)
+    pub async fn get(&self, client: &Client, peer: &Peer, message_id: i32) -> Result<Option<Message>, DriveError> {
+        Ok(self.get_many(client, peer, &[message_id]).await?.pop().flatten())
+    }
+
+    /// Fetches several messages, in the order of `message_ids`.
+    /// Only the ones not cached are requested, up to 100 per request.
+    pub async fn get_many(&self, client: &Client, peer: &Peer, message_ids: &[i32]) -> Result<Vec<Option<Message>>, DriveError> {
+        let chat_id = peer_id(peer).unwrap_or_default();
+        let mut found: Vec<Option<Message>> = message_ids.iter().map(|id| self.cached(chat_id, *id)).collect();
+
+        let missing: Vec<i32> = message_ids.iter().zip(&found)
+            .filter(|(_, msg)| msg.is_none())
+            .map(|(id, _)| *id)
+            .collect();
+        for chunk in missing.chunks(BATCH_SIZE) {
+            let fetched = with_retry("messages.getMessages", || client.get_messages_by_id(peer, chunk)).await?;
+            for (id, msg) in chunk.iter().zip(fetched) {
+                let Some(msg) = msg else { continue };
+                self.insert(chat_id, msg.clone());
+                // The same ID may be asked for twice
+                for (slot, _) in found.iter_mut().zip(message_ids).filter(|(_, want)| *want == id) {
+                    *slot = Some(msg.clone());
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Drops messages that were deleted or moved away
+    pub fn forget(&self, peer: &Peer, message_ids: &[i32]) {
+        self.forget_in(peer_id(peer).unwrap_or_default(), message_ids);
+    }
+
+    /// Like `forget`, for when only the chat ID is known (e.g. from an update)
+    pub fn forget_in(&self, chat_id: i64, message_ids: &[i32]) {
+        self.recent.lock().unwrap().retain(|e| e.chat_id != chat_id || !message_ids.contains(&e.message_id));
+    }
+
+    /// Whether the message is cached, without fetching it
+    pub fn contains(&self, chat_id: i64, message_id: i32) -> bool {
+        self.cached(chat_id, message_id).is_some()
+    }
+
+    pub fn clear(&self) {
+        self.recent.lock().unwrap().clear();
+    }
+
+    fn cached(&self, chat_id: i64, message_id: i32) -> Option<Message> {
+        let recent = self.recent.lock().unwrap();
+        recent.iter()
+            .rev()
+            .find(|e| e.chat_id == chat_id && e.message_id == message_id && e.fetched.elapsed() < MAX_AGE)
+            .map(|e| e.message.clone())
+    }
+
+    fn insert(&self, chat_id: i64, message: Message) {
+        let message_id = message.id();
+        let mut recent = self.recent.lock().unwrap();
+        recent.retain(|e| e.chat_id != chat_id || e.message_id != message_id);
+        if recent.len() >= CAPACITY {
+            recent.pop_front();
+        }
+        recent.push_back(Entry { chat_id, message_id, message, fetched: Instant::now() });
+    }
+}
@@ -41,3 +41,129 @@ pub struct Drive {
     pub name: String,
     pub icon: Option<String>,
 }
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AccountInfo {
+    pub name: String,
+    pub api_id: Option<i32>,
+    pub active: bool,
+    pub connected: bool,
+    /// Signed in with a bot token rather than a phone number
+    pub bot: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VaultStatus {
+    pub enabled: bool,
+    pub unlocked: bool,
+}
+
+/// Payload of the `qr-login-token` event
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QrLoginToken {
+    pub url: String,
+    /// The same URL rendered as a `data:image/png;base64,...` QR code
+    pub png: String,
+    /// Unix timestamp after which a new code is sent
+    pub expires: i32,
+}
+
+/// A local file a directory transfer skipped or failed on, and why
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TransferIssue {
+    pub path: String,
+    pub reason: String,
+}
+
+/// Result of uploading a directory tree
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DirectoryUploadSummary {
+    pub uploaded: Vec<String>,
+    pub skipped: Vec<TransferIssue>,
+    pub failed: Vec<TransferIssue>,
+    /// Drive folders made for directories that had none yet
+    pub created_folders: Vec<FolderMetadata>,
+}
+
+/// A file of a directory upload and the drive folder it goes to
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PlannedUpload {
+    pub path: String,
+    pub folder_id: i64,
+    pub size: u64,
+}
+
+/// Files of a directory tree, ready to be queued for upload
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DirectoryUploadPlan {
+    pub files: Vec<PlannedUpload>,
+    pub skipped: Vec<TransferIssue>,
+    pub failed: Vec<TransferIssue>,
+    /// Drive folders made for directories that had none yet
+    pub created_folders: Vec<FolderMetadata>,
+}
+
+/// Progress of `upload_directory`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DirectoryUploadProgress {
+    /// File currently being uploaded
+    pub path: String,
+    pub files_done: usize,
+    pub files_total: usize,
+    pub bytes_sent: u64,
+    pub size: u64,
+}
+
+/// Payload of the `file-change` event, sent when a drive folder changes on Telegram
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum FileChange {
+    Added { file: FileMetadata },
+    Updated { file: FileMetadata },
+    /// `folder_id` is `None` for Saved Messages
+    Deleted { folder_id: Option<i64>, message_ids: Vec<i32> },
+}
+
+/// Payload of the `watch-activity` event, sent for each file a watched folder uploads
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WatchActivity {
+    pub rule_id: u32,
+    pub path: String,
+    /// `None` once the file is uploaded
+    pub error: Option<String>,
+}
+
+/// Where a single download ended up
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SavedFile {
+    pub path: String,
+    /// A file already existed there and the collision strategy was `skip`
+    pub skipped: bool,
+}
+
+/// Result of downloading a drive folder and its subfolders
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FolderDownloadSummary {
+    pub downloaded: Vec<String>,
+    pub skipped: Vec<TransferIssue>,
+    pub failed: Vec<TransferIssue>,
+}
+
+/// Payload of the `folder-download-progress` event
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FolderDownloadProgress {
+    /// File just finished (or skipped)
+    pub path: String,
+    pub files_done: usize,
+    pub files_total: usize,
+    pub bytes_done: u64,
+    /// Photos of unknown size are not counted
+    pub bytes_total: u64,
+}
+
+/// Messages of one folder to put in a ZIP archive
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ZipSelection {
+    pub folder_id: Option<i64>,
+    pub message_ids: Vec<i32>,
+}
@@ -0,0 +1,257 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+use crate::error::DriveError;
+
+/// Dialog picks remembered across restarts, so restored upload queues still work
+const MAX_PICKED: usize = 1000;
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
+struct PolicyState {
+    /// Folders the user approved, trusted with everything below them
+    roots: Vec<PathBuf>,
+    /// Files and folders picked in an open dialog the backend opened, oldest first. Read only.
+    picked: Vec<PathBuf>,
+}
+
+/// Write access from dialog picks, never saved
+#[derive(Debug, Default)]
+struct SessionGrants {
+    /// Folders picked since launch
+    folders: Vec<PathBuf>,
+    /// Save dialog picks, each good for one write
+    saves: Vec<PathBuf>,
+}
+
+/// Decides which local paths the file commands may read and write.
+///
+/// Paths from the webview are only accepted if they were picked in a dialog the
+/// backend opened, or sit inside a folder the user approved. Paths are resolved
+/// (`..`, symlinks) before the check, so nothing can step outside those.
+/// Approved folders and read access to picks are stored in `paths.json`, shared by
+/// all accounts. Write access from a pick lasts until the app quits, or for a save
+/// dialog, until the file is written once.
+pub struct PathPolicy {
+    file_path: PathBuf,
+    state: Mutex<PolicyState>,
+    session: Mutex<SessionGrants>,
+}
+
+impl PathPolicy {
+    pub fn new(app_handle: &tauri::AppHandle) -> Self {
+        let app_data_dir = app_handle.path().app_data_dir().unwrap_or_else(|_| PathBuf::from("data"));
+        Self::load(&app_data_dir)
+    }
+
+    pub fn load(app_data_dir: &Path) -> Self {
+        let file_path = app_data_dir.join("paths.json");
+        let state = fs::read_to_string(&file_path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        Self {
+            file_path,
+            state: Mutex::new(state),
+            session: Mutex::new(SessionGrants::default()),
+        }
+    }
+
+    /// Approved folders
+    pub fn roots(&self) -> Vec<PathBuf> {
+        self.state.lock().unwrap().roots.clone()
+    }
+
+    pub fn add_root(&self, path: &Path) -> Result<PathBuf, DriveError> {
+        let root = fs::canonicalize(path)?;
+        if !root.is_dir() {
+            return Err(DriveError::Other(format!("{} is not a folder", root.display())));
+        }
+        self.update(|state| {
+            if !state.roots.contains(&root) {
+                state.roots.push(root.clone());
+            }
+        });
+        Ok(root)
+    }
+
+    pub fn remove_root(&self, path: &Path) {
+        // Roots are stored resolved. A folder that is gone can only match as given.
+        let root = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.update(|state| state.roots.retain(|r| r != &root));
+    }
+
+    /// Allows reading a file or folder the user picked in a dialog. Folders include everything below them.
+    pub fn grant(&self, path: &Path) -> Result<PathBuf, DriveError> {
+        let resolved = fs::canonicalize(path)?;
+        self.update(|state| {
+            state.picked.retain(|p| p != &resolved);
+            state.picked.push(resolved.clone());
+            if state.picked.len() > MAX_PICKED {
+                let excess = state.picked.len() - MAX_PICKED;
+                state.picked.drain(..excess);
+            }
+        });
+        Ok(resolved)
+    }
+
+    /// Like `grant`, and allows writing inside the folder until the app quits
+    pub fn grant_folder(&self, path: &Path) -> Result<PathBuf, DriveError> {
+        let resolved = self.grant(path)?;
+        let mut session = self.session.lock().unwrap();
+        if !session.folders.contains(&resolved) {
+            session.folders.push(resolved.clone());
+        }
+        Ok(resolved)
+    }
+
+    /// Allows a single write to a path picked in a save dialog
+    pub fn grant_save(&self, path: &Path) -> Result<PathBuf, DriveError> {
+        let resolved = resolve(path)?;
+        self.session.lock().unwrap().saves.push(resolved.clone());
+        Ok(resolved)
+    }
+
+    /// Checks an existing file or folder the webview wants read, returning its resolved path
+    pub fn readable(&self, path: &str) -> Result<PathBuf, DriveError> {
+        let resolved = fs::canonicalize(absolute(path)?)?;
+        let allowed = {
+            let state = self.state.lock().unwrap();
+            state.roots.iter().chain(&state.picked).any(|allowed| resolved.starts_with(allowed))
+        };
+        self.check(resolved, path, allowed)
+    }
+
+    /// Checks a path the webview wants written. It may not exist yet, but its parent must.
+    /// Uses up the grant of a save dialog pick.
+    pub fn writable(&self, path: &str) -> Result<PathBuf, DriveError> {
+        let resolved = resolve(absolute(path)?)?;
+        let allowed = {
+            let state = self.state.lock().unwrap();
+            let mut session = self.session.lock().unwrap();
+            match session.saves.iter().position(|p| p == &resolved) {
+                Some(i) => {
+                    session.saves.remove(i);
+                    true
+                }
+                None => state.roots.iter().chain(&session.folders).any(|allowed| resolved.starts_with(allowed)),
+            }
+        };
+        self.check(resolved, path, allowed)
+    }
+
+    fn check(&self, resolved: PathBuf, requested: &str, allowed: bool) -> Result<PathBuf, DriveError> {
+        if allowed {
+            return Ok(resolved);
+        }
+        log::warn!("Rejected access to {} (resolved to {})", requested, resolved.display());
+        Err(DriveError::PathNotAllowed(requested.to_string()))
+    }
+
+    fn update(&self, change: impl FnOnce(&mut PolicyState)) {
+        let mut state = self.state.lock().unwrap();
+        change(&mut state);
+        match serde_json::to_string_pretty(&*state) {
+            Ok(json) => {
+                if let Err(e) = crate::atomic::write(&self.file_path, json) {
+                    log::error!("Failed to save path policy: {}", e);
+                }
+            }
+            Err(e) => log::error!("Failed to serialize path policy: {}", e),
+        }
+    }
+}
+
+fn absolute(path: &str) -> Result<&Path, DriveError> {
+    let path = Path::new(path);
+    if !path.is_absolute() {
+        return Err(DriveError::PathNotAllowed(path.display().to_string()));
+    }
+    Ok(path)
+}
+
+/// Resolves `path` like `fs::canonicalize`, except that the last component may not exist yet
+fn resolve(path: &Path) -> Result<PathBuf, DriveError> {
+    // Existing paths (including symlinks) are resolved all the way
+    if fs::symlink_metadata(path).is_ok() {
+        return Ok(fs::canonicalize(path)?);
+    }
+    let name = match path.components().next_back() {
+        Some(Component::Normal(name)) => name,
+        _ => return Err(DriveError::PathNotAllowed(path.display().to_string())),
+    };
+    let parent = path.parent().ok_or_else(|| DriveError::PathNotAllowed(path.display().to_string()))?;
+    Ok(fs::canonicalize(parent)?.join(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("telegram-drive-test-paths-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("root")).unwrap();
+        fs::write(dir.join("secret.txt"), b"secret").unwrap();
+        fs::canonicalize(dir).unwrap()
+    }
+
+    fn text(path: PathBuf) -> String {
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn parent_components_cannot_leave_an_approved_root() {
+        let dir = test_dir("dotdot");
+        let policy = PathPolicy::load(&dir);
+        policy.add_root(&dir.join("root")).unwrap();
+
+        assert!(matches!(policy.readable(&text(dir.join("root/../secret.txt"))), Err(DriveError::PathNotAllowed(_))));
+        assert!(matches!(policy.writable(&text(dir.join("root/../new.txt"))), Err(DriveError::PathNotAllowed(_))));
+        assert!(matches!(policy.writable("root/new.txt"), Err(DriveError::PathNotAllowed(_))));
+        assert_eq!(policy.writable(&text(dir.join("root/new.txt"))).unwrap(), dir.join("root/new.txt"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_an_approved_root_are_rejected() {
+        let dir = test_dir("symlink");
+        fs::create_dir(dir.join("outside")).unwrap();
+        std::os::unix::fs::symlink(dir.join("outside"), dir.join("root/link")).unwrap();
+        std::os::unix::fs::symlink(dir.join("secret.txt"), dir.join("root/secret.txt")).unwrap();
+        let policy = PathPolicy::load(&dir);
+        policy.add_root(&dir.join("root")).unwrap();
+
+        assert!(matches!(policy.readable(&text(dir.join("root/secret.txt"))), Err(DriveError::PathNotAllowed(_))));
+        assert!(matches!(policy.writable(&text(dir.join("root/secret.txt"))), Err(DriveError::PathNotAllowed(_))));
+        assert!(matches!(policy.writable(&text(dir.join("root/link/new.txt"))), Err(DriveError::PathNotAllowed(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn save_dialog_grant_allows_one_write() {
+        let dir = test_dir("save");
+        let policy = PathPolicy::load(&dir);
+        let picked = policy.grant_save(&dir.join("picked.txt")).unwrap();
+
+        assert_eq!(policy.writable(&text(picked.clone())).unwrap(), picked);
+        assert!(matches!(policy.writable(&text(picked)), Err(DriveError::PathNotAllowed(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn roots_are_removed_by_their_resolved_path() {
+        let dir = test_dir("remove");
+        let policy = PathPolicy::load(&dir);
+        policy.add_root(&dir.join("root")).unwrap();
+
+        policy.remove_root(&dir.join("root/../root/"));
+        assert!(policy.roots().is_empty());
+        assert!(PathPolicy::load(&dir).roots().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use grammers_client::types::Peer;
+use grammers_tl_types as tl;
+use tauri::Manager;
+use crate::accounts::AccountManager;
+use crate::error::DriveError;
+
+/// Name of the cache file inside each account's directory
+pub const CACHE_FILE: &str = "peers.json";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PeerKind {
+    /// Basic group, addressed by ID alone
+    Group,
+    /// Broadcast channel or supergroup
+    Channel,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct CachedPeer {
+    pub kind: PeerKind,
+    pub access_hash: i64,
+}
+
+/// Access hashes of the channels and groups an account has seen, keyed by chat ID.
+///
+/// Saved to `peers.json` so folders resolve without walking every dialog. The
+/// `Peer` values fetched during this run (users included) are kept in memory on top of that.
+pub struct PeerCache {
+    file_path: Mutex<PathBuf>,
+    entries: Mutex<HashMap<i64, CachedPeer>>,
+    resolved: Mutex<HashMap<i64, Peer>>,
+}
+
+impl PeerCache {
+    /// Loads the cache of the active account
+    pub fn new(app_handle: &tauri::AppHandle) -> Self {
+        let accounts = app_handle.state::<AccountManager>();
+        let account = accounts.ensure_active();
+        Self::load(&accounts.account_dir(&account.name))
+    }
+
+    pub fn load(dir: &Path) -> Self {
+        let (file_path, entries) = Self::read_cache(dir);
+        Self {
+            file_path: Mutex::new(file_path),
+            entries: Mutex::new(entries),
+            resolved: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Points the cache at another account's file
+    pub fn switch_to(&self, dir: &Path) {
+        let (file_path, entries) = Self::read_cache(dir);
+        *self.file_path.lock().unwrap() = file_path;
+        *self.entries.lock().unwrap() = entries;
+        self.resolved.lock().unwrap().clear();
+    }
+
+    fn read_cache(dir: &Path) -> (PathBuf, HashMap<i64, CachedPeer>) {
+        let file_path = dir.join(CACHE_FILE);
+        let entries = fs::read_to_string(&file_path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        (file_path, entries)
+    }
+
+    fn save(&self, entries: &HashMap<i64, CachedPeer>) {
+        if let Ok(json) = serde_json::to_string(entries) {
+            let _ = crate::atomic::write(&*self.file_path.lock().unwrap(), json);
+        }
+    }
+
+    pub fn get(&self, id: i64) -> Option<CachedPeer> {
+        self.entries.lock().unwrap().get(&id).copied()
+    }
+
+    /// The peer fetched earlier in this run, if any
+    pub fn resolved(&self, id: i64) -> Option<Peer> {
+        self.resolved.lock().unwrap().get(&id).cloned()
+    }
+
+    /// Records a peer the account has seen
+    pub fn remember(&self, peer: &Peer) {
+        self.remember_all(std::slice::from_ref(peer));
+    }
+
+    /// Records several peers with a single write, e.g. a whole dialog list
+    pub fn remember_all(&self, peers: &[Peer]) {
+        let mut entries = self.entries.lock().unwrap();
+        let mut resolved = self.resolved.lock().unwrap();
+        let mut changed = false;
+        for peer in peers {
+            let Some(id) = peer_id(peer) else { continue };
+            resolved.insert(id, peer.clone());
+            let Some(cached) = cache_entry(peer) else { continue };
+            let known = entries.get(&id).is_some_and(|c| c.kind == cached.kind && c.access_hash == cached.access_hash);
+            if !known {
+                entries.insert(id, cached);
+                changed = true;
+            }
+        }
+        if changed {
+            self.save(&entries);
+        }
+    }
+
+    /// Drops an entry whose access hash no longer works
+    pub fn invalidate(&self, id: i64) {
+        self.resolved.lock().unwrap().remove(&id);
+        let mut entries = self.entries.lock().unwrap();
+        if entries.remove(&id).is_some() {
+            self.save(&entries);
+        }
+    }
+
+    /// Passes `result` through, dropping `peer` if the call failed because its channel
+    /// is gone or private, so the next lookup does not hand out the stale `Peer` again
+    pub fn check<T>(&self, peer: &Peer, result: Result<T, DriveError>) -> Result<T, DriveError> {
+        if let (Err(e), Some(id)) = (&result, peer_id(peer)) {
+            if e.is_channel_unavailable() {
+                log::warn!("Chat {} is no longer accessible, dropping it from the cache", id);
+                self.invalidate(id);
+            }
+        }
+        result
+    }
+
+    /// Builds the `InputPeer` for a cached chat without any request
+    pub fn input_peer(&self, id: i64) -> Option<tl::enums::InputPeer> {
+        let cached = self.get(id)?;
+        Some(match cached.kind {
+            PeerKind::Group => tl::enums::InputPeer::Chat(tl::types::InputPeerChat { chat_id: id }),
+            PeerKind::Channel => tl::enums::InputPeer::Channel(tl::types::InputPeerChannel {
+                channel_id: id,
+                access_hash: cached.access_hash,
+            }),
+        })
+    }
+
+    /// Builds the `InputChannel` for a cached channel without any request
+    pub fn input_channel(&self, id: i64) -> Option<tl::enums::InputChannel> {
+        let cached = self.get(id).filter(|c| c.kind == PeerKind::Channel)?;
+        Some(tl::enums::InputChannel::Channel(tl::types::InputChannel {
+            channel_id: id,
+            access_hash: cached.access_hash,
+        }))
+    }
+}
+
+/// ID of any peer, including basic groups and supergroups (which grammers reports as groups)
+pub fn peer_id(peer: &Peer) -> Option<i64> {
+    match peer {
+        Peer::User(u) => Some(u.raw.id()),
+        _ => cache_entry(peer).map(|(id, _)| id),
+    }
+}
+
+fn cache_entry(peer: &Peer) -> Option<(i64, CachedPeer)> {
+    let channel = |id, access_hash: Option<i64>| Some((id, CachedPeer { kind: PeerKind::Channel, access_hash: access_hash.unwrap_or(0) }));
+    let group = |id| Some((id, CachedPeer { kind: PeerKind::Group, access_hash: 0 }));
+    match peer {
+        Peer::Channel(c) => channel(c.raw.id, c.raw.access_hash),
+        Peer::Group(g) => match &g.raw {
+            tl::enums::Chat::Chat(raw) => group(raw.id),
+            tl::enums::Chat::Forbidden(raw) => group(raw.id),
+            tl::enums::Chat::Channel(raw) => channel(raw.id, raw.access_hash),
+            _ => None,
+        },
+        // Users are not folders, and Saved Messages is resolved through get_me
+        Peer::User(_) => None,
+    }
+}
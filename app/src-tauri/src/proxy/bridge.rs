@@ -0,0 +1,144 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use super::{ProxyConfig, http_connect, mtproxy};
+
+/// Local SOCKS5 server (no auth, CONNECT only) that forwards grammers'
+/// connections through an HTTP CONNECT proxy or an MTProxy.
+pub struct Bridge {
+    config: ProxyConfig,
+    port: u16,
+    task: tauri::async_runtime::JoinHandle<()>,
+}
+
+impl Bridge {
+    pub async fn start(config: ProxyConfig) -> Result<Self, String> {
+        if let ProxyConfig::MtProxy { secret, .. } = &config {
+            mtproxy::Secret::parse(secret)?;
+        }
+
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await
+            .map_err(|e| format!("Failed to start proxy bridge: {}", e))?;
+        let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+        log::info!("Proxy bridge listening on 127.0.0.1:{}", port);
+
+        let task_config = config.clone();
+        let task = tauri::async_runtime::spawn(async move {
+            loop {
+                let (conn, _) = match listener.accept().await {
+                    Ok(c) => c,
+                    Err(e) => {
+                        log::warn!("Proxy bridge accept failed: {}", e);
+                        continue;
+                    }
+                };
+                let config = task_config.clone();
+                tauri::async_runtime::spawn(async move {
+                    if let Err(e) = handle(conn, &config).await {
+                        log::warn!("Proxy bridge connection failed: {}", e);
+                    }
+                });
+            }
+        });
+
+        Ok(Self { config, port, task })
+    }
+
+    pub fn config(&self) -> &ProxyConfig {
+        &self.config
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Stops accepting new connections. Established tunnels run until they close.
+    pub fn stop(self) {
+        self.task.abort();
+    }
+}
+
+async fn handle(mut conn: TcpStream, config: &ProxyConfig) -> Result<(), String> {
+    let target = socks5_accept(&mut conn).await.map_err(|e| format!("SOCKS5 handshake: {}", e))?;
+
+    match config {
+        ProxyConfig::Http { host, port, username, password } => {
+            let upstream = TcpStream::connect((host.as_str(), *port)).await.map_err(|e| e.to_string());
+            let upstream = match upstream {
+                Ok(s) => http_connect(s, &target, username.as_deref(), password.as_deref()).await,
+                Err(e) => Err(e),
+            };
+            let mut upstream = match upstream {
+                Ok(s) => s,
+                Err(e) => {
+                    let _ = socks5_reply(&mut conn, 0x05).await;
+                    return Err(e);
+                }
+            };
+            socks5_reply(&mut conn, 0x00).await.map_err(|e| e.to_string())?;
+            tokio::io::copy_bidirectional(&mut conn, &mut upstream).await.map_err(|e| e.to_string())?;
+            Ok(())
+        }
+        ProxyConfig::MtProxy { host, port, secret } => {
+            let secret = mtproxy::Secret::parse(secret)?;
+            let ip = target.rsplit_once(':').map(|(ip, _)| ip).unwrap_or(&target);
+            let dc_id = mtproxy::dc_for_address(ip);
+            // MTProxy only reports failures once traffic flows, so accept right away
+            socks5_reply(&mut conn, 0x00).await.map_err(|e| e.to_string())?;
+            mtproxy::relay(conn, host, *port, &secret, dc_id).await
+        }
+        _ => Err("Proxy bridge is only used for HTTP and MTProxy".to_string()),
+    }
+}
+
+/// Server side of the SOCKS5 greeting and CONNECT request. Returns the requested "host:port".
+async fn socks5_accept(conn: &mut TcpStream) -> std::io::Result<String> {
+    let invalid = |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string());
+
+    let mut greeting = [0u8; 2];
+    conn.read_exact(&mut greeting).await?;
+    if greeting[0] != 0x05 {
+        return Err(invalid("not a SOCKS5 client"));
+    }
+    let mut methods = vec![0u8; greeting[1] as usize];
+    conn.read_exact(&mut methods).await?;
+    if !methods.contains(&0x00) {
+        conn.write_all(&[0x05, 0xff]).await?;
+        return Err(invalid("client requires authentication"));
+    }
+    conn.write_all(&[0x05, 0x00]).await?;
+
+    let mut head = [0u8; 4];
+    conn.read_exact(&mut head).await?;
+    if head[1] != 0x01 {
+        socks5_reply(conn, 0x07).await?;
+        return Err(invalid("only CONNECT is supported"));
+    }
+    let host = match head[3] {
+        0x01 => {
+            let mut ip = [0u8; 4];
+            conn.read_exact(&mut ip).await?;
+            std::net::Ipv4Addr::from(ip).to_string()
+        }
+        0x04 => {
+            let mut ip = [0u8; 16];
+            conn.read_exact(&mut ip).await?;
+            format!("[{}]", std::net::Ipv6Addr::from(ip))
+        }
+        0x03 => {
+            let mut len = [0u8; 1];
+            conn.read_exact(&mut len).await?;
+            let mut name = vec![0u8; len[0] as usize];
+            conn.read_exact(&mut name).await?;
+            String::from_utf8_lossy(&name).to_string()
+        }
+        _ => return Err(invalid("unknown address type")),
+    };
+    let mut port = [0u8; 2];
+    conn.read_exact(&mut port).await?;
+
+    Ok(format!("{}:{}", host, u16::from_be_bytes(port)))
+}
+
+async fn socks5_reply(conn: &mut TcpStream, code: u8) -> std::io::Result<()> {
+    conn.write_all(&[0x05, code, 0x00, 0x01, 0, 0, 0, 0, 0, 0]).await
+}
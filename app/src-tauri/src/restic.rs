@@ -0,0 +1,223 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use base64::{Engine as _, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+/// Object types restic stores besides its single `config` file
+pub const OBJECT_TYPES: [&str; 5] = ["data", "keys", "locks", "snapshots", "index"];
+/// Basic auth user restic connects as
+pub const USERNAME: &str = "restic";
+
+/// Where the restic repository lives and the password restic connects with
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ResticRepoConfig {
+    /// The repository is only served while this account is active
+    pub account: String,
+    pub folder_id: i64,
+    pub password: String,
+}
+
+/// A restic object stored as a document in the repository folder
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct ResticObject {
+    pub message_id: i32,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
+struct ResticState {
+    repo: Option<ResticRepoConfig>,
+    /// By key, `config` or `<type>/<name>`
+    objects: BTreeMap<String, ResticObject>,
+}
+
+/// The drive folder served to restic by the local server (see `server.rs`), stored in `restic.json`.
+///
+/// Keeps the message and size of every object, so HEAD and listing requests are
+/// answered without asking Telegram, and GET goes straight to the right message.
+pub struct ResticRepo {
+    file_path: PathBuf,
+    state: Mutex<ResticState>,
+    /// Keys being uploaded, so two requests for the same object do not both store it
+    saving: Mutex<HashSet<String>>,
+}
+
+/// A key held by `ResticRepo::reserve` until this is dropped
+pub struct Reservation<'a> {
+    repo: &'a ResticRepo,
+    key: String,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.repo.saving.lock().unwrap().remove(&self.key);
+    }
+}
+
+impl ResticRepo {
+    pub fn new(app_handle: &tauri::AppHandle) -> Self {
+        let app_data_dir = app_handle.path().app_data_dir().unwrap_or_else(|_| PathBuf::from("data"));
+        Self::load(&app_data_dir)
+    }
+
+    pub fn load(app_data_dir: &Path) -> Self {
+        let file_path = app_data_dir.join("restic.json");
+        let state = fs::read_to_string(&file_path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self { file_path, state: Mutex::new(state), saving: Mutex::new(HashSet::new()) }
+    }
+
+    pub fn config(&self) -> Option<ResticRepoConfig> {
+        self.state.lock().unwrap().repo.clone()
+    }
+
+    /// Serves `folder_id` as the repository, holding `objects`, with a new password
+    pub fn enable(&self, account: String, folder_id: i64, objects: BTreeMap<String, ResticObject>) -> ResticRepoConfig {
+        let password = general_purpose::URL_SAFE_NO_PAD.encode(rand::random::<[u8; 24]>());
+        let repo = ResticRepoConfig { account, folder_id, password };
+        log::info!("Serving folder {} as a restic repository ({} objects)", folder_id, objects.len());
+        self.update(|state| {
+            state.repo = Some(repo.clone());
+            state.objects = objects;
+        });
+        repo
+    }
+
+    /// Stops serving the repository. Its objects stay in the folder.
+    pub fn disable(&self) {
+        self.update(|state| *state = ResticState::default());
+    }
+
+    pub fn check_credentials(&self, username: &str, password: &str) -> bool {
+        let state = self.state.lock().unwrap();
+        state.repo.as_ref().is_some_and(|repo| username == USERNAME && constant_time_eq(password.as_bytes(), repo.password.as_bytes()))
+    }
+
+    pub fn object(&self, key: &str) -> Option<ResticObject> {
+        self.state.lock().unwrap().objects.get(key).copied()
+    }
+
+    /// Names and sizes of the objects of one type
+    pub fn list(&self, kind: &str) -> Vec<(String, u64)> {
+        let prefix = format!("{}/", kind);
+        self.state.lock().unwrap().objects.range(prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .map(|(key, object)| (key[prefix.len()..].to_string(), object.size))
+            .collect()
+    }
+
+    /// Claims `key` for an upload, `None` if the object exists or another upload has it.
+    /// Insert the object before dropping the reservation.
+    pub fn reserve(&self, key: &str) -> Option<Reservation<'_>> {
+        let state = self.state.lock().unwrap();
+        let mut saving = self.saving.lock().unwrap();
+        if state.objects.contains_key(key) || !saving.insert(key.to_string()) {
+            return None;
+        }
+        Some(Reservation { repo: self, key: key.to_string() })
+    }
+
+    pub fn insert(&self, key: String, object: ResticObject) {
+        self.update(|state| {
+            state.objects.insert(key, object);
+        });
+    }
+
+    pub fn remove(&self, key: &str) {
+        self.update(|state| {
+            state.objects.remove(key);
+        });
+    }
+
+    fn update(&self, change: impl FnOnce(&mut ResticState)) {
+        let mut state = self.state.lock().unwrap();
+        change(&mut state);
+        match serde_json::to_string(&*state) {
+            Ok(json) => {
+                if let Err(e) = crate::atomic::write(&self.file_path, json) {
+                    log::error!("Failed to save restic repository: {}", e);
+                }
+            }
+            Err(e) => log::error!("Failed to serialize restic repository: {}", e),
+        }
+    }
+}
+
+/// Compares without stopping at the first difference, so timing does not reveal the password
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Key of an object, `None` for unknown types and names that are not plain hex IDs
+pub fn object_key(kind: &str, name: &str) -> Option<String> {
+    let valid = OBJECT_TYPES.contains(&kind) && !name.is_empty() && name.chars().all(|c| c.is_ascii_hexdigit());
+    valid.then(|| format!("{}/{}", kind, name))
+}
+
+/// File name an object is uploaded under, e.g. `data-0a1b…`, so the lookup can be rebuilt from the folder
+pub fn document_name(key: &str) -> String {
+    key.replace('/', "-")
+}
+
+/// Key of the object stored under a document name, the reverse of `document_name`
+pub fn key_from_document(name: &str) -> Option<String> {
+    if name == "config" {
+        return Some(name.to_string());
+    }
+    let (kind, id) = name.split_once('-')?;
+    object_key(kind, id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo(name: &str) -> ResticRepo {
+        let dir = std::env::temp_dir().join(format!("telegram-drive-test-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let _ = fs::remove_file(dir.join("restic.json"));
+        ResticRepo::load(&dir)
+    }
+
+    #[test]
+    fn a_key_is_reserved_once() {
+        let repo = repo("reserve");
+        let first = repo.reserve("data/ab");
+        assert!(first.is_some());
+        assert!(repo.reserve("data/ab").is_none());
+        assert!(repo.reserve("data/cd").is_some());
+
+        drop(first);
+        let again = repo.reserve("data/ab");
+        assert!(again.is_some());
+        repo.insert("data/ab".to_string(), ResticObject { message_id: 1, size: 2 });
+        drop(again);
+        assert!(repo.reserve("data/ab").is_none());
+    }
+
+    #[test]
+    fn credentials_need_the_exact_password() {
+        let repo = repo("credentials");
+        assert!(!repo.check_credentials(USERNAME, ""));
+        let config = repo.enable("main".to_string(), 1, BTreeMap::new());
+        assert!(repo.check_credentials(USERNAME, &config.password));
+        assert!(!repo.check_credentials("admin", &config.password));
+        assert!(!repo.check_credentials(USERNAME, &config.password[1..]));
+        assert!(!repo.check_credentials(USERNAME, &format!("{}x", config.password)));
+    }
+
+    #[test]
+    fn document_names_map_back_to_keys() {
+        assert_eq!(key_from_document(&document_name("data/0a1b")), Some("data/0a1b".to_string()));
+        assert_eq!(key_from_document("config"), Some("config".to_string()));
+        assert_eq!(key_from_document("data-xyz"), None);
+        assert_eq!(key_from_document("other-0a1b"), None);
+        assert_eq!(object_key("keys", ""), None);
+    }
+}
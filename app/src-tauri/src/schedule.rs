@@ -0,0 +1,247 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+
+/// Gives up looking for the next run after this many days (e.g. `0 0 31 2 *` never fires)
+const MAX_SEARCH_DAYS: i64 = 366 * 5;
+
+/// A cron schedule in the usual five fields: minute, hour, day of month, month, day of week.
+///
+/// Fields take `*`, numbers, ranges (`1-5`), lists (`1,15`) and steps (`*/10`, `8-18/2`).
+/// Sunday is 0 or 7. As in cron, a day matches if either the day of month or the day
+/// of week matches, when both are restricted. `@hourly`, `@daily`, `@weekly` and
+/// `@monthly` are accepted as well. Times are wall-clock times in the zone of the
+/// `DateTime` the search starts from, `Local` for backup jobs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let expanded = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("'{}' is not a cron schedule, expected five fields like '0 2 * * *'", expr));
+        };
+
+        let mut weekdays = parse_field(weekday, 0, 7).map_err(|e| format!("Day of week: {}", e))?;
+        // 7 is another name for Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59).map_err(|e| format!("Minute: {}", e))?,
+            hours: parse_field(hour, 0, 23).map_err(|e| format!("Hour: {}", e))?,
+            days: parse_field(day, 1, 31).map_err(|e| format!("Day of month: {}", e))?,
+            months: parse_field(month, 1, 12).map_err(|e| format!("Month: {}", e))?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    /// First time strictly after `after` that matches, `None` if there is none within five years
+    pub fn next_after<Tz: TimeZone>(&self, after: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let zone = after.timezone();
+        let start = after.naive_local().with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = start + Duration::days(MAX_SEARCH_DAYS);
+        let mut t = start;
+
+        while t < limit {
+            if !has(self.months, t.month()) {
+                t = first_of_next_month(t.date())?;
+                continue;
+            }
+            if !self.day_matches(t.date()) {
+                t = t.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if !has(self.hours, t.hour()) {
+                t = t.with_minute(0)? + Duration::hours(1);
+                continue;
+            }
+            if !has(self.minutes, t.minute()) {
+                t += Duration::minutes(1);
+                continue;
+            }
+            // Times skipped by a daylight saving change do not exist locally
+            match zone.from_local_datetime(&t).earliest() {
+                Some(local) if local > after => return Some(local),
+                _ => t += Duration::minutes(1),
+            }
+        }
+        None
+    }
+
+    /// Latest time at or before `now` that matches, looking back no further than `since`
+    pub fn last_before<Tz: TimeZone>(&self, since: DateTime<Tz>, now: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let mut last = None;
+        let mut t = since;
+        while let Some(next) = self.next_after(t).filter(|next| *next <= now) {
+            last = Some(next.clone());
+            t = next;
+        }
+        last
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = has(self.days, date.day());
+        let weekday = has(self.weekdays, date.weekday().num_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (false, true) => day,
+            (true, false) => weekday,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+fn has(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+fn first_of_next_month(date: NaiveDate) -> Option<NaiveDateTime> {
+    let (year, month) = match date.month() {
+        12 => (date.year() + 1, 1),
+        m => (date.year(), m + 1),
+    };
+    NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)
+}
+
+/// Parses one field into a bit set of the values it allows
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut set = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().map_err(|_| format!("invalid step in '{}'", part))?;
+                if step == 0 {
+                    return Err(format!("invalid step in '{}'", part));
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (first, last) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((first, last)) => (parse_value(first, part)?, parse_value(last, part)?),
+                // `5/15` means from 5 to the end
+                None if step > 1 => (parse_value(range, part)?, max),
+                None => {
+                    let value = parse_value(range, part)?;
+                    (value, value)
+                }
+            },
+        };
+        if first < min || last > max || first > last {
+            return Err(format!("'{}' is outside {}-{}", part, min, max));
+        }
+        for value in (first..=last).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+fn parse_value(value: &str, part: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("invalid value in '{}'", part))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Offset};
+    use chrono_tz::{Europe::Berlin, Tz};
+
+    /// Wall-clock time in a zone with daylight saving: Berlin springs forward on
+    /// 2026-03-29 (02:00 -> 03:00) and falls back on 2026-10-25 (03:00 -> 02:00)
+    fn berlin(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Tz> {
+        Berlin.with_ymd_and_hms(year, month, day, hour, minute, 0).earliest().unwrap()
+    }
+
+    fn next<Z: TimeZone>(expr: &str, after: DateTime<Z>) -> Option<DateTime<Z>> {
+        Schedule::parse(expr).unwrap().next_after(after)
+    }
+
+    #[test]
+    fn skips_times_missing_on_spring_forward() {
+        assert_eq!(next("30 2 * * *", berlin(2026, 3, 28, 12, 0)), Some(berlin(2026, 3, 30, 2, 30)));
+        assert_eq!(next("0 * * * *", berlin(2026, 3, 29, 1, 30)), Some(berlin(2026, 3, 29, 3, 0)));
+    }
+
+    #[test]
+    fn runs_once_on_fall_back() {
+        let first = next("30 2 * * *", berlin(2026, 10, 25, 0, 0)).unwrap();
+        assert_eq!(first, berlin(2026, 10, 25, 2, 30));
+        assert_eq!(first.offset().fix().local_minus_utc(), 2 * 3600);
+        assert_eq!(next("30 2 * * *", first), Some(berlin(2026, 10, 26, 2, 30)));
+    }
+
+    #[test]
+    fn uses_the_wall_clock_of_the_given_zone() {
+        let india = FixedOffset::east_opt(5 * 3600 + 30 * 60).unwrap();
+        let after = india.with_ymd_and_hms(2026, 11, 10, 23, 30, 0).unwrap();
+        assert_eq!(next("0 0 * * *", after), Some(india.with_ymd_and_hms(2026, 11, 11, 0, 0, 0).unwrap()));
+        assert_eq!(next("45 23 * * *", after), Some(india.with_ymd_and_hms(2026, 11, 10, 23, 45, 0).unwrap()));
+    }
+
+    #[test]
+    fn last_before_finds_the_latest_missed_run() {
+        let since = berlin(2026, 11, 10, 12, 0);
+        let schedule = Schedule::parse("0 3 * * *").unwrap();
+        assert_eq!(schedule.last_before(since, berlin(2026, 11, 13, 2, 0)), Some(berlin(2026, 11, 12, 3, 0)));
+        assert_eq!(schedule.last_before(since, berlin(2026, 11, 11, 2, 59)), None);
+    }
+
+    #[test]
+    fn day_of_month_or_day_of_week() {
+        // 2026-11-13 is a Friday, 2026-11-16 a Monday
+        let after = berlin(2026, 11, 10, 12, 0);
+        assert_eq!(next("0 0 13 * 1", after), Some(berlin(2026, 11, 13, 0, 0)));
+        assert_eq!(next("0 0 13 * 1", berlin(2026, 11, 13, 0, 0)), Some(berlin(2026, 11, 16, 0, 0)));
+        assert_eq!(next("0 0 13 * *", berlin(2026, 11, 13, 0, 0)), Some(berlin(2026, 12, 13, 0, 0)));
+        assert_eq!(next("0 0 * * 1", after), Some(berlin(2026, 11, 16, 0, 0)));
+    }
+
+    #[test]
+    fn sunday_is_0_and_7() {
+        // 2026-11-15 is a Sunday
+        let after = berlin(2026, 11, 10, 12, 0);
+        assert_eq!(next("0 0 * * 7", after), Some(berlin(2026, 11, 15, 0, 0)));
+        assert_eq!(next("0 0 * * 0", after), Some(berlin(2026, 11, 15, 0, 0)));
+    }
+
+    #[test]
+    fn steps_and_ranges() {
+        assert_eq!(Schedule::parse("5/15 * * * *"), Schedule::parse("5,20,35,50 * * * *"));
+        assert_eq!(Schedule::parse("*/15 * * * *"), Schedule::parse("0,15,30,45 * * * *"));
+        assert_eq!(Schedule::parse("0 8-18/4 * * *"), Schedule::parse("0 8,12,16 * * *"));
+        assert_eq!(Schedule::parse("@daily"), Schedule::parse("0 0 * * *"));
+    }
+
+    #[test]
+    fn rejects_invalid_fields() {
+        assert!(Schedule::parse("0 2 * *").is_err());
+        assert!(Schedule::parse("60 * * * *").is_err());
+        assert!(Schedule::parse("*/0 * * * *").is_err());
+        assert!(Schedule::parse("0 0 0 * *").is_err());
+        assert!(Schedule::parse("0 18-8 * * *").is_err());
+    }
+
+    #[test]
+    fn impossible_date_never_fires() {
+        assert_eq!(next("0 0 31 2 *", berlin(2026, 1, 1, 0, 0)), None);
+    }
+}
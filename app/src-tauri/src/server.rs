@@ -1,17 +1,177 @@
-use actix_web::{get, web, App, HttpServer, HttpResponse, Responder};
+use actix_web::{get, web, App, HttpRequest, HttpServer, HttpResponse, Responder};
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::{from_fn, Next};
 use actix_cors::Cors;
-use crate::commands::TelegramState;
+use crate::commands::{AccountCaches, TelegramState};
+use crate::bot::BotDrive;
 use crate::commands::utils::resolve_peer;
-use grammers_client::types::Media;
+use crate::messages::MessageCache;
+use crate::peers::PeerCache;
+use crate::download::{CHUNK_SIZE, RemoteFile, chunk_stream, file_stream};
+use crate::commands::fs::{delete_files, upload_file};
+use crate::restic::{self, ResticObject, ResticRepo};
+use crate::archive::{ZipMode, collect_items, selection_from_path, zip_stream};
+use crate::error::DriveError;
+use crate::transfers::TransferManager;
+use crate::bandwidth::BandwidthManager;
+use crate::accounts::AccountManager;
+use grammers_client::Client;
+use grammers_client::types::{Media, Peer};
+use futures::StreamExt;
+use actix_web::dev::ServerHandle;
+use tauri::{Emitter, Manager};
+use tokio::sync::oneshot;
+use base64::{Engine as _, engine::general_purpose};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
 
-use std::sync::Arc;
+use std::fs::File;
+use std::io::Write;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[get("/stream/{folder_id}/{message_id}")]
+pub const DEFAULT_STREAM_PORT: u16 = 14200;
+
+/// How long a signed stream URL stays valid. Every request is checked, including the
+/// range requests a player sends on seeking, so `MediaPlayer` asks for a new URL when one fails.
+const SIGNED_URL_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Origins the webview loads from (production on each platform, plus the Vite dev server)
+const ALLOWED_ORIGINS: [&str; 4] = [
+    "tauri://localhost",
+    "http://tauri.localhost",
+    "https://tauri.localhost",
+    "http://localhost:1420",
+];
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Signs and verifies streaming URLs with a secret generated on every launch.
+///
+/// Without this any web page open in the user's browser could read private
+/// files from the local server just by guessing a message id.
+pub struct StreamAuth {
+    secret: [u8; 32],
+}
+
+impl Default for StreamAuth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamAuth {
+    pub fn new() -> Self {
+        Self { secret: rand::random() }
+    }
+
+    fn mac(&self, path: &str, expires: u64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(path.as_bytes());
+        mac.update(b"\n");
+        mac.update(expires.to_string().as_bytes());
+        mac
+    }
+
+    /// Returns `path` with `exp` and `sig` query parameters appended.
+    pub fn sign_path(&self, path: &str) -> String {
+        let expires = unix_now() + SIGNED_URL_TTL.as_secs();
+        let sig = general_purpose::URL_SAFE_NO_PAD.encode(self.mac(path, expires).finalize().into_bytes());
+        format!("{}?exp={}&sig={}", path, expires, sig)
+    }
+
+    pub fn verify(&self, path: &str, query: &str) -> bool {
+        let mut expires = None;
+        let mut sig = None;
+        for pair in query.split('&') {
+            match pair.split_once('=') {
+                Some(("exp", v)) => expires = v.parse::<u64>().ok(),
+                Some(("sig", v)) => sig = general_purpose::URL_SAFE_NO_PAD.decode(v).ok(),
+                _ => {}
+            }
+        }
+        let (Some(expires), Some(sig)) = (expires, sig) else { return false; };
+        if expires < unix_now() {
+            return false;
+        }
+        self.mac(path, expires).verify_slice(&sig).is_ok()
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Rejects any request whose URL was not signed by `StreamAuth` for this launch
+async fn require_signed_url(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let authorized = req.app_data::<web::Data<tauri::AppHandle>>()
+        .map(|app| app.state::<StreamAuth>().verify(req.path(), req.query_string()))
+        .unwrap_or(false);
+
+    if !authorized {
+        log::warn!("Rejected unsigned stream request: {}", req.path());
+        return Err(actix_web::error::ErrorForbidden("Missing, invalid or expired stream signature"));
+    }
+    next.call(req).await
+}
+
+/// The account a stream or ZIP is served from, active or parked, with the caches that belong to it
+#[derive(Clone)]
+struct StreamAccount {
+    client: Client,
+    app_handle: tauri::AppHandle,
+    /// `None` for the active account, which uses the managed state
+    parked: Option<Arc<AccountCaches>>,
+}
+
+impl StreamAccount {
+    async fn get(data: &TelegramState, app_handle: &tauri::AppHandle, account: &str) -> Option<Self> {
+        let active_account = app_handle.state::<AccountManager>().active_name();
+        let (client, parked) = data.client_for(account, active_account.as_deref()).await?;
+        Some(Self { client, app_handle: app_handle.clone(), parked })
+    }
+
+    fn peers(&self) -> &PeerCache {
+        match &self.parked {
+            Some(caches) => &caches.peers,
+            None => self.app_handle.state::<PeerCache>().inner(),
+        }
+    }
+
+    fn messages(&self) -> &MessageCache {
+        match &self.parked {
+            Some(caches) => &caches.messages,
+            None => self.app_handle.state::<MessageCache>().inner(),
+        }
+    }
+
+    fn drive(&self) -> &BotDrive {
+        match &self.parked {
+            Some(caches) => &caches.drive,
+            None => self.app_handle.state::<BotDrive>().inner(),
+        }
+    }
+
+    fn bandwidth(&self) -> &BandwidthManager {
+        match &self.parked {
+            Some(caches) => &caches.bandwidth,
+            None => self.app_handle.state::<BandwidthManager>().inner(),
+        }
+    }
+}
+
+#[get("/stream/{account}/{folder_id}/{message_id}")]
 async fn stream_media(
-    path: web::Path<(String, i32)>,
+    path: web::Path<(String, String, i32)>,
     data: web::Data<Arc<TelegramState>>,
+    app_handle: web::Data<tauri::AppHandle>,
 ) -> impl Responder {
-    let (folder_id_str, message_id) = path.into_inner();
+    let (account, folder_id_str, message_id) = path.into_inner();
     
     // Parse folder ID
     let folder_id = if folder_id_str == "me" || folder_id_str == "home" || folder_id_str == "null" {
@@ -23,32 +183,41 @@ async fn stream_media(
         }
     };
 
-    let client_opt = {
-        data.client.lock().await.clone()
-    };
-
-    if let Some(client) = client_opt {
-        match resolve_peer(&client, folder_id).await {
+    // Inactive accounts that are still connected can keep streaming
+    if let Some(stream_account) = StreamAccount::get(&data, &app_handle, &account).await {
+        let client = &stream_account.client;
+        let peers = stream_account.peers();
+        match resolve_peer(client, stream_account.drive(), peers, folder_id).await {
             Ok(peer) => {
-                // Try to fetch message efficiently
-                 match client.get_messages_by_id(peer, &[message_id]).await {
-                    Ok(messages) => {
-                        if let Some(Some(msg)) = messages.first() {
+                 match peers.check(&peer, stream_account.messages().get(client, &peer, message_id).await) {
+                    Ok(message) => {
+                        if let Some(msg) = message {
                             if let Some(media) = msg.media() {
-                                let size = match &media {
-                                    Media::Document(d) => d.size(),
-                                    Media::Photo(_) => 0, 
-                                    _ => 0,
+                                let Some(file) = RemoteFile::from_media(&media) else {
+                                    return HttpResponse::NotFound().body("Message has no downloadable file");
                                 };
+                                let size = file.size;
                                 
                                 let mime = mime_type_from_media(&media);
+
+                                if let Err(e) = stream_account.bandwidth().can_transfer(size.unwrap_or(0)) {
+                                    log::warn!("Refusing stream for message {}: {}", message_id, e);
+                                    return HttpResponse::TooManyRequests().body(e.to_string());
+                                }
                                 
-                                // Create chunk-streaming response
-                                let mut download_iter = client.iter_download(&media);
+                                // Several chunks are fetched ahead while the player reads, in order
+                                let parallelism = app_handle.state::<TransferManager>().download_parallelism();
+                                let mut chunks = Box::pin(file_stream(client.clone(), Arc::new(file), parallelism));
+                                let owner = stream_account.clone();
                                 let stream = async_stream::stream! {
-                                    while let Some(chunk) = download_iter.next().await.transpose() {
+                                    let bw_state = owner.bandwidth();
+                                    while let Some(chunk) = chunks.next().await {
                                         match chunk {
-                                            Ok(bytes) => yield Ok::<_, actix_web::Error>(web::Bytes::from(bytes)),
+                                            Ok(bytes) => {
+                                                // Count only what is actually handed to the player
+                                                bw_state.add_down(bytes.len() as u64);
+                                                yield Ok::<_, actix_web::Error>(web::Bytes::from(bytes));
+                                            },
                                             Err(e) => {
                                                 log::error!("Stream error: {}", e);
                                                 break;
@@ -57,10 +226,13 @@ async fn stream_media(
                                     }
                                 };
                                 
-                                return HttpResponse::Ok()
-                                    .insert_header(("Content-Type", mime)) 
-                                    .insert_header(("Content-Length", size.to_string()))
-                                    .streaming(stream);
+                                let mut response = HttpResponse::Ok();
+                                response.insert_header(("Content-Type", mime));
+                                // Without a known size the body goes out chunked
+                                if let Some(size) = size {
+                                    response.insert_header(("Content-Length", size.to_string()));
+                                }
+                                return response.streaming(stream);
                             }
                         }
                         HttpResponse::NotFound().body("Message or media not found")
@@ -75,6 +247,393 @@ async fn stream_media(
     }
 }
 
+/// A ZIP of the selected files, built while they download (see `archive::selection_to_path`)
+#[get("/zip/{account}/{mode}/{selection}")]
+async fn zip_archive(
+    path: web::Path<(String, String, String)>,
+    data: web::Data<Arc<TelegramState>>,
+    app_handle: web::Data<tauri::AppHandle>,
+) -> impl Responder {
+    let (account, mode, selection) = path.into_inner();
+    let Some(mode) = ZipMode::parse(&mode) else {
+        return HttpResponse::BadRequest().body("Unknown ZIP mode");
+    };
+    let Some(selection) = selection_from_path(&selection) else {
+        return HttpResponse::BadRequest().body("Invalid file selection");
+    };
+
+    let Some(stream_account) = StreamAccount::get(&data, &app_handle, &account).await else {
+        return HttpResponse::ServiceUnavailable().body("Telegram client not connected");
+    };
+
+    let items = match collect_items(
+        &stream_account.client,
+        stream_account.drive(),
+        stream_account.peers(),
+        stream_account.messages(),
+        &selection,
+    ).await {
+        Ok(items) => items,
+        Err(e @ (DriveError::NotFound(_) | DriveError::ChannelUnavailable { .. })) => return HttpResponse::NotFound().body(e.to_string()),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Failed to fetch messages: {}", e)),
+    };
+
+    let total = items.iter().filter_map(|i| i.file.size).sum();
+    if let Err(e) = stream_account.bandwidth().can_transfer(total) {
+        log::warn!("Refusing ZIP download: {}", e);
+        return HttpResponse::TooManyRequests().body(e.to_string());
+    }
+
+    let parallelism = app_handle.state::<TransferManager>().download_parallelism();
+    let client = stream_account.client.clone();
+    let archive = zip_stream(client, items, mode, parallelism, move |n| {
+        stream_account.bandwidth().add_down(n);
+    });
+    // The length is unknown up front, so a failure can only cut the response short
+    let body = archive.map(|bytes| bytes.map(web::Bytes::from).map_err(|e| {
+        log::error!("ZIP stream error: {}", e);
+        actix_web::error::ErrorInternalServerError(e.to_string())
+    }));
+
+    HttpResponse::Ok()
+        .insert_header(("Content-Type", "application/zip"))
+        .insert_header(("Content-Disposition", "attachment; filename=\"telegram-drive.zip\""))
+        .streaming(body)
+}
+
+/// Accept header restic sends when it understands version 2 of the REST protocol
+const RESTIC_V2: &str = "application/vnd.x.restic.rest.v2";
+
+/// Rejects restic requests without the repository's basic auth credentials
+async fn require_restic_auth(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let credentials = req.headers().get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Basic "))
+        .and_then(|v| general_purpose::STANDARD.decode(v).ok())
+        .and_then(|v| String::from_utf8(v).ok());
+    let authorized = match (req.app_data::<web::Data<tauri::AppHandle>>(), credentials) {
+        (Some(app), Some(credentials)) => credentials.split_once(':')
+            .is_some_and(|(user, password)| app.state::<ResticRepo>().check_credentials(user, password)),
+        _ => false,
+    };
+
+    if !authorized {
+        log::warn!("Rejected restic request without valid credentials: {}", req.path());
+        let response = HttpResponse::Unauthorized()
+            .insert_header(("WWW-Authenticate", "Basic realm=\"restic\""))
+            .finish();
+        return Err(actix_web::error::InternalError::from_response("Invalid restic credentials", response).into());
+    }
+    next.call(req).await
+}
+
+/// Object key from the route: `config`, or `<type>/<name>`
+fn restic_key(req: &HttpRequest) -> Option<String> {
+    match (req.match_info().get("kind"), req.match_info().get("name")) {
+        (Some(kind), Some(name)) => restic::object_key(kind, name),
+        _ => Some("config".to_string()),
+    }
+}
+
+fn restic_error(e: DriveError) -> HttpResponse {
+    match e {
+        DriveError::NotFound(_) | DriveError::ChannelUnavailable { .. } => HttpResponse::NotFound().body(e.to_string()),
+        DriveError::BandwidthExceeded(_) => HttpResponse::TooManyRequests().body(e.to_string()),
+        DriveError::NotConnected => HttpResponse::ServiceUnavailable().body(e.to_string()),
+        _ => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// Client and folder of the restic repository, which is only served while its account is active
+async fn restic_folder(data: &TelegramState, app_handle: &tauri::AppHandle) -> Result<(Client, Peer, i64), HttpResponse> {
+    let Some(repo) = app_handle.state::<ResticRepo>().config() else {
+        return Err(HttpResponse::NotFound().body("No restic repository is set up"));
+    };
+    if app_handle.state::<AccountManager>().active_name().as_deref() != Some(repo.account.as_str()) {
+        return Err(HttpResponse::ServiceUnavailable().body(format!("Account '{}' is not the active account", repo.account)));
+    }
+    let Some(client) = data.client.lock().await.clone() else {
+        return Err(HttpResponse::ServiceUnavailable().body("Telegram client not connected"));
+    };
+    let peer = resolve_peer(&client, &app_handle.state::<BotDrive>(), &app_handle.state::<PeerCache>(), Some(repo.folder_id))
+        .await
+        .map_err(restic_error)?;
+    Ok((client, peer, repo.folder_id))
+}
+
+/// `POST /?create=true`. The folder already exists, so there is nothing to create.
+async fn restic_create_repo(data: web::Data<Arc<TelegramState>>, app_handle: web::Data<tauri::AppHandle>) -> HttpResponse {
+    match restic_folder(&data, &app_handle).await {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(response) => response,
+    }
+}
+
+/// `GET /{type}/`: names only, or names and sizes for protocol version 2
+async fn restic_list(
+    req: HttpRequest,
+    data: web::Data<Arc<TelegramState>>,
+    app_handle: web::Data<tauri::AppHandle>,
+) -> HttpResponse {
+    if let Err(response) = restic_folder(&data, &app_handle).await {
+        return response;
+    }
+    let kind = req.match_info().get("kind").unwrap_or_default();
+    if !restic::OBJECT_TYPES.contains(&kind) {
+        return HttpResponse::NotFound().body("Unknown object type");
+    }
+    let objects = app_handle.state::<ResticRepo>().list(kind);
+    let v2 = req.headers().get("Accept")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains(RESTIC_V2));
+
+    if v2 {
+        let body: Vec<_> = objects.into_iter()
+            .map(|(name, size)| serde_json::json!({ "name": name, "size": size }))
+            .collect();
+        HttpResponse::Ok().content_type(RESTIC_V2).json(body)
+    } else {
+        let names: Vec<String> = objects.into_iter().map(|(name, _)| name).collect();
+        HttpResponse::Ok().json(names)
+    }
+}
+
+/// `HEAD /config` and `HEAD /{type}/{name}`, answered from the lookup once the account is checked
+async fn restic_head(
+    req: HttpRequest,
+    data: web::Data<Arc<TelegramState>>,
+    app_handle: web::Data<tauri::AppHandle>,
+) -> HttpResponse {
+    if let Err(response) = restic_folder(&data, &app_handle).await {
+        return response;
+    }
+    match restic_key(&req).and_then(|key| app_handle.state::<ResticRepo>().object(&key)) {
+        Some(object) => HttpResponse::Ok()
+            .no_chunking(object.size)
+            .streaming(futures::stream::empty::<Result<web::Bytes, actix_web::Error>>()),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// `GET /config` and `GET /{type}/{name}`, honoring a single `Range: bytes=` range
+async fn restic_get(
+    req: HttpRequest,
+    data: web::Data<Arc<TelegramState>>,
+    app_handle: web::Data<tauri::AppHandle>,
+) -> HttpResponse {
+    let Some(object) = restic_key(&req).and_then(|key| app_handle.state::<ResticRepo>().object(&key)) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let range = req.headers().get("Range").and_then(|v| v.to_str().ok());
+    let (start, end) = match range.map(|r| parse_range(r, object.size)) {
+        None => (0, object.size),
+        Some(Some(range)) => range,
+        Some(None) => {
+            return HttpResponse::RangeNotSatisfiable()
+                .insert_header(("Content-Range", format!("bytes */{}", object.size)))
+                .finish();
+        }
+    };
+
+    let (client, peer, _) = match restic_folder(&data, &app_handle).await {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+    let media = match app_handle.state::<MessageCache>().get(&client, &peer, object.message_id).await {
+        Ok(message) => message.and_then(|m| m.media()),
+        Err(e) => return restic_error(e),
+    };
+    let Some(file) = media.as_ref().and_then(RemoteFile::from_media) else {
+        return HttpResponse::NotFound().body("The object's message has no file");
+    };
+    let length = end - start;
+    if let Err(e) = app_handle.state::<BandwidthManager>().can_transfer(length) {
+        return HttpResponse::TooManyRequests().body(e.to_string());
+    }
+
+    // Telegram serves whole chunks, so start at the chunk holding `start` and trim
+    let first_chunk = start - start % CHUNK_SIZE as u64;
+    let parallelism = app_handle.state::<TransferManager>().download_parallelism();
+    let mut chunks = Box::pin(chunk_stream(client, Arc::new(file), first_chunk, end, parallelism));
+    let mut skip = (start - first_chunk) as usize;
+    let mut remaining = length;
+    let app_handle = app_handle.clone();
+    let stream = async_stream::stream! {
+        let bw_state = app_handle.state::<BandwidthManager>();
+        while remaining > 0 {
+            let Some(chunk) = chunks.next().await else { break };
+            match chunk {
+                Ok(bytes) => {
+                    let from = skip.min(bytes.len());
+                    let to = (from as u64 + remaining).min(bytes.len() as u64) as usize;
+                    skip -= from;
+                    remaining -= (to - from) as u64;
+                    bw_state.add_down((to - from) as u64);
+                    yield Ok::<_, actix_web::Error>(web::Bytes::copy_from_slice(&bytes[from..to]));
+                }
+                Err(e) => {
+                    log::error!("restic download error: {}", e);
+                    yield Err(actix_web::error::ErrorInternalServerError(e.to_string()));
+                    break;
+                }
+            }
+        }
+    };
+
+    let mut response = if range.is_some() { HttpResponse::PartialContent() } else { HttpResponse::Ok() };
+    if range.is_some() {
+        response.insert_header(("Content-Range", format!("bytes {}-{}/{}", start, end - 1, object.size)));
+    }
+    response
+        .insert_header(("Content-Type", "application/octet-stream"))
+        .no_chunking(length)
+        .streaming(stream)
+}
+
+/// Start and exclusive end of a `bytes=` range, `None` if it cannot be served
+fn parse_range(header: &str, size: u64) -> Option<(u64, u64)> {
+    let (first, last) = header.strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = match (first.trim(), last.trim()) {
+        // The last n bytes
+        ("", suffix) => (size.saturating_sub(suffix.parse().ok()?), size),
+        (first, "") => (first.parse().ok()?, size),
+        (first, last) => (first.parse().ok()?, last.parse::<u64>().ok()?.saturating_add(1).min(size)),
+    };
+    (start < end).then_some((start, end))
+}
+
+/// `POST /config` and `POST /{type}/{name}`: stores the body as a document in the repository folder.
+///
+/// Objects never change once written, so existing ones are refused. Names other than
+/// `config` are the SHA-256 of the content, which is checked before uploading.
+async fn restic_save(
+    req: HttpRequest,
+    mut payload: web::Payload,
+    data: web::Data<Arc<TelegramState>>,
+    app_handle: web::Data<tauri::AppHandle>,
+) -> HttpResponse {
+    let Some(key) = restic_key(&req) else {
+        return HttpResponse::BadRequest().body("Invalid object name");
+    };
+    let restic_repo = app_handle.state::<ResticRepo>();
+    // Held until the object is inserted, so a second request for the same key cannot slip in meanwhile
+    let Some(_reservation) = restic_repo.reserve(&key) else {
+        return HttpResponse::Forbidden().body("Object already exists");
+    };
+    let (client, _, folder_id) = match restic_folder(&data, &app_handle).await {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+
+    let dir = match app_handle.path().app_cache_dir() {
+        Ok(dir) => dir.join("restic"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    // The document takes the file's name, so concurrent uploads each get a directory
+    let path = dir.join(format!("{:08x}", rand::random::<u32>())).join(restic::document_name(&key));
+    let written = write_payload(&mut payload, &path).await;
+    let result = match written {
+        Ok(hash) if key != "config" && key.rsplit('/').next() != Some(hash.as_str()) => {
+            Err(HttpResponse::BadRequest().body("Content does not match the object name"))
+        }
+        Ok(_) => {
+            let transfers = app_handle.state::<TransferManager>();
+            upload_file(
+                &client,
+                &app_handle.state::<BandwidthManager>(),
+                &app_handle.state::<BotDrive>(),
+                &app_handle.state::<PeerCache>(),
+                &transfers,
+                &path,
+                Some(folder_id),
+                |_| {},
+            ).await.map_err(restic_error)
+        }
+        Err(e) => Err(HttpResponse::BadRequest().body(e.to_string())),
+    };
+    let size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::remove_dir_all(parent);
+    }
+
+    match result {
+        Ok(message_id) => {
+            restic_repo.insert(key, ResticObject { message_id, size });
+            HttpResponse::Ok().finish()
+        }
+        Err(response) => response,
+    }
+}
+
+/// Streams a request body into `path`, returning its SHA-256 in hex
+async fn write_payload(payload: &mut web::Payload, path: &std::path::Path) -> Result<String, DriveError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut out = File::create(path)?;
+    let mut hasher = Sha256::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| DriveError::Io(e.to_string()))?;
+        hasher.update(&chunk);
+        out.write_all(&chunk)?;
+    }
+    out.sync_all()?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// `DELETE /config` and `DELETE /{type}/{name}`
+async fn restic_delete(
+    req: HttpRequest,
+    data: web::Data<Arc<TelegramState>>,
+    app_handle: web::Data<tauri::AppHandle>,
+) -> HttpResponse {
+    let Some(key) = restic_key(&req) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let restic_repo = app_handle.state::<ResticRepo>();
+    let Some(object) = restic_repo.object(&key) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let (client, _, folder_id) = match restic_folder(&data, &app_handle).await {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+    let deleted = delete_files(
+        &client,
+        &app_handle.state::<BotDrive>(),
+        &app_handle.state::<PeerCache>(),
+        &app_handle.state::<MessageCache>(),
+        &[object.message_id],
+        Some(folder_id),
+    ).await;
+    match deleted {
+        Ok(_) => {
+            restic_repo.remove(&key);
+            HttpResponse::Ok().finish()
+        }
+        Err(e) => restic_error(e),
+    }
+}
+
+/// restic's REST backend protocol, served at `/restic/`
+fn restic_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/").route(web::post().to(restic_create_repo)))
+        .service(web::resource("/config")
+            .route(web::head().to(restic_head))
+            .route(web::get().to(restic_get))
+            .route(web::post().to(restic_save))
+            .route(web::delete().to(restic_delete)))
+        .service(web::resource("/{kind}/").route(web::get().to(restic_list)))
+        .service(web::resource("/{kind}/{name}")
+            .route(web::head().to(restic_head))
+            .route(web::get().to(restic_get))
+            .route(web::post().to(restic_save))
+            .route(web::delete().to(restic_delete)));
+}
+
 fn mime_type_from_media(media: &Media) -> String {
     match media {
         Media::Document(d) => d.mime_type().unwrap_or("application/octet-stream").to_string(),
@@ -82,18 +641,154 @@ fn mime_type_from_media(media: &Media) -> String {
     }
 }
 
-pub async fn start_server(state: Arc<TelegramState>, port: u16) -> std::io::Result<()> {
+/// Port and handle of the running streaming server, so it can be reported and restarted.
+pub struct StreamServer {
+    preferred_port: Mutex<u16>,
+    port: Mutex<Option<u16>>,
+    handle: Mutex<Option<ServerHandle>>,
+}
+
+impl Default for StreamServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamServer {
+    pub fn new() -> Self {
+        Self {
+            preferred_port: Mutex::new(DEFAULT_STREAM_PORT),
+            port: Mutex::new(None),
+            handle: Mutex::new(None),
+        }
+    }
+
+    /// Port the server is currently listening on, if it is running
+    pub fn port(&self) -> Option<u16> {
+        *self.port.lock().unwrap()
+    }
+
+    pub fn set_preferred_port(&self, port: u16) {
+        *self.preferred_port.lock().unwrap() = port;
+    }
+
+    /// Stops the running server (if any) and waits for it to release its port.
+    pub async fn stop(&self) {
+        let handle = self.handle.lock().unwrap().take();
+        *self.port.lock().unwrap() = None;
+        if let Some(handle) = handle {
+            log::info!("Stopping Streaming Server...");
+            handle.stop(true).await;
+        }
+    }
+}
+
+/// Starts the streaming server on a dedicated thread (Actix needs its own runtime).
+///
+/// Tries the preferred port first and falls back to any free port the OS hands out.
+/// The chosen port is stored in `StreamServer` and emitted as `stream-server-started`.
+/// The returned receiver gets the port once the server listens, or why it could not.
+pub fn spawn_server(app_handle: tauri::AppHandle) -> oneshot::Receiver<Result<u16, String>> {
+    let (started_tx, started_rx) = oneshot::channel();
+    std::thread::spawn(move || {
+        let sys = actix_rt::System::new();
+        sys.block_on(async move {
+            let mut started = Some(started_tx);
+            if let Err(e) = run_server(app_handle.clone(), &mut started).await {
+                log::error!("Streaming server failed: {}", e);
+                let _ = app_handle.emit("stream-server-failed", e.to_string());
+                if let Some(started) = started.take() {
+                    let _ = started.send(Err(e.to_string()));
+                }
+            }
+        });
+    });
+    started_rx
+}
+
+async fn run_server(app_handle: tauri::AppHandle, started: &mut Option<oneshot::Sender<Result<u16, String>>>) -> std::io::Result<()> {
+    let stream_server = app_handle.state::<StreamServer>();
+    let preferred = *stream_server.preferred_port.lock().unwrap();
+
+    let listener = match TcpListener::bind(("127.0.0.1", preferred)) {
+        Ok(l) => l,
+        Err(e) => {
+            log::warn!("Port {} unavailable ({}), falling back to a free port", preferred, e);
+            TcpListener::bind(("127.0.0.1", 0))?
+        }
+    };
+    let port = listener.local_addr()?.port();
+
+    let state = Arc::new(app_handle.state::<TelegramState>().inner().clone());
     let state_data = web::Data::new(state);
+    let app_data = web::Data::new(app_handle.clone());
     
     log::info!("Starting Streaming Server on port {}", port);
     
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
+        let cors = ALLOWED_ORIGINS.iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+            .allowed_methods(vec!["GET", "HEAD"]);
+
         App::new()
-            .wrap(Cors::permissive())
+            // CORS wraps everything so it runs first and can answer preflights without a signature
+            .wrap(cors)
             .app_data(state_data.clone())
-            .service(stream_media)
+            .app_data(app_data.clone())
+            // restic cannot sign URLs, it signs in with basic auth instead
+            .service(web::scope("/restic")
+                .wrap(from_fn(require_restic_auth))
+                .configure(restic_routes))
+            .service(web::scope("")
+                .wrap(from_fn(require_signed_url))
+                .service(stream_media)
+                .service(zip_archive))
     })
-    .bind(("127.0.0.1", port))?
-    .run()
-    .await
+    .listen(listener)?
+    .run();
+
+    *stream_server.handle.lock().unwrap() = Some(server.handle());
+    *stream_server.port.lock().unwrap() = Some(port);
+    let _ = app_handle.emit("stream-server-started", port);
+    if let Some(started) = started.take() {
+        let _ = started.send(Ok(port));
+    }
+
+    server.await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_are_exclusive_at_the_end() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some((0, 100)));
+        assert_eq!(parse_range("bytes=100-", 1000), Some((100, 1000)));
+        assert_eq!(parse_range("bytes=-100", 1000), Some((900, 1000)));
+        assert_eq!(parse_range("bytes= 5 - 9 ", 1000), Some((5, 10)));
+    }
+
+    #[test]
+    fn ranges_are_clamped_to_the_size() {
+        assert_eq!(parse_range("bytes=900-5000", 1000), Some((900, 1000)));
+        assert_eq!(parse_range("bytes=-5000", 1000), Some((0, 1000)));
+        assert_eq!(parse_range(&format!("bytes=0-{}", u64::MAX), 1000), Some((0, 1000)));
+    }
+
+    #[test]
+    fn unservable_ranges_are_rejected() {
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=50-10", 1000), None);
+        assert_eq!(parse_range("bytes=-0", 1000), None);
+        assert_eq!(parse_range("bytes=0-0", 0), None);
+    }
+
+    #[test]
+    fn malformed_ranges_are_rejected() {
+        assert_eq!(parse_range("items=0-99", 1000), None);
+        assert_eq!(parse_range("bytes=0", 1000), None);
+        assert_eq!(parse_range("bytes=a-b", 1000), None);
+        assert_eq!(parse_range("bytes=-", 1000), None);
+    }
 }
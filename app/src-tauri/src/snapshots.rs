@@ -0,0 +1,796 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use chrono::Utc;
+use grammers_client::Client;
+use grammers_client::types::Media;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{Emitter, Manager};
+use walkdir::WalkDir;
+use crate::accounts::AccountManager;
+use crate::bandwidth::BandwidthManager;
+use crate::bot::BotDrive;
+use crate::chunker::Chunker;
+use crate::commands::TelegramState;
+use crate::commands::fs::{create_folder, delete_files, upload_file};
+use crate::commands::tree::relative_path;
+use crate::commands::utils::resolve_peer;
+use crate::download::{FileSource, download_to_path, part_path};
+use crate::error::DriveError;
+use crate::filenames::{CollisionStrategy, resolve_collision, sanitize_filename};
+use crate::messages::MessageCache;
+use crate::peers::PeerCache;
+use crate::transfers::TransferManager;
+
+/// New chunks are uploaded together once they add up to this much
+const PACK_SIZE: u64 = 32 * 1024 * 1024;
+/// Most messages `messages.deleteMessages` takes at once
+const DELETE_BATCH: usize = 100;
+/// Name of the channel created for the repository
+const CHANNEL_NAME: &str = "Snapshots";
+
+/// Where a chunk is stored: a byte range of an uploaded pack
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+struct ChunkLocation {
+    /// Message holding the pack
+    pack: i32,
+    offset: u64,
+    length: u32,
+}
+
+/// A snapshot as listed, without its tree
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SnapshotInfo {
+    pub id: u32,
+    /// Unix time
+    pub time: i64,
+    pub paths: Vec<PathBuf>,
+    pub files: usize,
+    /// Total size of the files
+    pub size: u64,
+    /// Bytes of new chunks uploaded for this snapshot; the rest was already stored
+    pub added: u64,
+    /// Files that could not be read and are missing from the snapshot
+    pub failed: usize,
+    /// Message holding the uploaded tree
+    pub message_id: i32,
+}
+
+/// Per-account index of the repository, stored in `snapshots/repo.json`
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
+struct RepoIndex {
+    /// Channel holding packs and trees, created on the first snapshot
+    channel_id: Option<i64>,
+    /// Size of each uploaded pack, by message ID
+    packs: BTreeMap<i32, u64>,
+    /// By SHA-256 of the chunk, in hex
+    chunks: HashMap<String, ChunkLocation>,
+    /// Oldest first
+    snapshots: Vec<SnapshotInfo>,
+    next_id: u32,
+}
+
+/// A file in a snapshot: its chunks in order
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct TreeEntry {
+    size: u64,
+    /// Milliseconds since the epoch
+    modified: u64,
+    chunks: Vec<String>,
+}
+
+/// The files of a snapshot, by path. Each snapshotted path is a top-level name,
+/// e.g. backing up `/home/me/Documents` gives `Documents/notes.txt`.
+///
+/// The uploaded copy also carries the snapshot and where each of its chunks is,
+/// so `rebuild` can recreate the index from the channel alone.
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
+struct Tree {
+    files: BTreeMap<String, TreeEntry>,
+    info: Option<SnapshotInfo>,
+    /// By chunk hash, for the chunks of `files`
+    locations: BTreeMap<String, ChunkLocation>,
+}
+
+/// Paths that differ between two snapshots
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct SnapshotDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RestoreResult {
+    pub restored: usize,
+    /// Files left alone because they already existed
+    pub skipped: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PruneResult {
+    /// Packs deleted from the channel
+    pub packs: usize,
+    pub bytes: u64,
+}
+
+/// Emitted as `snapshot-progress` while a snapshot is created or restored
+#[derive(Debug, Serialize, Clone)]
+pub struct SnapshotProgress {
+    pub snapshot_id: u32,
+    /// File being read, or pack being uploaded or downloaded
+    pub current: String,
+    pub done: usize,
+    pub total: usize,
+}
+
+/// The active account's repository on disk
+struct Repo {
+    dir: PathBuf,
+    index: RepoIndex,
+}
+
+impl Repo {
+    fn open(accounts: &AccountManager) -> Result<Self, DriveError> {
+        let account = accounts.active_name().ok_or(DriveError::NotConnected)?;
+        let dir = accounts.account_dir(&account).join("snapshots");
+        let index = fs::read_to_string(dir.join("repo.json"))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Ok(Self { dir, index })
+    }
+
+    fn save(&self) -> Result<(), DriveError> {
+        fs::create_dir_all(&self.dir)?;
+        let json = serde_json::to_string(&self.index).map_err(|e| DriveError::Other(e.to_string()))?;
+        crate::atomic::write(self.dir.join("repo.json"), json)?;
+        Ok(())
+    }
+
+    fn info(&self, id: u32) -> Result<&SnapshotInfo, DriveError> {
+        self.index.snapshots.iter().find(|s| s.id == id)
+            .ok_or_else(|| DriveError::NotFound(format!("Snapshot {} not found", id)))
+    }
+
+    fn tree_path(&self, id: u32) -> PathBuf {
+        self.dir.join(format!("snapshot-{}.json", id))
+    }
+
+    fn tree(&self, id: u32) -> Result<Tree, DriveError> {
+        self.info(id)?;
+        let content = fs::read_to_string(self.tree_path(id))?;
+        serde_json::from_str(&content).map_err(|e| DriveError::Other(format!("Snapshot {} is damaged: {}", id, e)))
+    }
+
+    fn channel(&self) -> Result<i64, DriveError> {
+        self.index.channel_id.ok_or_else(|| DriveError::NotFound("There are no snapshots yet".to_string()))
+    }
+}
+
+/// New chunks written to a local file until it is big enough to upload
+struct Pack {
+    path: PathBuf,
+    out: BufWriter<File>,
+    /// Hash, offset and length of each chunk
+    chunks: Vec<(String, u64, u32)>,
+    len: u64,
+}
+
+impl Pack {
+    fn create(path: PathBuf) -> Result<Self, DriveError> {
+        let out = BufWriter::new(File::create(&path)?);
+        Ok(Self { path, out, chunks: Vec::new(), len: 0 })
+    }
+
+    fn add(&mut self, hash: String, data: &[u8]) -> Result<(), DriveError> {
+        self.out.write_all(data)?;
+        self.chunks.push((hash, self.len, data.len() as u32));
+        self.len += data.len() as u64;
+        Ok(())
+    }
+
+    fn contains(&self, hash: &str) -> bool {
+        self.chunks.iter().any(|(h, _, _)| h == hash)
+    }
+}
+
+/// The packs of a snapshot being created, numbered in order; a new one is started
+/// after each upload
+struct PackWriter {
+    dir: PathBuf,
+    snapshot_id: u32,
+    written: usize,
+    current: Option<Pack>,
+}
+
+impl PackWriter {
+    fn contains(&self, hash: &str) -> bool {
+        self.current.as_ref().is_some_and(|pack| pack.contains(hash))
+    }
+
+    fn add(&mut self, hash: String, data: &[u8]) -> Result<(), DriveError> {
+        if self.current.is_none() {
+            self.written += 1;
+            let path = self.dir.join(format!("pack-{}-{}.bin", self.snapshot_id, self.written));
+            self.current = Some(Pack::create(path)?);
+        }
+        match self.current.as_mut() {
+            Some(pack) => pack.add(hash, data),
+            None => Ok(()),
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.current.as_ref().is_some_and(|pack| pack.len >= PACK_SIZE)
+    }
+}
+
+/// A file being chunked, which may take several packs
+struct FileChunks {
+    chunker: Chunker<File>,
+    /// Hashes of the chunks read so far
+    chunks: Vec<String>,
+    read: u64,
+    /// Bytes of new chunks written to packs
+    added: u64,
+}
+
+/// Where `fill_pack` stopped
+enum Fill {
+    /// The whole file is chunked
+    Done,
+    /// The current pack has to be uploaded before the rest of the file
+    PackFull,
+    /// New chunks read before the error stay in the pack
+    Unreadable(std::io::Error),
+}
+
+/// Chunks `file` into new packs until it ends or a pack is full. Chunks already in
+/// `stored` or in the current pack are only referenced. Blocks while reading,
+/// hashing and writing.
+fn fill_pack(file: &mut FileChunks, packs: &mut PackWriter, stored: &HashMap<String, ChunkLocation>) -> Result<Fill, DriveError> {
+    for chunk in file.chunker.by_ref() {
+        let data = match chunk {
+            Ok(data) => data,
+            Err(e) => return Ok(Fill::Unreadable(e)),
+        };
+        file.read += data.len() as u64;
+        let hash = format!("{:x}", Sha256::digest(&data));
+        if !stored.contains_key(&hash) && !packs.contains(&hash) {
+            packs.add(hash.clone(), &data)?;
+            file.added += data.len() as u64;
+        }
+        file.chunks.push(hash);
+        if packs.is_full() {
+            return Ok(Fill::PackFull);
+        }
+    }
+    Ok(Fill::Done)
+}
+
+/// Deduplicated snapshots of local paths, kept in a channel of the active account.
+///
+/// Files are split into content-defined chunks (see `Chunker`) and only chunks the
+/// repository does not have yet are uploaded, packed together into documents of
+/// about `PACK_SIZE`. Each snapshot records a tree of its files and their chunks,
+/// stored locally and uploaded next to the packs. The chunk index lives in the
+/// account's `snapshots/repo.json` and can be rebuilt from the uploaded trees.
+/// One operation runs at a time.
+pub struct SnapshotManager {
+    busy: tokio::sync::Mutex<()>,
+}
+
+impl Default for SnapshotManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SnapshotManager {
+    pub fn new() -> Self {
+        Self { busy: tokio::sync::Mutex::new(()) }
+    }
+
+    fn start(&self) -> Result<tokio::sync::MutexGuard<'_, ()>, DriveError> {
+        self.busy.try_lock().map_err(|_| DriveError::Other("Another snapshot operation is running".to_string()))
+    }
+
+    /// Snapshots of the active account, oldest first
+    pub fn list(&self, accounts: &AccountManager) -> Result<Vec<SnapshotInfo>, DriveError> {
+        Ok(Repo::open(accounts)?.index.snapshots)
+    }
+
+    pub fn diff(&self, accounts: &AccountManager, from: u32, to: u32) -> Result<SnapshotDiff, DriveError> {
+        let repo = Repo::open(accounts)?;
+        let (old, new) = (repo.tree(from)?, repo.tree(to)?);
+        let mut diff = SnapshotDiff::default();
+        for (path, entry) in &new.files {
+            match old.files.get(path) {
+                None => diff.added.push(path.clone()),
+                Some(previous) if previous.chunks != entry.chunks => diff.modified.push(path.clone()),
+                Some(_) => {}
+            }
+        }
+        diff.removed = old.files.keys().filter(|p| !new.files.contains_key(*p)).cloned().collect();
+        Ok(diff)
+    }
+
+    /// Takes a snapshot of `paths`, files or directories.
+    ///
+    /// Files whose size and modification time match the latest snapshot are not read
+    /// again. Packs uploaded before a failure stay in the index, so a retry does not
+    /// upload them twice.
+    pub async fn create(&self, app_handle: &tauri::AppHandle, paths: Vec<PathBuf>) -> Result<SnapshotInfo, DriveError> {
+        let _busy = self.start()?;
+        let client = connected_client(app_handle).await?;
+        let mut repo = Repo::open(&app_handle.state::<AccountManager>())?;
+
+        let mut names = HashSet::new();
+        for path in &paths {
+            let name = path.file_name().map(|n| n.to_string_lossy().to_string())
+                .ok_or_else(|| DriveError::Other(format!("Cannot snapshot {}", path.display())))?;
+            if !names.insert(name.clone()) {
+                return Err(DriveError::Other(format!("Two of the paths are named '{}'", name)));
+            }
+            if !path.exists() {
+                return Err(DriveError::NotFound(format!("{} does not exist", path.display())));
+            }
+        }
+
+        fs::create_dir_all(&repo.dir)?;
+        let channel_id = match repo.index.channel_id {
+            Some(id) => id,
+            None => {
+                let folder = create_folder(&client, &app_handle.state::<BotDrive>(), &app_handle.state::<PeerCache>(), CHANNEL_NAME.to_string()).await?;
+                repo.index.channel_id = Some(folder.id);
+                repo.save()?;
+                folder.id
+            }
+        };
+
+        let id = repo.index.next_id + 1;
+        let parent = match repo.index.snapshots.last() {
+            Some(last) => repo.tree(last.id).unwrap_or_default(),
+            None => Tree::default(),
+        };
+
+        let files = collect_files(&paths);
+        let mut tree = Tree::default();
+        let mut info = SnapshotInfo {
+            id,
+            time: Utc::now().timestamp(),
+            paths: paths.clone(),
+            files: 0,
+            size: 0,
+            added: 0,
+            failed: 0,
+            message_id: 0,
+        };
+        let mut packs = PackWriter { dir: repo.dir.clone(), snapshot_id: id, written: 0, current: None };
+
+        for (done, (key, path)) in files.iter().enumerate() {
+            let _ = app_handle.emit("snapshot-progress", SnapshotProgress {
+                snapshot_id: id,
+                current: key.clone(),
+                done,
+                total: files.len(),
+            });
+            let Some((size, modified)) = file_stamp(path) else {
+                log::warn!("Snapshot {}: cannot read {}", id, path.display());
+                info.failed += 1;
+                continue;
+            };
+            if let Some(previous) = parent.files.get(key) {
+                if previous.size == size && previous.modified == modified
+                    && previous.chunks.iter().all(|c| repo.index.chunks.contains_key(c))
+                {
+                    tree.files.insert(key.clone(), previous.clone());
+                    info.files += 1;
+                    info.size += size;
+                    continue;
+                }
+            }
+
+            let reader = match File::open(path) {
+                Ok(file) => file,
+                Err(e) => {
+                    log::warn!("Snapshot {}: cannot read {}: {}", id, path.display(), e);
+                    info.failed += 1;
+                    continue;
+                }
+            };
+            let mut file = FileChunks { chunker: Chunker::new(reader), chunks: Vec::new(), read: 0, added: 0 };
+            let readable = loop {
+                // Reading and hashing block, so they run off the async runtime and hand everything back
+                let fill;
+                (repo, packs, file, fill) = tokio::task::spawn_blocking(move || {
+                    let fill = fill_pack(&mut file, &mut packs, &repo.index.chunks);
+                    (repo, packs, file, fill)
+                }).await.map_err(|e| DriveError::Other(e.to_string()))?;
+                match fill? {
+                    Fill::Done => break true,
+                    Fill::PackFull => {
+                        if let Some(full) = packs.current.take() {
+                            upload_pack(app_handle, &client, &mut repo, channel_id, full).await?;
+                        }
+                    }
+                    Fill::Unreadable(e) => {
+                        log::warn!("Snapshot {}: cannot read {}: {}", id, path.display(), e);
+                        break false;
+                    }
+                }
+            };
+            info.added += file.added;
+            if !readable {
+                info.failed += 1;
+                continue;
+            }
+            tree.files.insert(key.clone(), TreeEntry { size: file.read, modified, chunks: file.chunks });
+            info.files += 1;
+            info.size += file.read;
+        }
+        if let Some(last) = packs.current.take() {
+            upload_pack(app_handle, &client, &mut repo, channel_id, last).await?;
+        }
+
+        // The tree goes up with the packs, so the channel holds everything a snapshot refers to
+        tree.locations = tree.files.values()
+            .flat_map(|entry| &entry.chunks)
+            .filter_map(|hash| repo.index.chunks.get(hash).map(|location| (hash.clone(), *location)))
+            .collect();
+        tree.info = Some(info.clone());
+        let tree_path = repo.tree_path(id);
+        let json = serde_json::to_string(&tree).map_err(|e| DriveError::Other(e.to_string()))?;
+        fs::write(&tree_path, json)?;
+        info.message_id = upload_to(app_handle, &client, &tree_path, channel_id).await?;
+
+        repo.index.next_id = id;
+        repo.index.snapshots.push(info.clone());
+        repo.save()?;
+        log::info!("Snapshot {}: {} files, {} new bytes, {} failed", id, info.files, info.added, info.failed);
+        Ok(info)
+    }
+
+    /// Restores `path` from a snapshot into `dest`, or the whole snapshot without one.
+    ///
+    /// The selected path keeps its name, so restoring `Documents/work` creates
+    /// `dest/work`. Files are written to `<name>.part` first, so an existing file is
+    /// only replaced once the restored one is complete.
+    pub async fn restore(
+        &self,
+        app_handle: &tauri::AppHandle,
+        id: u32,
+        path: Option<String>,
+        dest: &Path,
+        collision: CollisionStrategy,
+    ) -> Result<RestoreResult, DriveError> {
+        let _busy = self.start()?;
+        let client = connected_client(app_handle).await?;
+        let repo = Repo::open(&app_handle.state::<AccountManager>())?;
+        let tree = repo.tree(id)?;
+
+        let selected = path.as_deref().map(|p| p.trim_matches('/')).unwrap_or("");
+        // Everything up to the selected name is dropped from the restored paths
+        let base = selected.rfind('/').map(|i| i + 1).unwrap_or(0);
+        let entries: Vec<(&String, &TreeEntry)> = tree.files.iter()
+            .filter(|(key, _)| {
+                selected.is_empty()
+                    || key.as_str() == selected
+                    || key.strip_prefix(selected).is_some_and(|rest| rest.starts_with('/'))
+            })
+            .collect();
+        if entries.is_empty() {
+            return Err(DriveError::NotFound(format!("'{}' is not in snapshot {}", selected, id)));
+        }
+
+        let mut result = RestoreResult { restored: 0, skipped: 0, bytes: 0 };
+        let mut targets = Vec::new();
+        // Chunks to copy out of each pack: location, hash, file to write and offset in it
+        let mut plan: BTreeMap<i32, Vec<(ChunkLocation, String, PathBuf, u64)>> = BTreeMap::new();
+        for (key, entry) in entries {
+            let mut target = dest.to_path_buf();
+            for part in key[base..].split('/').filter(|p| !p.is_empty()) {
+                target.push(sanitize_filename(part));
+            }
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let Some(target) = resolve_collision(&target, collision) else {
+                result.skipped += 1;
+                continue;
+            };
+            // Written beside the target, which is only replaced once complete
+            let part = part_path(&target);
+            let mut offset = 0;
+            for hash in &entry.chunks {
+                let location = *repo.index.chunks.get(hash)
+                    .ok_or_else(|| DriveError::NotFound(format!("Chunk {} of {} is missing from the repository", hash, key)))?;
+                plan.entry(location.pack).or_default().push((location, hash.clone(), part.clone(), offset));
+                offset += location.length as u64;
+            }
+            File::create(&part)?.set_len(entry.size)?;
+            targets.push((target, part, entry.modified));
+            result.restored += 1;
+            result.bytes += entry.size;
+        }
+
+        let copied = copy_packs(app_handle, &client, &repo, id, plan).await;
+        if copied.is_err() {
+            for (_, part, _) in &targets {
+                let _ = fs::remove_file(part);
+            }
+        }
+        copied?;
+
+        for (target, part, modified) in &targets {
+            if let Ok(file) = OpenOptions::new().write(true).open(part) {
+                let _ = file.set_modified(UNIX_EPOCH + Duration::from_millis(*modified));
+            }
+            fs::rename(part, target)?;
+        }
+        log::info!("Restored {} files from snapshot {} to {}", result.restored, id, dest.display());
+        Ok(result)
+    }
+
+    /// Recreates the index of the active account from the snapshot channel `channel_id`,
+    /// e.g. on a new computer or after `repo.json` was lost. Replaces the current index.
+    ///
+    /// Every pack in the channel is listed, so `prune` can still delete packs left
+    /// behind by a failed snapshot, but only chunks used by a snapshot are indexed.
+    pub async fn rebuild(&self, app_handle: &tauri::AppHandle, channel_id: i64) -> Result<Vec<SnapshotInfo>, DriveError> {
+        let _busy = self.start()?;
+        let client = connected_client(app_handle).await?;
+        let drive = app_handle.state::<BotDrive>();
+        if drive.is_bot() {
+            return Err(DriveError::Other("Bots cannot read the snapshot channel's history".to_string()));
+        }
+        let dir = Repo::open(&app_handle.state::<AccountManager>())?.dir;
+        let peer = resolve_peer(&client, &drive, &app_handle.state::<PeerCache>(), Some(channel_id)).await?;
+
+        let mut index = RepoIndex { channel_id: Some(channel_id), ..Default::default() };
+        let mut trees = Vec::new();
+        let mut history = client.iter_messages(&peer);
+        while let Some(msg) = history.next().await? {
+            let Some(media) = msg.media() else { continue };
+            let Media::Document(doc) = &media else { continue };
+            let name = doc.name().to_string();
+            if name.starts_with("pack-") && name.ends_with(".bin") {
+                index.packs.insert(msg.id(), doc.size() as u64);
+            } else if let Some(id) = name.strip_prefix("snapshot-").and_then(|n| n.strip_suffix(".json")).and_then(|n| n.parse::<u32>().ok()) {
+                trees.push((id, msg.id(), media));
+            }
+        }
+
+        fs::create_dir_all(&dir)?;
+        let bw_state = app_handle.state::<BandwidthManager>();
+        let messages = app_handle.state::<MessageCache>();
+        let mut repo = Repo { dir, index };
+        for (id, message_id, media) in trees {
+            let tree_path = repo.tree_path(id);
+            let source = FileSource { peer: &peer, messages: &messages, message_id };
+            download_to_path(&client, &bw_state, &media, &source, &tree_path, 1).await?;
+            let tree: Tree = fs::read_to_string(&tree_path).ok()
+                .and_then(|content| serde_json::from_str(&content).ok())
+                .unwrap_or_default();
+            let Some(mut info) = tree.info else {
+                log::warn!("Snapshot {} in channel {} has no index and cannot be recovered", id, channel_id);
+                let _ = fs::remove_file(&tree_path);
+                continue;
+            };
+            let missing = tree.locations.values().filter(|l| !repo.index.packs.contains_key(&l.pack)).count();
+            if missing > 0 {
+                log::warn!("Snapshot {}: {} chunks are in packs no longer in the channel", id, missing);
+            }
+            info.message_id = message_id;
+            repo.index.next_id = repo.index.next_id.max(info.id);
+            repo.index.chunks.extend(tree.locations);
+            repo.index.snapshots.push(info);
+        }
+        repo.index.snapshots.sort_by_key(|s| s.id);
+        repo.save()?;
+        log::info!("Rebuilt the snapshot index from channel {}: {} snapshots, {} packs",
+            channel_id, repo.index.snapshots.len(), repo.index.packs.len());
+        Ok(repo.index.snapshots)
+    }
+
+    /// Deletes a snapshot and its uploaded tree. Its chunks stay until `prune`.
+    pub async fn forget(&self, app_handle: &tauri::AppHandle, id: u32) -> Result<(), DriveError> {
+        let _busy = self.start()?;
+        let client = connected_client(app_handle).await?;
+        let mut repo = Repo::open(&app_handle.state::<AccountManager>())?;
+        let message_id = repo.info(id)?.message_id;
+
+        delete_messages(app_handle, &client, repo.channel()?, &[message_id]).await?;
+        repo.index.snapshots.retain(|s| s.id != id);
+        repo.save()?;
+        let _ = fs::remove_file(repo.tree_path(id));
+        Ok(())
+    }
+
+    /// Deletes the packs none of whose chunks are used by a remaining snapshot.
+    ///
+    /// Packs still partly in use are kept whole.
+    pub async fn prune(&self, app_handle: &tauri::AppHandle) -> Result<PruneResult, DriveError> {
+        let _busy = self.start()?;
+        let client = connected_client(app_handle).await?;
+        let mut repo = Repo::open(&app_handle.state::<AccountManager>())?;
+        let channel_id = repo.channel()?;
+
+        let mut used = HashSet::new();
+        for info in &repo.index.snapshots {
+            // A tree that cannot be read might still need every pack
+            for entry in repo.tree(info.id)?.files.values() {
+                used.extend(entry.chunks.iter().filter_map(|c| repo.index.chunks.get(c)).map(|l| l.pack));
+            }
+        }
+        let unused: Vec<i32> = repo.index.packs.keys().filter(|p| !used.contains(*p)).copied().collect();
+
+        let mut result = PruneResult { packs: 0, bytes: 0 };
+        for batch in unused.chunks(DELETE_BATCH) {
+            delete_messages(app_handle, &client, channel_id, batch).await?;
+            for pack in batch {
+                result.bytes += repo.index.packs.remove(pack).unwrap_or(0);
+            }
+            repo.index.chunks.retain(|_, l| !batch.contains(&l.pack));
+            result.packs += batch.len();
+            repo.save()?;
+        }
+        log::info!("Pruned {} packs ({} bytes) from the snapshot repository", result.packs, result.bytes);
+        Ok(result)
+    }
+}
+
+async fn connected_client(app_handle: &tauri::AppHandle) -> Result<Client, DriveError> {
+    app_handle.state::<TelegramState>().client.lock().await.clone().ok_or(DriveError::NotConnected)
+}
+
+/// Files under `paths`, keyed by their path in the tree, in tree order
+fn collect_files(paths: &[PathBuf]) -> Vec<(String, PathBuf)> {
+    let mut files = Vec::new();
+    for root in paths {
+        let name = root.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        for entry in WalkDir::new(root).sort_by_file_name() {
+            match entry {
+                Ok(entry) if entry.file_type().is_file() => {
+                    let key = match entry.depth() {
+                        0 => name.clone(),
+                        _ => format!("{}/{}", name, relative_path(root, entry.path())),
+                    };
+                    files.push((key, entry.into_path()));
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("Cannot read {}", e),
+            }
+        }
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    files
+}
+
+/// Size and modification time in milliseconds
+fn file_stamp(path: &Path) -> Option<(u64, u64)> {
+    let meta = fs::metadata(path).ok()?;
+    let modified = meta.modified().ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    Some((meta.len(), modified))
+}
+
+/// Uploads a full pack and records where its chunks went
+async fn upload_pack(
+    app_handle: &tauri::AppHandle,
+    client: &Client,
+    repo: &mut Repo,
+    channel_id: i64,
+    pack: Pack,
+) -> Result<(), DriveError> {
+    let Pack { path, mut out, chunks, len } = pack;
+    out.flush()?;
+    drop(out);
+    let _ = app_handle.emit("snapshot-progress", SnapshotProgress {
+        snapshot_id: repo.index.next_id + 1,
+        current: path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+        done: 0,
+        total: 1,
+    });
+    let uploaded = upload_to(app_handle, client, &path, channel_id).await;
+    let _ = fs::remove_file(&path);
+    let message_id = uploaded?;
+
+    repo.index.packs.insert(message_id, len);
+    for (hash, offset, length) in chunks {
+        repo.index.chunks.insert(hash, ChunkLocation { pack: message_id, offset, length });
+    }
+    repo.save()
+}
+
+async fn upload_to(app_handle: &tauri::AppHandle, client: &Client, path: &Path, channel_id: i64) -> Result<i32, DriveError> {
+    upload_file(
+        client,
+        &app_handle.state::<BandwidthManager>(),
+        &app_handle.state::<BotDrive>(),
+        &app_handle.state::<PeerCache>(),
+        &app_handle.state::<TransferManager>(),
+        path,
+        Some(channel_id),
+        |_| {},
+    ).await
+}
+
+async fn delete_messages(app_handle: &tauri::AppHandle, client: &Client, channel_id: i64, ids: &[i32]) -> Result<bool, DriveError> {
+    delete_files(
+        client,
+        &app_handle.state::<BotDrive>(),
+        &app_handle.state::<PeerCache>(),
+        &app_handle.state::<MessageCache>(),
+        ids,
+        Some(channel_id),
+    ).await
+}
+
+/// Downloads the packs of a restore one at a time and copies their chunks into the
+/// targets' `.part` files. Each pack is deleted once its chunks are written out.
+async fn copy_packs(
+    app_handle: &tauri::AppHandle,
+    client: &Client,
+    repo: &Repo,
+    id: u32,
+    plan: BTreeMap<i32, Vec<(ChunkLocation, String, PathBuf, u64)>>,
+) -> Result<(), DriveError> {
+    let channel_id = repo.channel()?;
+    let bw_state = app_handle.state::<BandwidthManager>();
+    let drive = app_handle.state::<BotDrive>();
+    let peers = app_handle.state::<PeerCache>();
+    let messages = app_handle.state::<MessageCache>();
+    let transfers = app_handle.state::<TransferManager>();
+    let peer = resolve_peer(client, &drive, &peers, Some(channel_id)).await?;
+
+    let total = plan.len();
+    for (done, (pack, chunks)) in plan.into_iter().enumerate() {
+        let _ = app_handle.emit("snapshot-progress", SnapshotProgress {
+            snapshot_id: id,
+            current: format!("pack {}", pack),
+            done,
+            total,
+        });
+        let media = messages.get(client, &peer, pack).await?
+            .and_then(|msg| msg.media())
+            .ok_or_else(|| DriveError::NotFound(format!("Pack {} is missing from the channel", pack)))?;
+        let pack_path = repo.dir.join(format!("restore-{}.bin", pack));
+        let source = FileSource { peer: &peer, messages: &messages, message_id: pack };
+        download_to_path(client, &bw_state, &media, &source, &pack_path, transfers.download_parallelism()).await?;
+
+        let copy_from = pack_path.clone();
+        let copied = tokio::task::spawn_blocking(move || copy_chunks(&copy_from, &chunks)).await;
+        let _ = fs::remove_file(&pack_path);
+        copied.map_err(|e| DriveError::Other(e.to_string()))??;
+    }
+    Ok(())
+}
+
+/// Writes the chunks of a downloaded pack into the `.part` files of their targets, checking each hash.
+/// Blocks, so it runs on a blocking thread.
+fn copy_chunks(pack_path: &Path, chunks: &[(ChunkLocation, String, PathBuf, u64)]) -> Result<(), DriveError> {
+    let mut pack = File::open(pack_path)?;
+    let mut data = Vec::new();
+    for (location, hash, part, offset) in chunks {
+        data.resize(location.length as usize, 0);
+        pack.seek(SeekFrom::Start(location.offset))?;
+        pack.read_exact(&mut data)?;
+        if format!("{:x}", Sha256::digest(&data)) != *hash {
+            return Err(DriveError::Io(format!("Chunk {} in pack {} is corrupted", hash, location.pack)));
+        }
+        let mut out = OpenOptions::new().write(true).open(part)?;
+        out.seek(SeekFrom::Start(*offset))?;
+        out.write_all(&data)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use std::sync::Mutex;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+/// More parallel requests than this only earn flood waits
+pub const MAX_PARALLELISM: usize = 16;
+
+/// How many chunk requests a single transfer keeps in flight
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct TransferSettings {
+    pub download_parallelism: usize,
+    pub upload_parallelism: usize,
+}
+
+impl Default for TransferSettings {
+    fn default() -> Self {
+        Self {
+            download_parallelism: 4,
+            upload_parallelism: 4,
+        }
+    }
+}
+
+/// Stores the transfer settings in `transfers.json`, shared by all accounts
+pub struct TransferManager {
+    file_path: PathBuf,
+    settings: Mutex<TransferSettings>,
+}
+
+impl TransferManager {
+    pub fn new(app_handle: &tauri::AppHandle) -> Self {
+        let app_data_dir = app_handle.path().app_data_dir().unwrap_or_else(|_| PathBuf::from("data"));
+        Self::load(&app_data_dir)
+    }
+
+    pub fn load(app_data_dir: &Path) -> Self {
+        let file_path = app_data_dir.join("transfers.json");
+        let settings = fs::read_to_string(&file_path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        Self {
+            file_path,
+            settings: Mutex::new(settings),
+        }
+    }
+
+    pub fn settings(&self) -> TransferSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn set_settings(&self, settings: TransferSettings) -> Result<(), String> {
+        let valid = 1..=MAX_PARALLELISM;
+        if !valid.contains(&settings.download_parallelism) || !valid.contains(&settings.upload_parallelism) {
+            return Err(format!("Parallel transfers must be between 1 and {}", MAX_PARALLELISM));
+        }
+        let json = serde_json::to_string_pretty(&settings).map_err(|e| e.to_string())?;
+        crate::atomic::write(&self.file_path, json).map_err(|e| format!("Failed to save transfer settings: {}", e))?;
+        *self.settings.lock().unwrap() = settings;
+        Ok(())
+    }
+
+    pub fn download_parallelism(&self) -> usize {
+        self.settings.lock().unwrap().download_parallelism.clamp(1, MAX_PARALLELISM)
+    }
+
+    pub fn upload_parallelism(&self) -> usize {
+        self.settings.lock().unwrap().upload_parallelism.clamp(1, MAX_PARALLELISM)
+    }
+}
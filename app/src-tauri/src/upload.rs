@@ -0,0 +1,255 @@
+use std::io::SeekFrom;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use grammers_client::Client;
+use grammers_client::types::Uploaded;
+use grammers_tl_types as tl;
+use crate::bandwidth::BandwidthManager;
+use crate::error::{DriveError, with_retry};
+
+/// Bytes per saved part. Telegram accepts up to 512 KB, and every part but the last must be this size.
+pub const PART_SIZE: usize = 512 * 1024;
+/// Files above this must go through `upload.saveBigFilePart`
+const BIG_FILE_SIZE: u64 = 10 * 1024 * 1024;
+/// Parts a file may have without Telegram Premium (2000 MB), Premium allows twice as many
+const FREE_MAX_PARTS: i32 = 4000;
+/// Tries per part once the parallel pass is over, on top of the flood wait handling in `with_retry`
+const PART_ATTEMPTS: usize = 3;
+
+/// Emitted as `upload-progress` after every part Telegram accepted
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadProgress {
+    pub path: String,
+    pub part: i32,
+    pub total_parts: i32,
+    pub bytes_sent: u64,
+    pub size: u64,
+}
+
+/// A local file being saved part by part under a random file ID
+struct PartUpload<'a> {
+    client: &'a Client,
+    path: &'a Path,
+    file_id: i64,
+    size: u64,
+    total_parts: i32,
+}
+
+impl PartUpload<'_> {
+    fn is_big(&self) -> bool {
+        is_big_file(self.size)
+    }
+
+    fn operation(&self) -> &'static str {
+        if self.is_big() { "upload.saveBigFilePart" } else { "upload.saveFilePart" }
+    }
+
+    /// Sends one part, returning its length
+    async fn send_part(&self, part: i32) -> Result<usize, DriveError> {
+        let bytes = read_part(self.path, self.size, part).await?;
+        let len = bytes.len();
+        let saved = if self.is_big() {
+            self.client.invoke(&tl::functions::upload::SaveBigFilePart {
+                file_id: self.file_id,
+                file_part: part,
+                file_total_parts: self.total_parts,
+                bytes,
+            }).await?
+        } else {
+            self.client.invoke(&tl::functions::upload::SaveFilePart {
+                file_id: self.file_id,
+                file_part: part,
+                bytes,
+            }).await?
+        };
+        if !saved {
+            return Err(DriveError::Other(format!("Telegram did not accept part {} of {}", part, self.total_parts)));
+        }
+        Ok(len)
+    }
+
+    fn input_file(&self, name: String) -> tl::enums::InputFile {
+        if self.is_big() {
+            tl::enums::InputFile::Big(tl::types::InputFileBig {
+                id: self.file_id,
+                parts: self.total_parts,
+                name,
+            })
+        } else {
+            // The checksum is optional, Telegram only compares it when one is given
+            tl::enums::InputFile::File(tl::types::InputFile {
+                id: self.file_id,
+                parts: self.total_parts,
+                name,
+                md5_checksum: String::new(),
+            })
+        }
+    }
+}
+
+/// Uploads the file at `path` with up to `parallelism` parts in flight.
+///
+/// Parts that fail in the parallel pass are sent again one at a time afterwards,
+/// so a flood wait or a dropped connection does not throw away the whole upload.
+/// `on_progress` is called after every accepted part. The result can be attached
+/// to an `InputMessage` like the one from `Client::upload_file`.
+pub async fn upload_from_path(
+    client: &Client,
+    bw_state: &BandwidthManager,
+    path: &Path,
+    parallelism: usize,
+    on_progress: impl Fn(UploadProgress),
+) -> Result<Uploaded, DriveError> {
+    let size = std::fs::metadata(path)?.len();
+    if size == 0 {
+        return Err("Telegram does not accept empty files".into());
+    }
+    let name = path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "file".to_string());
+
+    let upload = PartUpload {
+        client,
+        path,
+        file_id: rand::random(),
+        size,
+        total_parts: total_parts(size),
+    };
+    // Telegram only rejects the extra parts at the very end, so check before sending any
+    if upload.total_parts > FREE_MAX_PARTS && !is_premium(client).await? {
+        return Err(DriveError::FileTooLarge { size, limit: FREE_MAX_PARTS as u64 * PART_SIZE as u64 });
+    }
+
+    let sent = AtomicU64::new(0);
+    let report = |part: i32, len: usize| {
+        bw_state.add_up(len as u64);
+        on_progress(UploadProgress {
+            path: path.to_string_lossy().to_string(),
+            part,
+            total_parts: upload.total_parts,
+            bytes_sent: sent.fetch_add(len as u64, Ordering::Relaxed) + len as u64,
+            size,
+        });
+    };
+
+    let mut results = stream::iter(0..upload.total_parts)
+        .map(|part| {
+            let upload = &upload;
+            async move { (part, upload.send_part(part).await) }
+        })
+        .buffer_unordered(parallelism.max(1));
+
+    let mut failed = Vec::new();
+    while let Some((part, result)) = results.next().await {
+        match result {
+            Ok(len) => report(part, len),
+            Err(e) => {
+                log::warn!("Part {} of {} failed, retrying later: {}", part, name, e);
+                failed.push(part);
+            }
+        }
+    }
+    drop(results);
+
+    failed.sort_unstable();
+    for part in failed {
+        let mut attempt = 1;
+        let len = loop {
+            match with_retry(upload.operation(), || upload.send_part(part)).await {
+                Ok(len) => break len,
+                Err(e) if attempt < PART_ATTEMPTS && matches!(e, DriveError::Io(_) | DriveError::Other(_)) => {
+                    log::warn!("Part {} of {} failed again (attempt {}): {}", part, name, attempt, e);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        report(part, len);
+    }
+
+    Ok(Uploaded::from_raw(upload.input_file(name)))
+}
+
+fn is_big_file(size: u64) -> bool {
+    size > BIG_FILE_SIZE
+}
+
+/// Parts a file of `size` bytes is split into
+fn total_parts(size: u64) -> i32 {
+    size.div_ceil(PART_SIZE as u64) as i32
+}
+
+/// Offset and length of `part` in a file of `size` bytes. Only the last part may be shorter.
+fn part_span(size: u64, part: i32) -> (u64, usize) {
+    let offset = part as u64 * PART_SIZE as u64;
+    (offset, (size - offset).min(PART_SIZE as u64) as usize)
+}
+
+async fn read_part(path: &Path, size: u64, part: i32) -> Result<Vec<u8>, DriveError> {
+    let (offset, len) = part_span(size, part);
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(SeekFrom::Start(offset)).await?;
+    let mut bytes = vec![0; len];
+    file.read_exact(&mut bytes).await?;
+    Ok(bytes)
+}
+
+async fn is_premium(client: &Client) -> Result<bool, DriveError> {
+    let me = with_retry("users.getFullUser", || client.get_me()).await?;
+    Ok(matches!(me.raw, tl::enums::User::User(ref u) if u.premium))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PART: u64 = PART_SIZE as u64;
+
+    #[test]
+    fn parts_cover_the_file_exactly() {
+        for size in [1, PART - 1, PART, PART + 1, 3 * PART + 7] {
+            let parts = total_parts(size);
+            let mut next = 0;
+            for part in 0..parts {
+                let (offset, len) = part_span(size, part);
+                assert_eq!(offset, next);
+                assert!(len > 0 && len <= PART_SIZE);
+                assert!(part == parts - 1 || len == PART_SIZE, "only the last part may be short");
+                next += len as u64;
+            }
+            assert_eq!(next, size);
+        }
+        assert_eq!(total_parts(PART), 1);
+        assert_eq!(total_parts(PART + 1), 2);
+    }
+
+    #[test]
+    fn free_accounts_get_exactly_4000_parts() {
+        let free_limit = FREE_MAX_PARTS as u64 * PART;
+        assert_eq!(free_limit, 2000 * 1024 * 1024);
+        assert_eq!(total_parts(free_limit), FREE_MAX_PARTS);
+        assert!(total_parts(free_limit + 1) > FREE_MAX_PARTS);
+    }
+
+    #[test]
+    fn files_above_10_mb_are_big() {
+        assert!(!is_big_file(BIG_FILE_SIZE));
+        assert!(is_big_file(BIG_FILE_SIZE + 1));
+    }
+
+    #[tokio::test]
+    async fn reads_each_part_from_its_offset() {
+        let path = std::env::temp_dir().join(format!("telegram-drive-test-upload-{}", std::process::id()));
+        let content: Vec<u8> = (0..2 * PART + 10).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &content).unwrap();
+        let size = content.len() as u64;
+
+        assert_eq!(read_part(&path, size, 0).await.unwrap(), &content[..PART_SIZE]);
+        assert_eq!(read_part(&path, size, 1).await.unwrap(), &content[PART_SIZE..2 * PART_SIZE]);
+        assert_eq!(read_part(&path, size, 2).await.unwrap(), &content[2 * PART_SIZE..]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,366 @@
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use aes_gcm::aead::{Aead, KeyInit};
+use base64::{Engine as _, engine::general_purpose};
+use sha2::{Digest, Sha256};
+use tauri::Manager;
+
+/// Returned while the vault is enabled but no passphrase has been entered yet
+pub const SESSION_LOCKED: &str = "SESSION_LOCKED";
+
+const SESSION_FILE: &str = "telegram.session";
+const SEALED_FILE: &str = "telegram.session.enc";
+const MAGIC: &[u8; 5] = b"TDSV1";
+const NONCE_LEN: usize = 12;
+/// OWASP recommendation for PBKDF2-HMAC-SHA256
+const KDF_ROUNDS: u32 = 600_000;
+/// Encrypted with the derived key so a passphrase can be checked without any session
+const CHECK_PLAINTEXT: &[u8] = b"telegram-drive-vault";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct VaultConfig {
+    salt: String,
+    check: String,
+}
+
+/// Optional passphrase protection for the session files.
+///
+/// Each account keeps its session as `telegram.session.enc` (AES-256-GCM, key
+/// derived from the passphrase). grammers needs a plain SQLite file, so `open`
+/// decrypts it into a private temporary directory, never the account directory.
+/// The sealed file is brought up to date with `checkpoint` right after opening and
+/// after every login, so the plain copy is never the only one, and `seal` removes
+/// the plain copy on exit. The salt and a check value live in `vault.json`.
+pub struct SessionVault {
+    file_path: PathBuf,
+    config: Mutex<Option<VaultConfig>>,
+    key: Mutex<Option<[u8; 32]>>,
+}
+
+impl SessionVault {
+    pub fn new(app_handle: &tauri::AppHandle) -> Self {
+        let app_data_dir = app_handle.path().app_data_dir().unwrap_or_else(|_| PathBuf::from("data"));
+        Self::load(&app_data_dir)
+    }
+
+    pub fn load(app_data_dir: &Path) -> Self {
+        let file_path = app_data_dir.join("vault.json");
+        let config = fs::read_to_string(&file_path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok());
+
+        Self {
+            file_path,
+            config: Mutex::new(config),
+            key: Mutex::new(None),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.lock().unwrap().is_some()
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.key.lock().unwrap().is_some()
+    }
+
+    /// Turns encryption on. Sessions are encrypted the next time they are sealed.
+    pub fn enable(&self, passphrase: &str) -> Result<(), String> {
+        if self.is_enabled() {
+            return Err("Session encryption is already enabled".to_string());
+        }
+        if passphrase.chars().count() < 8 {
+            return Err("Passphrase must be at least 8 characters".to_string());
+        }
+
+        let salt: [u8; 16] = rand::random();
+        let key = derive_key(passphrase, &salt);
+        let config = VaultConfig {
+            salt: general_purpose::STANDARD.encode(salt),
+            check: general_purpose::STANDARD.encode(encrypt(&key, CHECK_PLAINTEXT)?),
+        };
+        let json = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+        crate::atomic::write(&self.file_path, json).map_err(|e| format!("Failed to save vault settings: {}", e))?;
+
+        *self.config.lock().unwrap() = Some(config);
+        *self.key.lock().unwrap() = Some(key);
+        Ok(())
+    }
+
+    /// Checks the passphrase and keeps the derived key for this run
+    pub fn unlock(&self, passphrase: &str) -> Result<(), String> {
+        let config = self.config.lock().unwrap().clone().ok_or("Session encryption is not enabled")?;
+        let salt = general_purpose::STANDARD.decode(&config.salt).map_err(|_| "vault.json is damaged")?;
+        let check = general_purpose::STANDARD.decode(&config.check).map_err(|_| "vault.json is damaged")?;
+
+        let key = derive_key(passphrase, &salt);
+        match decrypt(&key, &check) {
+            Ok(plain) if plain == CHECK_PLAINTEXT => {
+                *self.key.lock().unwrap() = Some(key);
+                Ok(())
+            }
+            _ => Err("Wrong passphrase".to_string()),
+        }
+    }
+
+    /// Puts every session under `account_dirs` back into its directory unencrypted and turns encryption off
+    pub fn disable(&self, passphrase: &str, account_dirs: &[PathBuf]) -> Result<(), String> {
+        self.unlock(passphrase)?;
+        let key = (*self.key.lock().unwrap()).ok_or(SESSION_LOCKED)?;
+        for dir in account_dirs {
+            let plain_path = dir.join(SESSION_FILE);
+            let unsealed_path = unsealed_dir(dir).join(SESSION_FILE);
+            if unsealed_path.exists() {
+                // Open right now: copy its latest state and leave the decrypted copy to the
+                // running client, `seal` removes it on exit
+                for suffix in ["", "-wal"] {
+                    let from = suffixed(&unsealed_path, suffix);
+                    if from.exists() {
+                        fs::copy(&from, suffixed(&plain_path, suffix)).map_err(|e| format!("Failed to restore session: {}", e))?;
+                    }
+                }
+            } else if !plain_path.exists() && dir.join(SEALED_FILE).exists() {
+                unseal(&key, dir, &plain_path)?;
+            }
+            let _ = fs::remove_file(dir.join(SEALED_FILE));
+        }
+        let _ = fs::remove_file(&self.file_path);
+        *self.config.lock().unwrap() = None;
+        *self.key.lock().unwrap() = None;
+        Ok(())
+    }
+
+    /// Where grammers finds the session of the account in `dir`
+    pub fn session_path(&self, dir: &Path) -> PathBuf {
+        if self.is_enabled() {
+            unsealed_dir(dir).join(SESSION_FILE)
+        } else {
+            dir.join(SESSION_FILE)
+        }
+    }
+
+    /// Whether a plain copy of the session in `dir` exists, i.e. someone has it open
+    pub fn is_open(&self, dir: &Path) -> bool {
+        plain_session(dir).is_some()
+    }
+
+    /// Makes the plain session of `dir` available and returns its path, decrypting it if needed.
+    ///
+    /// A plain copy that already exists is used as is: another process may have it
+    /// open, or the app did not exit cleanly and it is at least as new as the sealed one.
+    pub fn open(&self, dir: &Path) -> Result<PathBuf, String> {
+        if !self.is_enabled() {
+            return Ok(dir.join(SESSION_FILE));
+        }
+        let key = (*self.key.lock().unwrap()).ok_or(SESSION_LOCKED)?;
+        if let Some(plain) = plain_session(dir) {
+            return Ok(plain);
+        }
+
+        let session_path = self.session_path(dir);
+        create_private_dir(&unsealed_dir(dir)).map_err(|e| format!("Failed to create session directory: {}", e))?;
+        if dir.join(SEALED_FILE).exists() {
+            unseal(&key, dir, &session_path)?;
+        }
+        Ok(session_path)
+    }
+
+    /// Encrypts the current plain session of `dir` into the sealed file, leaving the
+    /// plain copy in place for the client still using it
+    pub fn checkpoint(&self, dir: &Path) -> Result<(), String> {
+        let Some(key) = *self.key.lock().unwrap() else { return Ok(()) };
+        let Some(session_path) = plain_session(dir) else { return Ok(()) };
+
+        let main = fs::read(&session_path).map_err(|e| format!("Failed to read session: {}", e))?;
+        // Unflushed pages still sit in the WAL, so keep it rather than lose them
+        let wal = fs::read(suffixed(&session_path, "-wal")).unwrap_or_default();
+
+        let mut payload = Vec::with_capacity(8 + main.len() + wal.len());
+        payload.extend_from_slice(&(main.len() as u64).to_le_bytes());
+        payload.extend_from_slice(&main);
+        payload.extend_from_slice(&wal);
+
+        let mut sealed = MAGIC.to_vec();
+        sealed.extend(encrypt(&key, &payload)?);
+
+        crate::atomic::write(dir.join(SEALED_FILE), sealed).map_err(|e| format!("Failed to write encrypted session: {}", e))?;
+        Ok(())
+    }
+
+    /// Encrypts the plain session of `dir` and removes it. The session must not be open.
+    ///
+    /// With encryption turned off, only removes a decrypted copy left over from before `disable`.
+    pub fn seal(&self, dir: &Path) -> Result<(), String> {
+        if !self.is_enabled() {
+            self.discard(dir);
+            return Ok(());
+        }
+        if !self.is_unlocked() {
+            return Ok(());
+        }
+        let Some(session_path) = plain_session(dir) else { return Ok(()) };
+        self.checkpoint(dir)?;
+        remove_session(&session_path);
+        let _ = fs::remove_dir(unsealed_dir(dir));
+        Ok(())
+    }
+
+    /// Removes the decrypted copy of the session of `dir` without sealing it, e.g. after logging out
+    pub fn discard(&self, dir: &Path) {
+        remove_session(&unsealed_dir(dir).join(SESSION_FILE));
+        let _ = fs::remove_dir(unsealed_dir(dir));
+    }
+}
+
+/// Private directory holding the decrypted session of the account in `dir` while it is open
+fn unsealed_dir(dir: &Path) -> PathBuf {
+    let hash = format!("{:x}", Sha256::digest(dir.to_string_lossy().as_bytes()));
+    std::env::temp_dir().join(format!("telegram-drive-{}", &hash[..16]))
+}
+
+/// The plain session of `dir`: the decrypted copy, or one still in `dir` from before
+/// encryption was turned on
+fn plain_session(dir: &Path) -> Option<PathBuf> {
+    [unsealed_dir(dir).join(SESSION_FILE), dir.join(SESSION_FILE)].into_iter().find(|p| p.exists())
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn remove_session(session_path: &Path) {
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = fs::remove_file(suffixed(session_path, suffix));
+    }
+}
+
+fn create_private_dir(path: &Path) -> std::io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(path)
+}
+
+/// Decrypts the sealed session of `dir` to `session_path`
+fn unseal(key: &[u8; 32], dir: &Path, session_path: &Path) -> Result<(), String> {
+    let sealed = fs::read(dir.join(SEALED_FILE)).map_err(|e| format!("Failed to read encrypted session: {}", e))?;
+    let body = sealed.strip_prefix(MAGIC.as_slice()).ok_or("Encrypted session has an unknown format")?;
+    let payload = decrypt(key, body)
+        .map_err(|_| "Encrypted session could not be decrypted (wrong passphrase or damaged file)".to_string())?;
+
+    // Payload: main database length, main database, then the write-ahead log
+    let (len, rest) = payload.split_at_checked(8).ok_or("Encrypted session is truncated")?;
+    let main_len = u64::from_le_bytes(len.try_into().unwrap()) as usize;
+    let (main, wal) = rest.split_at_checked(main_len).ok_or("Encrypted session is truncated")?;
+
+    fs::write(session_path, main).map_err(|e| format!("Failed to restore session: {}", e))?;
+    if !wal.is_empty() {
+        fs::write(suffixed(session_path, "-wal"), wal).map_err(|e| format!("Failed to restore session: {}", e))?;
+    }
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, KDF_ROUNDS, &mut key);
+    key
+}
+
+/// Returns nonce followed by ciphertext and tag
+fn encrypt(key: &[u8; 32], plain: &[u8]) -> Result<Vec<u8>, String> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let mut out = nonce.to_vec();
+    out.extend(cipher.encrypt(Nonce::from_slice(&nonce), plain).map_err(|_| "Encryption failed".to_string())?);
+    Ok(out)
+}
+
+fn decrypt(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>, aes_gcm::Error> {
+    if data.len() < NONCE_LEN {
+        return Err(aes_gcm::Error);
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSPHRASE: &str = "correct horse battery";
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("telegram-drive-test-vault-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("account")).unwrap();
+        fs::canonicalize(dir).unwrap()
+    }
+
+    /// Opens the session of `account`, writes a database and WAL into it and seals it
+    fn seal_session(vault: &SessionVault, account: &Path) -> PathBuf {
+        let session_path = vault.open(account).unwrap();
+        fs::write(&session_path, b"main database").unwrap();
+        fs::write(suffixed(&session_path, "-wal"), b"write-ahead log").unwrap();
+        vault.seal(account).unwrap();
+        session_path
+    }
+
+    #[test]
+    fn sealed_session_opens_again_after_unlocking() {
+        let dir = test_dir("roundtrip");
+        let account = dir.join("account");
+        let vault = SessionVault::load(&dir);
+        vault.enable(PASSPHRASE).unwrap();
+        let session_path = seal_session(&vault, &account);
+        assert!(!session_path.exists());
+        assert!(account.join(SEALED_FILE).exists());
+
+        let restarted = SessionVault::load(&dir);
+        assert_eq!(restarted.open(&account), Err(SESSION_LOCKED.to_string()));
+        restarted.unlock(PASSPHRASE).unwrap();
+        let session_path = restarted.open(&account).unwrap();
+        assert_eq!(fs::read(&session_path).unwrap(), b"main database");
+        assert_eq!(fs::read(suffixed(&session_path, "-wal")).unwrap(), b"write-ahead log");
+
+        restarted.seal(&account).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let dir = test_dir("passphrase");
+        SessionVault::load(&dir).enable(PASSPHRASE).unwrap();
+
+        let restarted = SessionVault::load(&dir);
+        assert_eq!(restarted.unlock("incorrect horse"), Err("Wrong passphrase".to_string()));
+        assert!(!restarted.is_unlocked());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tampered_session_is_not_decrypted() {
+        let dir = test_dir("tampered");
+        let account = dir.join("account");
+        let vault = SessionVault::load(&dir);
+        vault.enable(PASSPHRASE).unwrap();
+        seal_session(&vault, &account);
+
+        let sealed_path = account.join(SEALED_FILE);
+        let mut sealed = fs::read(&sealed_path).unwrap();
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        fs::write(&sealed_path, sealed).unwrap();
+
+        assert!(vault.open(&account).unwrap_err().contains("could not be decrypted"));
+        assert!(!vault.is_open(&account));
+        vault.discard(&account);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
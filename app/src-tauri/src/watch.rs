@@ -0,0 +1,510 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+use tokio::sync::mpsc;
+use walkdir::WalkDir;
+use crate::accounts::AccountManager;
+use crate::bandwidth::BandwidthManager;
+use crate::bot::BotDrive;
+use crate::commands::TelegramState;
+use crate::commands::fs::{MAX_UPLOAD_SIZE, upload_file};
+use crate::commands::tree::{relative_path, stops_transfer};
+use crate::error::DriveError;
+use crate::filenames::{CollisionStrategy, resolve_collision};
+use crate::filters::PathFilter;
+use crate::models::WatchActivity;
+use crate::peers::PeerCache;
+use crate::transfers::TransferManager;
+
+/// How long a file must stay unchanged before it counts as completely written
+const SETTLE_TIME: Duration = Duration::from_secs(3);
+/// Every rule is rescanned this often, for missed events and account switches
+const RESCAN_INTERVAL: Duration = Duration::from_secs(60);
+/// Directory inside a watched folder that archived files are moved to
+pub const ARCHIVE_DIR: &str = ".uploaded";
+/// Names browsers and editors give files they are still writing
+const PARTIAL_SUFFIXES: [&str; 4] = [".part", ".crdownload", ".download", ".tmp"];
+
+/// What happens to a local file once it is uploaded
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AfterUpload {
+    /// Leave it in place. It is uploaded again only if it changes.
+    Keep,
+    Delete,
+    /// Move it into `.uploaded/` inside the watched folder
+    Archive,
+}
+
+/// A local directory whose files are uploaded to a drive folder as they appear
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WatchRule {
+    pub id: u32,
+    /// Account the drive folder belongs to. The rule only runs while it is active.
+    pub account: String,
+    pub local_path: PathBuf,
+    /// `None` for Saved Messages
+    pub folder_id: Option<i64>,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    pub after_upload: AfterUpload,
+    pub enabled: bool,
+}
+
+/// Size and modification time (ms since the epoch) of a local file
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+struct FileStamp {
+    size: u64,
+    modified: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
+struct WatchState {
+    rules: Vec<WatchRule>,
+    /// Files `Keep` rules already uploaded, by rule ID
+    uploaded: HashMap<u32, BTreeMap<PathBuf, FileStamp>>,
+}
+
+enum Signal {
+    /// Something changed below the folder of this rule
+    Changed(u32),
+    /// Rules were added, removed or toggled
+    RulesChanged,
+}
+
+/// Watched folders, stored in `watch.json` and shared by all accounts.
+///
+/// `spawn_worker` watches the folders of enabled rules and uploads every file
+/// that has stopped changing for a few seconds, through the same path as
+/// `cmd_upload_file`.
+pub struct WatchManager {
+    file_path: PathBuf,
+    state: Mutex<WatchState>,
+    /// Wakes the worker, once it runs
+    wake: Mutex<Option<mpsc::UnboundedSender<Signal>>>,
+}
+
+impl WatchManager {
+    pub fn new(app_handle: &tauri::AppHandle) -> Self {
+        let app_data_dir = app_handle.path().app_data_dir().unwrap_or_else(|_| PathBuf::from("data"));
+        Self::load(&app_data_dir)
+    }
+
+    pub fn load(app_data_dir: &Path) -> Self {
+        let file_path = app_data_dir.join("watch.json");
+        let state = fs::read_to_string(&file_path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        Self {
+            file_path,
+            state: Mutex::new(state),
+            wake: Mutex::new(None),
+        }
+    }
+
+    pub fn rules(&self) -> Vec<WatchRule> {
+        self.state.lock().unwrap().rules.clone()
+    }
+
+    fn rule(&self, id: u32) -> Option<WatchRule> {
+        self.state.lock().unwrap().rules.iter().find(|r| r.id == id).cloned()
+    }
+
+    pub fn add_rule(
+        &self,
+        account: String,
+        local_path: PathBuf,
+        folder_id: Option<i64>,
+        include: Vec<String>,
+        exclude: Vec<String>,
+        after_upload: AfterUpload,
+    ) -> Result<WatchRule, DriveError> {
+        if !local_path.is_dir() {
+            return Err(DriveError::Other(format!("{} is not a folder", local_path.display())));
+        }
+        // Reject bad globs now rather than on every scan
+        PathFilter::new(&include, &exclude)?;
+
+        let mut rule = WatchRule { id: 0, account, local_path, folder_id, include, exclude, after_upload, enabled: true };
+        self.update(|state| {
+            rule.id = state.rules.iter().map(|r| r.id).max().unwrap_or(0) + 1;
+            state.rules.push(rule.clone());
+        });
+        self.signal(Signal::RulesChanged);
+        log::info!("Watching {} for folder {:?}", rule.local_path.display(), rule.folder_id);
+        Ok(rule)
+    }
+
+    pub fn remove_rule(&self, id: u32) {
+        self.update(|state| {
+            state.rules.retain(|r| r.id != id);
+            state.uploaded.remove(&id);
+        });
+        self.signal(Signal::RulesChanged);
+    }
+
+    pub fn set_enabled(&self, id: u32, enabled: bool) -> Result<WatchRule, DriveError> {
+        let mut found = None;
+        self.update(|state| {
+            if let Some(rule) = state.rules.iter_mut().find(|r| r.id == id) {
+                rule.enabled = enabled;
+                found = Some(rule.clone());
+            }
+        });
+        self.signal(Signal::RulesChanged);
+        found.ok_or_else(|| DriveError::NotFound(format!("Watched folder {} not found", id)))
+    }
+
+    fn is_uploaded(&self, id: u32, path: &Path, stamp: FileStamp) -> bool {
+        let state = self.state.lock().unwrap();
+        state.uploaded.get(&id).and_then(|files| files.get(path)) == Some(&stamp)
+    }
+
+    fn mark_uploaded(&self, id: u32, path: &Path, stamp: FileStamp) {
+        self.update(|state| {
+            state.uploaded.entry(id).or_default().insert(path.to_path_buf(), stamp);
+        });
+    }
+
+    fn signal(&self, signal: Signal) {
+        if let Some(wake) = self.wake.lock().unwrap().as_ref() {
+            let _ = wake.send(signal);
+        }
+    }
+
+    fn update(&self, change: impl FnOnce(&mut WatchState)) {
+        let mut state = self.state.lock().unwrap();
+        change(&mut state);
+        match serde_json::to_string_pretty(&*state) {
+            Ok(json) => {
+                if let Err(e) = crate::atomic::write(&self.file_path, json) {
+                    log::error!("Failed to save watched folders: {}", e);
+                }
+            }
+            Err(e) => log::error!("Failed to serialize watched folders: {}", e),
+        }
+    }
+}
+
+/// What the worker has seen of the files in watched folders
+#[derive(Default)]
+struct Observed {
+    /// Last stamp of files that are not uploaded yet
+    pending: HashMap<PathBuf, FileStamp>,
+    /// Files whose upload failed, not retried until they change or the next full rescan
+    failed: HashMap<PathBuf, FileStamp>,
+}
+
+/// Watches the folders of all enabled rules for as long as the app runs
+pub fn spawn_worker(app_handle: tauri::AppHandle) {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    *app_handle.state::<WatchManager>().wake.lock().unwrap() = Some(tx.clone());
+
+    tauri::async_runtime::spawn(async move {
+        let watches = app_handle.state::<WatchManager>();
+        let mut watchers = start_watchers(&watches.rules(), &tx);
+        let mut dirty: HashSet<u32> = watches.rules().iter().map(|r| r.id).collect();
+        let mut observed = Observed::default();
+        let mut last_rescan = Instant::now();
+        let mut ticker = tokio::time::interval(SETTLE_TIME);
+
+        loop {
+            tokio::select! {
+                signal = rx.recv() => match signal {
+                    Some(Signal::Changed(id)) => {
+                        dirty.insert(id);
+                    }
+                    Some(Signal::RulesChanged) => {
+                        watchers.clear();
+                        watchers.extend(start_watchers(&watches.rules(), &tx));
+                        dirty.extend(watches.rules().iter().map(|r| r.id));
+                    }
+                    None => break,
+                },
+                _ = ticker.tick() => {
+                    if last_rescan.elapsed() >= RESCAN_INTERVAL {
+                        dirty.extend(watches.rules().iter().map(|r| r.id));
+                        // Also drops files that disappeared before they settled
+                        observed.pending.clear();
+                        observed.failed.clear();
+                        last_rescan = Instant::now();
+                    }
+                    for id in std::mem::take(&mut dirty) {
+                        let Some(rule) = watches.rule(id) else { continue };
+                        // Files still being written are looked at again on the next tick
+                        if process_rule(&app_handle, &rule, &mut observed).await {
+                            dirty.insert(id);
+                        }
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn start_watchers(rules: &[WatchRule], tx: &mpsc::UnboundedSender<Signal>) -> Vec<RecommendedWatcher> {
+    rules.iter()
+        .filter(|rule| rule.enabled)
+        .filter_map(|rule| {
+            let tx = tx.clone();
+            let id = rule.id;
+            let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if event.is_ok() {
+                    let _ = tx.send(Signal::Changed(id));
+                }
+            });
+            // The periodic rescan still covers folders that cannot be watched
+            let mut watcher = watcher.map_err(|e| log::warn!("Cannot create a file watcher: {}", e)).ok()?;
+            if let Err(e) = watcher.watch(&rule.local_path, RecursiveMode::Recursive) {
+                log::warn!("Cannot watch {}: {}", rule.local_path.display(), e);
+                return None;
+            }
+            Some(watcher)
+        })
+        .collect()
+}
+
+/// Uploads the settled files of `rule`. Returns whether some are still being written.
+async fn process_rule(app_handle: &tauri::AppHandle, rule: &WatchRule, observed: &mut Observed) -> bool {
+    if !rule.enabled {
+        return false;
+    }
+    // Folder IDs and caches belong to one account, the next rescan tries again
+    if app_handle.state::<AccountManager>().active_name().as_deref() != Some(rule.account.as_str()) {
+        return false;
+    }
+    let Some(client) = app_handle.state::<TelegramState>().client.lock().await.clone() else { return false };
+    let filter = match PathFilter::new(&rule.include, &rule.exclude) {
+        Ok(filter) => filter,
+        Err(e) => {
+            log::error!("Watched folder {} has invalid filters: {}", rule.id, e);
+            return false;
+        }
+    };
+
+    let watches = app_handle.state::<WatchManager>();
+    let bw_state = app_handle.state::<BandwidthManager>();
+    let drive = app_handle.state::<BotDrive>();
+    let peers = app_handle.state::<PeerCache>();
+    let transfers = app_handle.state::<TransferManager>();
+
+    let (ready, settling) = settled_files(rule, &filter, observed, |path, stamp| watches.is_uploaded(rule.id, path, stamp));
+    for (path, stamp) in ready {
+        let path_str = path.to_string_lossy().to_string();
+        let result = upload_file(&client, &bw_state, &drive, &peers, &transfers, &path, rule.folder_id, |_| {}).await;
+        let error = match result {
+            Ok(_) => {
+                log::info!("Uploaded watched file {}", path_str);
+                finish(&watches, rule, &path, stamp);
+                None
+            }
+            Err(e) => {
+                log::warn!("Failed to upload watched file {}: {}", path_str, e);
+                observed.failed.insert(path.clone(), stamp);
+                Some(e)
+            }
+        };
+        let stop = error.as_ref().is_some_and(stops_transfer);
+        let _ = app_handle.emit("watch-activity", WatchActivity {
+            rule_id: rule.id,
+            path: path_str,
+            error: error.map(|e| e.to_string()),
+        });
+        if stop {
+            return false;
+        }
+    }
+    settling
+}
+
+/// Files of `rule` that have not changed since the previous scan and were written
+/// long enough ago, plus whether any others are still changing
+fn settled_files(
+    rule: &WatchRule,
+    filter: &PathFilter,
+    observed: &mut Observed,
+    is_uploaded: impl Fn(&Path, FileStamp) -> bool,
+) -> (Vec<(PathBuf, FileStamp)>, bool) {
+    let root = &rule.local_path;
+    let mut ready = Vec::new();
+    let mut settling = false;
+
+    let entries = WalkDir::new(root)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| {
+            let archived = e.depth() == 1 && e.file_name() == ARCHIVE_DIR;
+            e.depth() == 0 || !(archived || filter.is_excluded(&relative_path(root, e.path())))
+        });
+    for entry in entries.flatten() {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy();
+        if PARTIAL_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)) || !filter.accepts(&relative_path(root, entry.path())) {
+            continue;
+        }
+        let Ok(meta) = entry.metadata() else { continue };
+        if meta.len() == 0 || meta.len() > MAX_UPLOAD_SIZE {
+            continue;
+        }
+        let modified = meta.modified().unwrap_or(UNIX_EPOCH);
+        let stamp = FileStamp {
+            size: meta.len(),
+            modified: modified.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0),
+        };
+        let path = entry.into_path();
+        if observed.failed.get(&path) == Some(&stamp) || is_uploaded(&path, stamp) {
+            continue;
+        }
+
+        let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+        if observed.pending.get(&path) == Some(&stamp) && age >= SETTLE_TIME {
+            observed.pending.remove(&path);
+            ready.push((path, stamp));
+        } else {
+            observed.pending.insert(path, stamp);
+            settling = true;
+        }
+    }
+    (ready, settling)
+}
+
+/// Applies the rule's `after_upload` to an uploaded file
+fn finish(watches: &WatchManager, rule: &WatchRule, path: &Path, stamp: FileStamp) {
+    let result = match rule.after_upload {
+        AfterUpload::Keep => Ok(()),
+        AfterUpload::Delete => fs::remove_file(path),
+        AfterUpload::Archive => {
+            let target = rule.local_path.join(ARCHIVE_DIR).join(path.strip_prefix(&rule.local_path).unwrap_or(path));
+            target.parent().map(fs::create_dir_all).transpose()
+                .and_then(|_| match resolve_collision(&target, CollisionStrategy::Rename) {
+                    Some(target) => fs::rename(path, target),
+                    None => Ok(()),
+                })
+        }
+    };
+    // A file that is still there must not be uploaded again
+    if let Err(e) = &result {
+        log::warn!("Uploaded {}, but could not remove it: {}", path.display(), e);
+    }
+    if rule.after_upload == AfterUpload::Keep || result.is_err() {
+        watches.mark_uploaded(rule.id, path, stamp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("telegram-drive-test-watch-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::canonicalize(dir).unwrap()
+    }
+
+    fn rule(dir: &Path) -> WatchRule {
+        WatchRule {
+            id: 1,
+            account: "default".to_string(),
+            local_path: dir.to_path_buf(),
+            folder_id: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            after_upload: AfterUpload::Keep,
+            enabled: true,
+        }
+    }
+
+    /// Writes `path` as if it was last modified `age` ago
+    fn write(path: &Path, content: &str, age: Duration) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+        fs::File::options().write(true).open(path).unwrap().set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    fn scan(rule: &WatchRule, filter: &PathFilter, observed: &mut Observed) -> (Vec<PathBuf>, bool) {
+        let (ready, settling) = settled_files(rule, filter, observed, |_, _| false);
+        (ready.into_iter().map(|(path, _)| path).collect(), settling)
+    }
+
+    #[test]
+    fn files_are_ready_once_unchanged_between_scans() {
+        let dir = test_dir("settle");
+        let (rule, filter, mut observed) = (rule(&dir), PathFilter::default(), Observed::default());
+        write(&dir.join("old.txt"), "old", Duration::from_secs(60));
+        write(&dir.join("fresh.txt"), "fresh", Duration::ZERO);
+
+        assert_eq!(scan(&rule, &filter, &mut observed), (vec![], true));
+        assert_eq!(scan(&rule, &filter, &mut observed), (vec![dir.join("old.txt")], true));
+        assert!(observed.pending.contains_key(&dir.join("fresh.txt")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_change_between_scans_starts_over() {
+        let dir = test_dir("change");
+        let (rule, filter, mut observed) = (rule(&dir), PathFilter::default(), Observed::default());
+        write(&dir.join("growing.bin"), "a", Duration::from_secs(60));
+        assert_eq!(scan(&rule, &filter, &mut observed), (vec![], true));
+
+        write(&dir.join("growing.bin"), "ab", Duration::from_secs(60));
+        assert_eq!(scan(&rule, &filter, &mut observed), (vec![], true));
+        assert_eq!(scan(&rule, &filter, &mut observed), (vec![dir.join("growing.bin")], false));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn partial_empty_archived_and_excluded_files_are_ignored() {
+        let dir = test_dir("ignored");
+        let old = Duration::from_secs(60);
+        for name in ["movie.mkv.part", "setup.exe.crdownload", "notes.tmp", "debug.log", "cache/data.txt", ".uploaded/done.txt"] {
+            write(&dir.join(name), "content", old);
+        }
+        write(&dir.join("empty.txt"), "", old);
+        write(&dir.join("sub/photo.jpg"), "jpeg", old);
+        let rule = rule(&dir);
+        let filter = PathFilter::new(&[], &["*.log".to_string(), "cache".to_string()]).unwrap();
+        let mut observed = Observed::default();
+
+        scan(&rule, &filter, &mut observed);
+        assert_eq!(scan(&rule, &filter, &mut observed), (vec![dir.join("sub/photo.jpg")], false));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn uploaded_and_failed_files_wait_until_they_change() {
+        let dir = test_dir("known");
+        let old = Duration::from_secs(60);
+        write(&dir.join("uploaded.txt"), "up", old);
+        write(&dir.join("failed.txt"), "fail", old);
+        let rule = rule(&dir);
+        let filter = PathFilter::default();
+        let mut observed = Observed::default();
+        let uploaded = dir.join("uploaded.txt");
+        let is_uploaded = |path: &Path, _| path == uploaded;
+
+        settled_files(&rule, &filter, &mut observed, is_uploaded);
+        let (ready, _) = settled_files(&rule, &filter, &mut observed, is_uploaded);
+        assert_eq!(ready.len(), 1);
+        let (path, stamp) = ready[0].clone();
+        assert_eq!(path, dir.join("failed.txt"));
+
+        observed.failed.insert(path.clone(), stamp);
+        assert_eq!(settled_files(&rule, &filter, &mut observed, is_uploaded), (vec![], false));
+        write(&path, "failed again", old);
+        assert!(settled_files(&rule, &filter, &mut observed, is_uploaded).1, "a changed file is tried again");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
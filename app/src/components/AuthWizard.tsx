@@ -1,11 +1,19 @@
 import { useState, useEffect } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { motion, AnimatePresence } from "framer-motion";
-import { Phone, Key, ArrowRight, Settings, ShieldCheck, Sun, Moon, HelpCircle, ExternalLink, X } from "lucide-react";
+import { Phone, Key, ArrowRight, Settings, ShieldCheck, Sun, Moon, HelpCircle, ExternalLink, X, QrCode, Lock } from "lucide-react";
 import { load } from '@tauri-apps/plugin-store';
 import { useTheme } from '../context/ThemeContext';
+import { errorMessage, isDriveError } from '../utils';
 
-type Step = "setup" | "phone" | "code" | "password";
+type Step = "unlock" | "setup" | "phone" | "code" | "password" | "qr";
+
+interface QrLoginToken {
+    url: string;
+    png: string;
+    expires: number;
+}
 
 function AuthThemeToggle() {
     const { theme, toggleTheme } = useTheme();
@@ -52,6 +60,10 @@ export function AuthWizard({ onLogin }: { onLogin: () => void }) {
 
     const [phone, setPhone] = useState("");
     const [code, setCode] = useState("");
+    const [password, setPassword] = useState("");
+    const [passphrase, setPassphrase] = useState("");
+    const [passwordHint, setPasswordHint] = useState<string | null>(null);
+    const [qrToken, setQrToken] = useState<QrLoginToken | null>(null);
     const [error, setError] = useState<string | null>(null);
     const [floodWait, setFloodWait] = useState<number | null>(null);
     const [showHelp, setShowHelp] = useState(false);
@@ -68,6 +80,14 @@ export function AuthWizard({ onLogin }: { onLogin: () => void }) {
         return () => clearInterval(interval);
     }, [floodWait]);
 
+    useEffect(() => {
+        invoke<{ enabled: boolean; unlocked: boolean }>("cmd_vault_status")
+            .then(status => {
+                if (status.enabled && !status.unlocked) setStep("unlock");
+            })
+            .catch(() => { });
+    }, []);
+
     useEffect(() => {
         const initStore = async () => {
             try {
@@ -86,6 +106,25 @@ export function AuthWizard({ onLogin }: { onLogin: () => void }) {
         initStore();
     }, []);
 
+    useEffect(() => {
+        if (step !== "qr") return;
+        const unlisten = Promise.all([
+            listen<QrLoginToken>("qr-login-token", (e) => setQrToken(e.payload)),
+            listen("qr-login-accepted", () => onLogin()),
+            listen<string | null>("qr-login-password-required", (e) => {
+                setPasswordHint(e.payload);
+                setStep("password");
+            }),
+            listen<string>("qr-login-failed", (e) => {
+                setError(e.payload);
+                setStep("phone");
+            }),
+        ]);
+        return () => {
+            unlisten.then(fns => fns.forEach(fn => fn()));
+        };
+    }, [step]);
+
     const saveCredentials = async () => {
         try {
             const store = await load('config.json');
@@ -123,18 +162,64 @@ export function AuthWizard({ onLogin }: { onLogin: () => void }) {
             });
             setStep("code");
         } catch (err: unknown) {
-            const msg = err instanceof Error ? err.message : JSON.stringify(err);
-            if (msg.includes("FLOOD_WAIT_")) {
-                const parts = msg.split("FLOOD_WAIT_");
-                if (parts[1]) {
-                    const seconds = parseInt(parts[1]);
-                    if (!isNaN(seconds)) {
-                        setFloodWait(seconds);
-                        return;
-                    }
-                }
+            if (isDriveError(err) && err.kind === "FloodWait" && err.seconds) {
+                setFloodWait(err.seconds);
+                return;
+            }
+            setError(errorMessage(err));
+        } finally {
+            setLoading(false);
+        }
+    };
+
+    const handleUnlockSubmit = async (e: React.FormEvent) => {
+        e.preventDefault();
+        setLoading(true);
+        setError(null);
+        try {
+            await invoke("cmd_vault_unlock", { passphrase });
+            setPassphrase("");
+            setStep("setup");
+        } catch (err: unknown) {
+            setError(errorMessage(err));
+        } finally {
+            setLoading(false);
+        }
+    };
+
+    const handleQrStart = async () => {
+        setError(null);
+        setQrToken(null);
+        const idInt = parseInt(apiId, 10);
+        if (isNaN(idInt)) {
+            setError("API ID must be a number");
+            return;
+        }
+        setStep("qr");
+        try {
+            await invoke("cmd_auth_qr_start", { apiId: idInt, apiHash });
+        } catch (err: unknown) {
+            setError(errorMessage(err));
+            setStep("phone");
+        }
+    };
+
+    const handleQrCancel = async () => {
+        await invoke("cmd_auth_qr_cancel").catch(() => { });
+        setStep("phone");
+    };
+
+    const handlePasswordSubmit = async (e: React.FormEvent) => {
+        e.preventDefault();
+        setLoading(true);
+        setError(null);
+        try {
+            const res = await invoke<{ success: boolean }>("cmd_auth_check_password", { password });
+            if (res.success) {
+                onLogin();
             }
-            setError(msg);
+        } catch (err: unknown) {
+            setError(errorMessage(err));
         } finally {
             setLoading(false);
         }
@@ -153,7 +238,7 @@ export function AuthWizard({ onLogin }: { onLogin: () => void }) {
                 setError("Unknown error");
             }
         } catch (err: unknown) {
-            setError(err instanceof Error ? err.message : String(err));
+            setError(errorMessage(err));
         } finally {
             setLoading(false);
         }
@@ -205,6 +290,42 @@ export function AuthWizard({ onLogin }: { onLogin: () => void }) {
                         <>
 
 
+                            {step === "unlock" && (
+                                <motion.form
+                                    key="unlock"
+                                    initial={{ x: 20, opacity: 0 }}
+                                    animate={{ x: 0, opacity: 1 }}
+                                    exit={{ x: -20, opacity: 0 }}
+                                    onSubmit={handleUnlockSubmit}
+                                    className="space-y-6"
+                                >
+                                    <div className="space-y-2">
+                                        <label className="block text-xs font-semibold text-gray-400 uppercase tracking-wider">Session Passphrase</label>
+                                        <div className="relative">
+                                            <Lock className="absolute left-4 top-1/2 -translate-y-1/2 w-5 h-5 auth-form-icon" />
+                                            <input
+                                                type="password"
+                                                value={passphrase}
+                                                onChange={(e) => setPassphrase(e.target.value)}
+                                                placeholder="Passphrase"
+                                                autoFocus
+                                                className="w-full glass-input rounded-xl pl-12 pr-4 py-4 text-white placeholder-gray-600 focus:outline-none focus:border-blue-500 transition-all"
+                                            />
+                                        </div>
+                                        <p className="text-xs text-gray-500">Your Telegram session is encrypted on this device.</p>
+                                    </div>
+
+                                    <button
+                                        type="submit"
+                                        disabled={loading || !passphrase}
+                                        className="w-full bg-white text-black hover:bg-gray-100 font-bold py-4 rounded-xl flex items-center justify-center gap-2 transition-all shadow-lg active:scale-[0.98] disabled:opacity-50 disabled:cursor-not-allowed"
+                                    >
+                                        {loading ? "Unlocking..." : "Unlock"}
+                                    </button>
+                                </motion.form>
+                            )}
+
+
                             {step === "setup" && (
                                 <motion.form
                                     key="setup"
@@ -301,6 +422,13 @@ export function AuthWizard({ onLogin }: { onLogin: () => void }) {
                                         >
                                             {loading ? "Connecting..." : <>Continue <ArrowRight className="w-5 h-5" /></>}
                                         </button>
+                                        <button
+                                            type="button"
+                                            onClick={handleQrStart}
+                                            className="w-full glass-input text-white hover:border-blue-500 font-semibold py-3.5 rounded-xl flex items-center justify-center gap-2 transition-all"
+                                        >
+                                            <QrCode className="w-5 h-5" /> Log in with QR Code
+                                        </button>
                                         <button type="button" onClick={() => setStep("setup")} className="text-xs text-gray-500 hover:text-white transition-colors py-2">
                                             Back to Configuration
                                         </button>
@@ -346,6 +474,65 @@ export function AuthWizard({ onLogin }: { onLogin: () => void }) {
                                     </div>
                                 </motion.form>
                             )}
+
+
+                            {step === "qr" && (
+                                <motion.div
+                                    key="qr"
+                                    initial={{ x: 20, opacity: 0 }}
+                                    animate={{ x: 0, opacity: 1 }}
+                                    exit={{ x: -20, opacity: 0 }}
+                                    className="space-y-6 text-center"
+                                >
+                                    <div className="w-56 h-56 mx-auto rounded-2xl bg-white p-2 flex items-center justify-center">
+                                        {qrToken ? (
+                                            <img src={qrToken.png} alt="Login QR code" className="w-full h-full" />
+                                        ) : (
+                                            <div className="w-8 h-8 border-2 border-blue-500 border-t-transparent rounded-full animate-spin" />
+                                        )}
+                                    </div>
+                                    <p className="text-sm text-gray-400 leading-relaxed">
+                                        Open Telegram on your phone, go to <strong>Settings → Devices → Link Desktop Device</strong> and scan this code.
+                                    </p>
+                                    <button type="button" onClick={handleQrCancel} className="text-xs text-gray-500 hover:text-white transition-colors py-2">
+                                        Use Phone Number Instead
+                                    </button>
+                                </motion.div>
+                            )}
+
+
+                            {step === "password" && (
+                                <motion.form
+                                    key="password"
+                                    initial={{ x: 20, opacity: 0 }}
+                                    animate={{ x: 0, opacity: 1 }}
+                                    exit={{ x: -20, opacity: 0 }}
+                                    onSubmit={handlePasswordSubmit}
+                                    className="space-y-6"
+                                >
+                                    <div className="space-y-2">
+                                        <label className="block text-xs font-semibold text-gray-400 uppercase tracking-wider">Two-Step Verification</label>
+                                        <div className="relative">
+                                            <Lock className="absolute left-4 top-1/2 -translate-y-1/2 w-5 h-5 auth-form-icon" />
+                                            <input
+                                                type="password"
+                                                value={password}
+                                                onChange={(e) => setPassword(e.target.value)}
+                                                placeholder={passwordHint ? `Hint: ${passwordHint}` : "Password"}
+                                                className="w-full glass-input rounded-xl pl-12 pr-4 py-4 text-white placeholder-gray-600 focus:outline-none focus:border-blue-500 transition-all"
+                                            />
+                                        </div>
+                                    </div>
+
+                                    <button
+                                        type="submit"
+                                        disabled={loading}
+                                        className="w-full bg-white text-black hover:bg-gray-100 font-bold py-4 rounded-xl flex items-center justify-center gap-2 transition-all shadow-lg active:scale-[0.98]"
+                                    >
+                                        {loading ? "Verifying..." : "Unlock"}
+                                    </button>
+                                </motion.form>
+                            )}
                         </>
                     )}
                 </AnimatePresence>
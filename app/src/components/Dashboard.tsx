@@ -5,7 +5,7 @@ import { invoke } from '@tauri-apps/api/core';
 import { toast } from 'sonner';
 
 import { TelegramFile, BandwidthStats } from '../types';
-import { formatBytes } from '../utils';
+import { toTelegramFile } from '../utils';
 
 // Components
 import { Sidebar } from './dashboard/Sidebar';
@@ -25,6 +25,9 @@ import { useFileOperations } from '../hooks/useFileOperations';
 import { useFileUpload } from '../hooks/useFileUpload';
 import { useFileDownload } from '../hooks/useFileDownload';
 import { useKeyboardShortcuts } from '../hooks/useKeyboardShortcuts';
+import { useRetryNotice } from '../hooks/useRetryNotice';
+import { useFileChanges } from '../hooks/useFileChanges';
+import { useWatchActivity } from '../hooks/useWatchActivity';
 
 export function Dashboard({ onLogout }: { onLogout: () => void }) {
     const queryClient = useQueryClient();
@@ -69,11 +72,7 @@ export function Dashboard({ onLogout }: { onLogout: () => void }) {
 
     const { data: allFiles = [], isLoading, error } = useQuery({
         queryKey: ['files', activeFolderId],
-        queryFn: () => invoke<any[]>('cmd_get_files', { folderId: activeFolderId }).then(res => res.map(f => ({
-            ...f,
-            sizeStr: formatBytes(f.size),
-            type: f.icon_type || (f.name.endsWith('/') ? 'folder' : 'file')
-        }))),
+        queryFn: () => invoke<any[]>('cmd_get_files', { folderId: activeFolderId }).then(res => res.map(toTelegramFile)),
         enabled: !!store,
     });
 
@@ -90,13 +89,22 @@ export function Dashboard({ onLogout }: { onLogout: () => void }) {
 
 
     const {
-        handleDelete, handleBulkDelete, handleDownload, handleBulkDownload,
+        handleDelete, handleBulkDelete, handleDownload, handleBulkDownload, handleDownloadZip,
         handleBulkMove, handleDownloadFolder, handleGlobalSearch
 
     } = useFileOperations(activeFolderId, selectedIds, setSelectedIds, displayedFiles);
 
-    const { uploadQueue, setUploadQueue, handleManualUpload, isDragging } = useFileUpload(activeFolderId, store);
+    const { uploadQueue, setUploadQueue, handleManualUpload, handleFolderUpload, isDragging } = useFileUpload(activeFolderId, store);
+    const handleUploadFolder = async () => {
+        const plan = await handleFolderUpload();
+        if (plan && plan.created_folders.length > 0) {
+            await handleSyncFolders();
+        }
+    };
     const { downloadQueue, clearFinished: clearDownloads } = useFileDownload(store);
+    useRetryNotice();
+    useFileChanges();
+    useWatchActivity();
 
 
     const handleSelectAll = useCallback(() => {
@@ -298,8 +306,10 @@ export function Dashboard({ onLogout }: { onLogout: () => void }) {
                     selectedIds={selectedIds}
                     onShowMoveModal={() => setShowMoveModal(true)}
                     onBulkDownload={handleBulkDownload}
+                    onDownloadZip={handleDownloadZip}
                     onBulkDelete={handleBulkDelete}
                     onDownloadFolder={handleDownloadFolder}
+                    onUploadFolder={handleUploadFolder}
                     viewMode={viewMode}
                     setViewMode={setViewMode}
                     searchTerm={searchTerm}
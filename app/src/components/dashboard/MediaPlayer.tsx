@@ -1,5 +1,8 @@
+import { useState, useEffect, useCallback, useRef } from 'react';
 import { X } from 'lucide-react';
+import { invoke } from '@tauri-apps/api/core';
 import { TelegramFile } from '../../types';
+import { errorMessage } from '../../utils';
 
 interface MediaPlayerProps {
     file: TelegramFile;
@@ -8,8 +11,46 @@ interface MediaPlayerProps {
 }
 
 export function MediaPlayer({ file, onClose, activeFolderId }: MediaPlayerProps) {
-    const folderIdParam = activeFolderId !== null ? activeFolderId.toString() : 'home';
-    const streamUrl = `http://localhost:14200/stream/${folderIdParam}/${file.id}`;
+    const [streamUrl, setStreamUrl] = useState<string | null>(null);
+    const [error, setError] = useState<string | null>(null);
+    // Where to continue after the URL was renewed, and whether that already happened since playback last ran
+    const resumeAt = useRef<{ time: number; paused: boolean } | null>(null);
+    const renewed = useRef(false);
+
+    const loadUrl = useCallback(() => {
+        invoke<string>('cmd_get_stream_url', { messageId: file.id, folderId: activeFolderId })
+            .then(setStreamUrl)
+            .catch(e => setError(errorMessage(e)));
+    }, [file, activeFolderId]);
+
+    useEffect(() => {
+        setStreamUrl(null);
+        setError(null);
+        resumeAt.current = null;
+        renewed.current = false;
+        loadUrl();
+    }, [loadUrl]);
+
+    // Signed URLs expire, and the player sends a new request on every seek or stall,
+    // so a failed request gets a fresh URL once and playback continues where it stopped
+    const handleError = (e: React.SyntheticEvent<HTMLMediaElement>) => {
+        if (renewed.current) return;
+        renewed.current = true;
+        resumeAt.current = { time: e.currentTarget.currentTime, paused: e.currentTarget.paused };
+        loadUrl();
+    };
+
+    const handleLoadedMetadata = (e: React.SyntheticEvent<HTMLMediaElement>) => {
+        const resume = resumeAt.current;
+        if (!resume) return;
+        resumeAt.current = null;
+        e.currentTarget.currentTime = resume.time;
+        if (resume.paused) e.currentTarget.pause();
+    };
+
+    const handlePlaying = () => {
+        renewed.current = false;
+    };
 
     const isVideo = ['mp4', 'webm', 'ogg', 'mov', 'mkv', 'avi'].some(ext => file.name.toLowerCase().endsWith(ext));
     const isAudio = ['mp3', 'wav', 'aac', 'flac', 'm4a', 'opus'].some(ext => file.name.toLowerCase().endsWith(ext));
@@ -25,11 +66,18 @@ export function MediaPlayer({ file, onClose, activeFolderId }: MediaPlayerProps)
                 </button>
 
                 <div className="w-full aspect-video bg-black rounded-xl overflow-hidden shadow-2xl ring-1 ring-white/10 flex items-center justify-center">
-                    {isVideo ? (
+                    {error ? (
+                        <div className="text-red-400">{error}</div>
+                    ) : !streamUrl ? (
+                        <div className="w-10 h-10 border-4 border-telegram-primary border-t-transparent rounded-full animate-spin"></div>
+                    ) : isVideo ? (
                         <video
                             src={streamUrl}
                             controls
                             autoPlay
+                            onError={handleError}
+                            onLoadedMetadata={handleLoadedMetadata}
+                            onPlaying={handlePlaying}
                             className="w-full h-full object-contain"
                         />
                     ) : isAudio ? (
@@ -37,7 +85,15 @@ export function MediaPlayer({ file, onClose, activeFolderId }: MediaPlayerProps)
                             <div className="w-32 h-32 rounded-full bg-telegram-surface flex items-center justify-center mb-8 shadow-xl animate-pulse-slow">
                                 <svg xmlns="http://www.w3.org/2000/svg" className="w-12 h-12 text-telegram-primary" viewBox="0 0 24 24" fill="none" stroke="currentColor" strokeWidth="2" strokeLinecap="round" strokeLinejoin="round"><path d="M9 18V5l12-2v13" /><circle cx="6" cy="18" r="3" /><circle cx="18" cy="16" r="3" /></svg>
                             </div>
-                            <audio src={streamUrl} controls autoPlay className="w-full max-w-md" />
+                            <audio
+                                src={streamUrl}
+                                controls
+                                autoPlay
+                                onError={handleError}
+                                onLoadedMetadata={handleLoadedMetadata}
+                                onPlaying={handlePlaying}
+                                className="w-full max-w-md"
+                            />
                         </div>
                     ) : (
                         <div className="text-white">Unsupported media type</div>
@@ -3,6 +3,7 @@ import { X, File } from 'lucide-react';
 import { invoke } from '@tauri-apps/api/core';
 import { convertFileSrc } from '@tauri-apps/api/core';
 import { TelegramFile } from '../../types';
+import { errorMessage } from '../../utils';
 
 interface PreviewModalProps {
     file: TelegramFile;
@@ -34,7 +35,7 @@ export function PreviewModal({ file, onClose, activeFolderId }: PreviewModalProp
                     setError("Preview not available");
                 }
             } catch (e) {
-                setError(String(e));
+                setError(errorMessage(e));
             } finally {
                 setLoading(false);
             }
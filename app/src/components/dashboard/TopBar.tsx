@@ -1,4 +1,4 @@
-import { HardDrive, LayoutGrid, Sun, Moon } from 'lucide-react';
+import { HardDrive, FolderUp, LayoutGrid, Sun, Moon } from 'lucide-react';
 import { useTheme } from '../../context/ThemeContext';
 
 interface TopBarProps {
@@ -6,8 +6,10 @@ interface TopBarProps {
     selectedIds: number[];
     onShowMoveModal: () => void;
     onBulkDownload: () => void;
+    onDownloadZip: () => void;
     onBulkDelete: () => void;
     onDownloadFolder: () => void;
+    onUploadFolder: () => void;
     viewMode: 'grid' | 'list';
     setViewMode: (mode: 'grid' | 'list') => void;
     searchTerm: string;
@@ -15,8 +17,8 @@ interface TopBarProps {
 }
 
 export function TopBar({
-    currentFolderName, selectedIds, onShowMoveModal, onBulkDownload, onBulkDelete,
-    onDownloadFolder, viewMode, setViewMode, searchTerm, onSearchChange
+    currentFolderName, selectedIds, onShowMoveModal, onBulkDownload, onDownloadZip, onBulkDelete,
+    onDownloadFolder, onUploadFolder, viewMode, setViewMode, searchTerm, onSearchChange
 }: TopBarProps) {
     const { theme, toggleTheme } = useTheme();
 
@@ -46,10 +48,15 @@ export function TopBar({
                         <span className="text-xs text-telegram-subtext mr-2">{selectedIds.length} Selected</span>
                         <button onClick={onShowMoveModal} className="px-3 py-1.5 bg-telegram-primary/20 hover:bg-telegram-primary/30 text-telegram-primary rounded-md text-xs transition font-medium">Move to...</button>
                         <button onClick={onBulkDownload} className="px-3 py-1.5 bg-telegram-hover hover:bg-telegram-border rounded-md text-xs text-telegram-text transition">Download Selected</button>
+                        <button onClick={onDownloadZip} className="px-3 py-1.5 bg-telegram-hover hover:bg-telegram-border rounded-md text-xs text-telegram-text transition">Download ZIP</button>
                         <button onClick={onBulkDelete} className="px-3 py-1.5 bg-red-500/10 hover:bg-red-500/20 text-red-400 rounded-md text-xs transition">Delete</button>
                     </div>
                 )}
 
+                <button onClick={onUploadFolder} className="p-2 hover:bg-telegram-hover rounded-md text-telegram-subtext hover:text-telegram-text transition group relative" title="Upload Folder">
+                    <FolderUp className="w-5 h-5" />
+                </button>
+
                 <button onClick={onDownloadFolder} className="p-2 hover:bg-telegram-hover rounded-md text-telegram-subtext hover:text-telegram-text transition group relative" title="Download Folder">
                     <HardDrive className="w-5 h-5" />
                 </button>
@@ -29,7 +29,11 @@ export function UploadQueue({ items, onClearFinished }: UploadQueueProps) {
                         </div>
                         {item.status === 'uploading' && (
                             <div className="w-full bg-telegram-border h-1 mt-1 rounded-full overflow-hidden">
-                                <div className="bg-blue-500 h-full w-full animate-progress-indeterminate"></div>
+                                {item.progress === undefined ? (
+                                    <div className="bg-blue-500 h-full w-full animate-progress-indeterminate"></div>
+                                ) : (
+                                    <div className="bg-blue-500 h-full transition-all" style={{ width: `${Math.round(item.progress * 100)}%` }}></div>
+                                )}
                             </div>
                         )}
                     </div>
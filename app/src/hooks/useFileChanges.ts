@@ -0,0 +1,40 @@
+import { useEffect } from 'react';
+import { listen } from '@tauri-apps/api/event';
+import { useQueryClient } from '@tanstack/react-query';
+import { FileChange, TelegramFile } from '../types';
+import { toTelegramFile } from '../utils';
+
+/**
+ * Applies folder changes made on other devices (`file-change` event) to the
+ * cached file lists, and reloads them all if the backend missed some.
+ */
+export function useFileChanges() {
+    const queryClient = useQueryClient();
+
+    useEffect(() => {
+        const unlistenChange = listen<FileChange>('file-change', ({ payload }) => {
+            const folderId = payload.kind === 'deleted' ? payload.folder_id : payload.file.folder_id;
+            queryClient.setQueryData<TelegramFile[]>(['files', folderId], (files) => {
+                // Lists that were never loaded are fetched in full when opened
+                if (!files) return files;
+                if (payload.kind === 'deleted') {
+                    return files.filter(f => !payload.message_ids.includes(f.id));
+                }
+                const file = toTelegramFile(payload.file);
+                const rest = files.filter(f => f.id !== file.id);
+                // Newest first, like cmd_get_files
+                return payload.kind === 'added' || rest.length === files.length
+                    ? [file, ...rest]
+                    : files.map(f => f.id === file.id ? file : f);
+            });
+        });
+        const unlistenMissed = listen('file-changes-missed', () => {
+            queryClient.invalidateQueries({ queryKey: ['files'] });
+        });
+
+        return () => {
+            unlistenChange.then(f => f());
+            unlistenMissed.then(f => f());
+        };
+    }, [queryClient]);
+}
@@ -1,8 +1,8 @@
 import { useState, useEffect } from 'react';
 import { invoke } from '@tauri-apps/api/core';
-import { save, open } from '@tauri-apps/plugin-dialog';
 import { toast } from 'sonner';
-import { DownloadItem, TelegramFile } from '../types';
+import { DownloadItem, SavedFile, TelegramFile } from '../types';
+import { errorMessage } from '../utils';
 import type { Store } from '@tauri-apps/plugin-store';
 
 export function useFileDownload(store: Store | null) {
@@ -47,24 +47,30 @@ export function useFileDownload(store: Store | null) {
         setDownloadQueue(q => q.map(i => i.id === item.id ? { ...i, status: 'downloading' } : i));
 
         try {
-            const savePath = await save({ defaultPath: item.filename });
-            if (!savePath) {
-                // User cancelled
-                setDownloadQueue(q => q.filter(i => i.id !== item.id));
-                setProcessing(false);
-                return;
+            let destination: { savePath: string } | { saveDir: string; collision: 'rename' };
+            if (item.saveDir) {
+                destination = { saveDir: item.saveDir, collision: 'rename' };
+            } else {
+                const savePath = await invoke<string | null>('cmd_pick_save_path', { defaultName: item.filename });
+                if (!savePath) {
+                    // User cancelled
+                    setDownloadQueue(q => q.filter(i => i.id !== item.id));
+                    setProcessing(false);
+                    return;
+                }
+                destination = { savePath };
             }
 
-            await invoke('cmd_download_file', {
+            const saved = await invoke<SavedFile>('cmd_download_file', {
                 messageId: item.messageId,
-                savePath,
-                folderId: item.folderId
+                folderId: item.folderId,
+                ...destination
             });
 
             setDownloadQueue(q => q.map(i => i.id === item.id ? { ...i, status: 'success' } : i));
-            toast.success(`Downloaded: ${item.filename}`);
+            toast.success(saved.skipped ? `Already exists: ${item.filename}` : `Downloaded: ${item.filename}`);
         } catch (e) {
-            setDownloadQueue(q => q.map(i => i.id === item.id ? { ...i, status: 'error', error: String(e) } : i));
+            setDownloadQueue(q => q.map(i => i.id === item.id ? { ...i, status: 'error', error: errorMessage(e) } : i));
             toast.error(`Download failed: ${item.filename}`);
         } finally {
             setProcessing(false);
@@ -83,11 +89,7 @@ export function useFileDownload(store: Store | null) {
     };
 
     const queueBulkDownload = async (files: TelegramFile[], folderId: number | null) => {
-        const dirPath = await open({
-            directory: true,
-            multiple: false,
-            title: "Select Download Destination"
-        });
+        const dirPath = await invoke<string | null>('cmd_pick_folder', { title: "Select Download Destination" });
         if (!dirPath) return;
 
         // For bulk, we don't prompt for each file - we use the directory
@@ -97,6 +99,7 @@ export function useFileDownload(store: Store | null) {
                 messageId: file.id,
                 filename: file.name,
                 folderId,
+                saveDir: dirPath,
                 status: 'pending'
             };
             setDownloadQueue(prev => [...prev, newItem]);
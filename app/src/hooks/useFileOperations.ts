@@ -1,8 +1,10 @@
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { useQueryClient } from '@tanstack/react-query';
 import { toast } from 'sonner';
 import { useConfirm } from '../context/ConfirmContext';
-import { TelegramFile } from '../types';
+import { TelegramFile, FolderDownloadProgress, FolderDownloadSummary, SavedFile } from '../types';
+import { errorMessage } from '../utils';
 
 export function useFileOperations(
     activeFolderId: number | null,
@@ -20,7 +22,7 @@ export function useFileOperations(
             queryClient.invalidateQueries({ queryKey: ['files', activeFolderId] });
             toast.success("File deleted");
         } catch (e) {
-            toast.error(`Delete failed: ${e}`);
+            toast.error(`Delete failed: ${errorMessage(e)}`);
         }
     }
 
@@ -46,40 +48,66 @@ export function useFileOperations(
 
     const handleDownload = async (id: number, name: string) => {
         try {
-            const savePath = await import('@tauri-apps/plugin-dialog').then(d => d.save({
-                defaultPath: name,
-            }));
+            const savePath = await invoke<string | null>('cmd_pick_save_path', { defaultName: name });
             if (!savePath) return;
             toast.info(`Download started: ${name}`);
             await invoke('cmd_download_file', { messageId: id, savePath, folderId: activeFolderId });
             toast.success(`Download complete: ${name}`);
         } catch (e) {
-            toast.error(`Download failed: ${e}`);
+            toast.error(`Download failed: ${errorMessage(e)}`);
         }
     }
 
     const handleBulkDownload = async () => {
         if (selectedIds.length === 0) return;
         try {
-            const dirPath = await import('@tauri-apps/plugin-dialog').then(d => d.open({
-                directory: true, multiple: false, title: "Select Download Destination"
-            }));
+            const dirPath = await invoke<string | null>('cmd_pick_folder', { title: "Select Download Destination" });
             if (!dirPath) return;
             let successCount = 0;
             const targetFiles = displayedFiles.filter((f) => selectedIds.includes(f.id));
             toast.info(`Starting batch download of ${targetFiles.length} files...`);
 
             for (const file of targetFiles) {
-                const filePath = `${dirPath}/${file.name}`;
                 try {
-                    await invoke('cmd_download_file', { messageId: file.id, savePath: filePath, folderId: activeFolderId });
-                    successCount++;
+                    // The backend picks a safe name and numbers duplicates instead of overwriting
+                    const saved = await invoke<SavedFile>('cmd_download_file', {
+                        messageId: file.id,
+                        saveDir: dirPath,
+                        folderId: activeFolderId,
+                        collision: 'rename'
+                    });
+                    if (!saved.skipped) successCount++;
                 } catch (e) { }
             }
             toast.success(`Downloaded ${successCount} files.`);
             setSelectedIds([]);
         } catch (e) {
-            toast.error(`Bulk download failed: ${e}`);
+            toast.error(`Bulk download failed: ${errorMessage(e)}`);
+        }
+    }
+
+    const handleDownloadZip = async () => {
+        if (selectedIds.length === 0) return;
+        try {
+            const savePath = await invoke<string | null>('cmd_pick_save_path', {
+                defaultName: 'telegram-drive.zip',
+                extensions: ['zip'],
+                title: "Save Selection As ZIP"
+            });
+            if (!savePath) return;
+            const toastId = toast.loading(`Zipping ${selectedIds.length} files...`);
+            try {
+                await invoke('cmd_download_zip', {
+                    selection: [{ folder_id: activeFolderId, message_ids: selectedIds }],
+                    savePath,
+                });
+                toast.success(`Saved ${selectedIds.length} files as ZIP.`, { id: toastId });
+                setSelectedIds([]);
+            } catch (e) {
+                toast.error(`ZIP download failed: ${errorMessage(e)}`, { id: toastId });
+            }
+        } catch (e) {
+            toast.error(`ZIP download failed: ${errorMessage(e)}`);
         }
     }
 
@@ -105,23 +133,35 @@ export function useFileOperations(
             toast.info("Folder is empty.");
             return;
         }
+        let dirPath: string | null;
         try {
-            const dirPath = await import('@tauri-apps/plugin-dialog').then(d => d.open({
-                directory: true, multiple: false, title: "Download Folder To..."
-            }));
-            if (!dirPath) return;
-            let successCount = 0;
-            toast.info(`Downloading folder contents (${displayedFiles.length} files)...`);
-            for (const file of displayedFiles) {
-                const filePath = `${dirPath}/${file.name}`;
-                try {
-                    await invoke('cmd_download_file', { messageId: file.id, savePath: filePath, folderId: activeFolderId });
-                    successCount++;
-                } catch (e) { }
+            dirPath = await invoke<string | null>('cmd_pick_folder', { title: "Download Folder To..." });
+        } catch {
+            toast.error("Failed to open folder dialog");
+            return;
+        }
+        if (!dirPath) return;
+
+        const toastId = toast.loading("Downloading folder...");
+        const unlisten = listen<FolderDownloadProgress>('folder-download-progress', ({ payload }) => {
+            const percent = payload.bytes_total > 0 ? ` (${Math.round(payload.bytes_done / payload.bytes_total * 100)}%)` : '';
+            toast.loading(`Downloading folder${percent}...`, {
+                id: toastId,
+                description: `${payload.files_done} of ${payload.files_total} files`,
+            });
+        });
+        try {
+            const summary = await invoke<FolderDownloadSummary>('cmd_download_folder', { folderId: activeFolderId, dest: dirPath });
+            const description = `${summary.skipped.length} already downloaded, ${summary.failed.length} failed`;
+            if (summary.failed.length > 0) {
+                toast.warning(`Folder Download Complete: ${summary.downloaded.length} files.`, { id: toastId, description });
+            } else {
+                toast.success(`Folder Download Complete: ${summary.downloaded.length} files.`, { id: toastId, description });
             }
-            toast.success(`Folder Download Complete: ${successCount} files.`);
         } catch (e) {
-            toast.error("Error: " + e);
+            toast.error(`Folder download failed: ${errorMessage(e)}`, { id: toastId });
+        } finally {
+            unlisten.then(f => f());
         }
     }
 
@@ -130,6 +170,7 @@ export function useFileOperations(
         handleBulkDelete,
         handleDownload,
         handleBulkDownload,
+        handleDownloadZip,
         handleBulkMove,
         handleDownloadFolder,
         handleGlobalSearch: async (query: string) => {
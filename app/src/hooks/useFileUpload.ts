@@ -1,9 +1,10 @@
 import { useState, useEffect } from 'react';
 import { invoke } from '@tauri-apps/api/core';
-import { open } from '@tauri-apps/plugin-dialog';
+import { listen } from '@tauri-apps/api/event';
 import { useQueryClient } from '@tanstack/react-query';
 import { toast } from 'sonner';
-import { QueueItem } from '../types';
+import { QueueItem, UploadProgress, DirectoryUploadPlan } from '../types';
+import { errorMessage } from '../utils';
 import { useFileDrop } from './useFileDrop';
 import type { Store } from '@tauri-apps/plugin-store';
 
@@ -36,6 +37,19 @@ export function useFileUpload(activeFolderId: number | null, store: Store | null
     }, [store, uploadQueue, initialized]);
 
 
+    useEffect(() => {
+        const unlisten = listen<UploadProgress>('upload-progress', ({ payload }) => {
+            const progress = payload.size > 0 ? payload.bytes_sent / payload.size : 0;
+            setUploadQueue(q => q.map(i =>
+                i.status === 'uploading' && i.path === payload.path ? { ...i, progress } : i
+            ));
+        });
+        return () => {
+            unlisten.then(f => f());
+        };
+    }, []);
+
+
     useEffect(() => {
         if (processing) return;
         const nextItem = uploadQueue.find(i => i.status === 'pending');
@@ -52,8 +66,8 @@ export function useFileUpload(activeFolderId: number | null, store: Store | null
             setUploadQueue(q => q.map(i => i.id === item.id ? { ...i, status: 'success' } : i));
             queryClient.invalidateQueries({ queryKey: ['files', item.folderId] });
         } catch (e) {
-            setUploadQueue(q => q.map(i => i.id === item.id ? { ...i, status: 'error', error: String(e) } : i));
-            toast.error(`Upload failed for ${item.path.split('/').pop()}: ${e}`);
+            setUploadQueue(q => q.map(i => i.id === item.id ? { ...i, status: 'error', error: errorMessage(e) } : i));
+            toast.error(`Upload failed for ${item.path.split('/').pop()}: ${errorMessage(e)}`);
         } finally {
             setProcessing(false);
         }
@@ -62,9 +76,8 @@ export function useFileUpload(activeFolderId: number | null, store: Store | null
     // Opens system file dialog for upload
     const handleManualUpload = async () => {
         try {
-            const selected = await open({ multiple: true, directory: false });
-            if (selected) {
-                const paths = Array.isArray(selected) ? selected : [selected];
+            const paths = await invoke<string[] | null>('cmd_pick_files');
+            if (paths && paths.length > 0) {
                 const newItems: QueueItem[] = paths.map((path: string) => ({
                     id: Math.random().toString(36).substr(2, 9),
                     path,
@@ -79,12 +92,49 @@ export function useFileUpload(activeFolderId: number | null, store: Store | null
         }
     };
 
+    // Queues a whole directory tree, one drive folder per directory.
+    // Returns the plan so the caller can pick up newly created folders.
+    const handleFolderUpload = async (): Promise<DirectoryUploadPlan | null> => {
+        let selected: string | null;
+        try {
+            selected = await invoke<string | null>('cmd_pick_folder', { title: "Upload Folder" });
+        } catch {
+            toast.error("Failed to open folder dialog");
+            return null;
+        }
+        if (!selected) return null;
+
+        const name = selected.split(/[\\/]/).pop();
+        const toastId = toast.loading(`Preparing ${name}...`);
+        try {
+            const plan = await invoke<DirectoryUploadPlan>('cmd_upload_directory', { path: selected });
+            const newItems: QueueItem[] = plan.files.map(file => ({
+                id: Math.random().toString(36).substr(2, 9),
+                path: file.path,
+                folderId: file.folder_id,
+                status: 'pending'
+            }));
+            setUploadQueue(prev => [...prev, ...newItems]);
+            const description = `${plan.skipped.length} skipped, ${plan.failed.length} failed`;
+            if (plan.failed.length > 0) {
+                toast.warning(`Queued ${newItems.length} files from ${name}`, { id: toastId, description });
+            } else {
+                toast.info(`Queued ${newItems.length} files from ${name}`, { id: toastId, description });
+            }
+            return plan;
+        } catch (e) {
+            toast.error(`Folder upload failed: ${errorMessage(e)}`, { id: toastId });
+            return null;
+        }
+    };
+
     const { isDragging } = useFileDrop();
 
     return {
         uploadQueue,
         setUploadQueue,
         handleManualUpload,
+        handleFolderUpload,
         isDragging
     };
 }
@@ -0,0 +1,48 @@
+import { useEffect } from 'react';
+import { listen } from '@tauri-apps/api/event';
+import { toast } from 'sonner';
+
+interface RetryNotice {
+    operation: string;
+    seconds: number;
+    attempt: number;
+    reason: 'flood_wait' | 'server_error';
+}
+
+/**
+ * Shows a countdown toast while the backend waits out a flood wait or a
+ * Telegram server error before retrying (`telegram-retry` event).
+ */
+export function useRetryNotice() {
+    useEffect(() => {
+        let timer: ReturnType<typeof setInterval> | undefined;
+
+        const unlisten = listen<RetryNotice>('telegram-retry', ({ payload }) => {
+            clearInterval(timer);
+            const label = payload.reason === 'flood_wait'
+                ? 'Telegram is rate limiting requests'
+                : 'Telegram server error';
+            let remaining = payload.seconds;
+
+            const show = () => toast.loading(`${label}, retrying in ${remaining}s`, {
+                id: 'telegram-retry',
+                description: `Attempt ${payload.attempt + 1}`,
+            });
+            show();
+            timer = setInterval(() => {
+                remaining -= 1;
+                if (remaining > 0) {
+                    show();
+                } else {
+                    clearInterval(timer);
+                    toast.dismiss('telegram-retry');
+                }
+            }, 1000);
+        });
+
+        return () => {
+            clearInterval(timer);
+            unlisten.then(f => f());
+        };
+    }, []);
+}
@@ -5,6 +5,7 @@ import { useQueryClient } from '@tanstack/react-query';
 import { toast } from 'sonner';
 import { useConfirm } from '../context/ConfirmContext';
 import { TelegramFolder } from '../types';
+import { errorMessage, isDriveError } from '../utils';
 import { useNetworkStatus } from './useNetworkStatus';
 
 export function useTelegramConnection(onLogoutParent: () => void) {
@@ -154,7 +155,7 @@ export function useTelegramConnection(onLogoutParent: () => void) {
             await store.save();
             toast.success(`Folder "${name}" created.`);
         } catch (e) {
-            toast.error("Failed to create folder: " + e);
+            toast.error("Failed to create folder: " + errorMessage(e));
             throw e;
         }
     };
@@ -178,8 +179,7 @@ export function useTelegramConnection(onLogoutParent: () => void) {
             if (activeFolderId === folderId) setActiveFolderId(null);
             toast.success(`Folder "${folderName}" deleted.`);
         } catch (e: unknown) {
-            const errStr = String(e);
-            if (errStr.includes("not found")) {
+            if (isDriveError(e) && (e.kind === 'NotFound' || e.kind === 'ChannelUnavailable')) {
                 if (await confirm({
                     title: "Folder Not Found",
                     message: `Folder "${folderName}" not found on Telegram (it may have been deleted externally).\nRemove from this app?`,
@@ -195,7 +195,7 @@ export function useTelegramConnection(onLogoutParent: () => void) {
                     if (activeFolderId === folderId) setActiveFolderId(null);
                 }
             } else {
-                toast.error(`Failed to delete folder: ${e}`);
+                toast.error(`Failed to delete folder: ${errorMessage(e)}`);
             }
         }
     };
@@ -0,0 +1,21 @@
+import { useEffect } from 'react';
+import { listen } from '@tauri-apps/api/event';
+import { toast } from 'sonner';
+import { WatchActivity } from '../types';
+
+/** Reports files the backend uploaded from watched folders (`watch-activity` event) */
+export function useWatchActivity() {
+    useEffect(() => {
+        const unlisten = listen<WatchActivity>('watch-activity', ({ payload }) => {
+            const name = payload.path.split(/[\\/]/).pop();
+            if (payload.error) {
+                toast.error(`Auto-upload failed: ${name}`, { description: payload.error });
+            } else {
+                toast.success(`Auto-uploaded: ${name}`);
+            }
+        });
+        return () => {
+            unlisten.then(f => f());
+        };
+    }, []);
+}
@@ -20,6 +20,71 @@ export interface QueueItem {
     folderId: number | null;
     status: 'pending' | 'uploading' | 'success' | 'error';
     error?: string;
+    progress?: number; // 0..1 while uploading
+}
+
+export interface UploadProgress {
+    path: string;
+    part: number;
+    total_parts: number;
+    bytes_sent: number;
+    size: number;
+}
+
+export interface TransferIssue {
+    path: string;
+    reason: string;
+}
+
+export interface PlannedUpload {
+    path: string;
+    folder_id: number;
+    size: number;
+}
+
+export interface DirectoryUploadPlan {
+    files: PlannedUpload[];
+    skipped: TransferIssue[];
+    failed: TransferIssue[];
+    created_folders: TelegramFolder[];
+}
+
+/** `FileMetadata` as the backend sends it */
+export interface FileMetadata {
+    id: number;
+    folder_id: number | null;
+    name: string;
+    size: number;
+    mime_type: string | null;
+    file_ext: string | null;
+    created_at: string;
+    icon_type: string;
+}
+
+/** Payload of the `file-change` event */
+export type FileChange =
+    | { kind: 'added' | 'updated'; file: FileMetadata }
+    | { kind: 'deleted'; folder_id: number | null; message_ids: number[] };
+
+/** Payload of the `watch-activity` event */
+export interface WatchActivity {
+    rule_id: number;
+    path: string;
+    error: string | null;
+}
+
+export interface FolderDownloadSummary {
+    downloaded: string[];
+    skipped: TransferIssue[];
+    failed: TransferIssue[];
+}
+
+export interface FolderDownloadProgress {
+    path: string;
+    files_done: number;
+    files_total: number;
+    bytes_done: number;
+    bytes_total: number;
 }
 
 export interface BandwidthStats {
@@ -32,6 +97,15 @@ export interface DownloadItem {
     messageId: number;
     filename: string;
     folderId: number | null;
+    /** Set for bulk downloads, which save under the file's own name without asking */
+    saveDir?: string;
     status: 'pending' | 'downloading' | 'success' | 'error';
     error?: string;
 }
+
+export type CollisionStrategy = 'overwrite' | 'skip' | 'rename';
+
+export interface SavedFile {
+    path: string;
+    skipped: boolean;
+}
@@ -1,3 +1,5 @@
+import { TelegramFile } from './types';
+
 export function formatBytes(bytes: number, decimals = 2) {
     if (!+bytes) return '0 Bytes';
     const k = 1024;
@@ -6,3 +8,30 @@ export function formatBytes(bytes: number, decimals = 2) {
     const i = Math.floor(Math.log(bytes) / Math.log(k));
     return `${parseFloat((bytes / Math.pow(k, i)).toFixed(dm))} ${sizes[i]}`;
 }
+
+/** Adds the display fields the file views use */
+export function toTelegramFile(f: any): TelegramFile {
+    return {
+        ...f,
+        sizeStr: formatBytes(f.size),
+        type: f.icon_type || (f.name.endsWith('/') ? 'folder' : 'file')
+    };
+}
+
+/** Error returned by the backend commands, see `DriveError` in src-tauri/src/error.rs */
+export interface DriveError {
+    kind: 'NotConnected' | 'AuthRequired' | 'FloodWait' | 'NotFound' | 'ChannelUnavailable' | 'BandwidthExceeded' | 'FileTooLarge' | 'PathNotAllowed' | 'Io' | 'Telegram' | 'Other';
+    message: string;
+    seconds?: number;
+}
+
+export function isDriveError(e: unknown): e is DriveError {
+    return typeof e === 'object' && e !== null && 'kind' in e && 'message' in e;
+}
+
+/** Readable message for anything a command can reject with (DriveError, plain string or Error) */
+export function errorMessage(e: unknown): string {
+    if (isDriveError(e)) return e.message;
+    if (e instanceof Error) return e.message;
+    return String(e);
+}